@@ -0,0 +1,283 @@
+//dataflow checks over function bodies, run once a function has been parsed
+use crate::parserlib::*;
+use std::collections::{HashMap, HashSet, LinkedList};
+
+/*
+ * Definite assignment state, None once the path has left the function or the loop
+ * through return/break/continue, such a path never reaches a merge point
+ */
+type Assigned = Option<HashSet<String>>;
+
+struct DefiniteAssignment<'a> {
+    fname: &'a String,
+    //scalar and struct locals, arrays are not tracked element wise
+    tracked: HashSet<String>,
+    warned: HashSet<String>,
+}
+/*
+ * Warn about reads of locals and struct fields that may not be assigned on some path,
+ * parameters are assigned by the caller, read() and &x count as assignments
+ */
+pub fn check_definite_assignment(fname: &String, body: &ASTNode, lst: &HashMap<String, LSymbol>) {
+    let mut da = DefiniteAssignment {
+        fname,
+        tracked: HashSet::default(),
+        warned: HashSet::default(),
+    };
+    for (name, entry) in lst.iter() {
+        let LSymbol::Var {
+            vartype: _,
+            varid,
+            varindices,
+        } = entry;
+        if *varid > 0 && varindices.is_empty() {
+            da.tracked.insert(name.clone());
+        }
+    }
+    let mut state: Assigned = Some(HashSet::default());
+    da.stmt(body, &mut state);
+}
+/*
+ * Paths reaching the merge point from both sides, an unreachable side adds nothing
+ */
+fn __merge(a: Assigned, b: Assigned) -> Assigned {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).cloned().collect()),
+        (Some(a), None) => Some(a),
+        (None, b) => b,
+    }
+}
+impl<'a> DefiniteAssignment<'a> {
+    fn assign(&self, key: String, state: &mut Assigned) {
+        if let Some(s) = state {
+            s.insert(key);
+        }
+    }
+    fn is_assigned(&self, name: &String, field: Option<&String>, state: &Assigned) -> bool {
+        let s = match state {
+            Some(s) => s,
+            None => return true,
+        };
+        if s.contains(name) {
+            return true;
+        }
+        match field {
+            Some(f) => s.contains(&(name.clone() + "." + f.as_str())),
+            //a whole struct read is fine once any of its fields is set
+            None => s.iter().any(|k| k.starts_with(&(name.clone() + "."))),
+        }
+    }
+    fn read(&mut self, name: &String, field: Option<&String>, state: &Assigned) {
+        if !self.tracked.contains(name) || self.warned.contains(name) {
+            return;
+        }
+        if !self.is_assigned(name, field, state) {
+            let var = match field {
+                Some(f) => name.clone() + "." + f.as_str(),
+                None => name.clone(),
+            };
+            log::warn!(
+                "Variable [{}] may be used before it is assigned in function [{}].",
+                var,
+                self.fname
+            );
+            self.warned.insert(name.clone());
+        }
+    }
+    /*
+     * Indices, method arguments and pointers walked through by a field chain are read
+     */
+    fn chain(&mut self, node: &ASTNode, state: &mut Assigned) {
+        match node {
+            ASTNode::VAR {
+                name: _,
+                array_access,
+                dot_field_access,
+                arrow_field_access,
+            } => {
+                for index in array_access {
+                    self.expr(index, state);
+                }
+                self.chain(dot_field_access, state);
+                self.chain(arrow_field_access, state);
+            }
+            ASTNode::FuncCallNode { fname: _, arglist } => self.args(arglist, state),
+            _ => {}
+        }
+    }
+    fn args(&mut self, arglist: &LinkedList<ASTNode>, state: &mut Assigned) {
+        for arg in arglist {
+            self.expr(arg, state);
+        }
+    }
+    fn __field(node: &ASTNode) -> Option<&String> {
+        match node {
+            ASTNode::VAR { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+    fn expr(&mut self, node: &ASTNode, state: &mut Assigned) {
+        match node {
+            ASTNode::VAR {
+                name,
+                array_access,
+                dot_field_access,
+                arrow_field_access,
+            } => {
+                for index in array_access {
+                    self.expr(index, state);
+                }
+                self.read(name, Self::__field(dot_field_access), state);
+                self.chain(dot_field_access, state);
+                self.chain(arrow_field_access, state);
+            }
+            ASTNode::BinaryNode {
+                op: _,
+                exprtype: _,
+                lhs,
+                rhs,
+            } => {
+                self.expr(lhs, state);
+                self.expr(rhs, state);
+            }
+            ASTNode::UnaryNode {
+                op: ASTNodeType::Ref,
+                exprtype: _,
+                ptr,
+                depth: _,
+            } => self.target(ptr, state),
+            ASTNode::UnaryNode {
+                op: _,
+                exprtype: _,
+                ptr,
+                depth: _,
+            } => self.expr(ptr, state),
+            ASTNode::FuncCallNode { fname: _, arglist }
+            | ASTNode::StdFuncCallNode { func: _, arglist } => self.args(arglist, state),
+            ASTNode::IndirectCallNode { fptr, arglist } => {
+                self.args(arglist, state);
+                self.expr(fptr, state);
+            }
+            ASTNode::TupleNode { elems } => {
+                for elem in elems {
+                    self.expr(elem, state);
+                }
+            }
+            _ => {}
+        }
+    }
+    /*
+     * A variable being written, x and s.f become assigned,
+     * writing through a pointer reads the pointer
+     */
+    fn target(&mut self, node: &ASTNode, state: &mut Assigned) {
+        match node {
+            ASTNode::VAR {
+                name,
+                array_access,
+                dot_field_access,
+                arrow_field_access,
+            } => {
+                for index in array_access {
+                    self.expr(index, state);
+                }
+                if **arrow_field_access != ASTNode::Void {
+                    if let Some(f) = Self::__field(dot_field_access) {
+                        self.read(name, Some(f), state);
+                    } else {
+                        self.read(name, None, state);
+                    }
+                    self.chain(dot_field_access, state);
+                    self.chain(arrow_field_access, state);
+                    return;
+                }
+                self.chain(dot_field_access, state);
+                match Self::__field(dot_field_access) {
+                    Some(f) => self.assign(name.clone() + "." + f.as_str(), state),
+                    None => self.assign(name.clone(), state),
+                }
+            }
+            //*p = e reads p
+            _ => self.expr(node, state),
+        }
+    }
+    fn stmt(&mut self, node: &ASTNode, state: &mut Assigned) {
+        match node {
+            ASTNode::BinaryNode {
+                op: ASTNodeType::Connector,
+                exprtype: _,
+                lhs,
+                rhs,
+            } => {
+                self.stmt(lhs, state);
+                self.stmt(rhs, state);
+            }
+            ASTNode::BinaryNode {
+                op: ASTNodeType::Equals,
+                exprtype: _,
+                lhs,
+                rhs,
+            } => {
+                self.expr(rhs, state);
+                self.target(lhs, state);
+            }
+            ASTNode::UnaryNode {
+                op: ASTNodeType::Read | ASTNodeType::Alloc,
+                exprtype: _,
+                ptr,
+                depth: _,
+            } => self.target(ptr, state),
+            ASTNode::TupleAssgNode { vars, call } => {
+                self.expr(call, state);
+                for var in vars {
+                    self.target(var, state);
+                }
+            }
+            ASTNode::IfNode { expr, xif } => {
+                self.expr(expr, state);
+                let mut s = state.clone();
+                self.stmt(xif, &mut s);
+            }
+            ASTNode::IfElseNode { expr, xif, xelse } => {
+                self.expr(expr, state);
+                let mut a = state.clone();
+                let mut b = state.clone();
+                self.stmt(xif, &mut a);
+                self.stmt(xelse, &mut b);
+                *state = __merge(a, b);
+            }
+            //the body may not run at all, nothing it assigns survives the loop
+            ASTNode::WhileNode { expr, xdo } => {
+                self.expr(expr, state);
+                let mut s = state.clone();
+                self.stmt(xdo, &mut s);
+            }
+            ASTNode::SwitchNode {
+                expr,
+                cases,
+                default,
+            } => {
+                self.expr(expr, state);
+                let mut merged: Assigned = None;
+                for (_, body) in cases {
+                    let mut s = state.clone();
+                    self.stmt(body, &mut s);
+                    merged = __merge(merged, s);
+                }
+                let mut s = state.clone();
+                self.stmt(default, &mut s);
+                if **default == ASTNode::Void {
+                    s = state.clone();
+                }
+                *state = __merge(merged, s);
+            }
+            ASTNode::ReturnNode { expr } => {
+                self.expr(expr, state);
+                *state = None;
+            }
+            ASTNode::BreakNode | ASTNode::ContinueNode => *state = None,
+            ASTNode::StmtNode { pos: _, stmt } => self.stmt(stmt, state),
+            _ => self.expr(node, state),
+        }
+    }
+}
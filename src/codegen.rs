@@ -1,34 +1,37 @@
-//register use table
+//lowering of the IR to XSM
+use crate::ir::*;
+use crate::irgen::build_program;
+use crate::options::*;
 use crate::parserlib::*;
-use crate::validation::*;
+use crate::passes::*;
+use crate::regalloc::*;
 
 use lazy_static::lazy_static; // 1.4.0
-use std::cmp::max;
-use std::cmp::min;
-use std::collections::LinkedList;
+use std::collections::{HashMap, LinkedList};
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::sync::Mutex;
 
 //global mutable arrays must be guarded with a mutex :(
-//TODO Assignment statement can be optimized
-//recursive call
-const MAX_REGISTERS: usize = 21;
-const CONN_RETURN: usize = 25;
+//registers after the allocatable ones, free for the lowering
+const SCRATCH: usize = 17;
+const SCRATCH2: usize = 18;
+//address of spill slots
+const SCRATCH_ADDR: usize = 19;
 pub const XSM_STACK_OFFSET: i64 = 4096;
 pub const LABEL_NOT_FOUND: usize = 10000;
+//heap used by the builtin allocator, same region as the library heap
+pub const HEAP_START: i64 = 1024;
+const HEAP_END: i64 = 2048;
 
 //Global variables used
 lazy_static! {
-    pub static ref REGISTERS: Mutex<Vec<(bool, i64)>> = Mutex::new(vec![(false, 0); MAX_REGISTERS]);
     //Label assigner
     pub static ref LABEL_COUNT: Mutex<usize> = Mutex::new(0);
     //for continue and break statements
     pub static ref WHILE_TRACKER: Mutex<Vec<usize>> = Mutex::new(Vec::default());
-    //Need a stack to call F(F(F(5))) type calls
-    pub static ref REGISTER_STACK: Mutex<Vec<Vec<(bool, i64)>>> = Mutex::new(Vec::default());
-    //TODO remove this stack
-    pub static ref FSTACK: Mutex<(String,i64)> = Mutex::new((String::default(),0));
+    //labels of the alloc and free routines of the builtin allocator
+    pub static ref ALLOCATOR_LABELS: Mutex<(usize,usize)> = Mutex::new((LABEL_NOT_FOUND,LABEL_NOT_FOUND));
 }
 //Gets the label of a function
 pub fn get_function_label(fname: &String, classname: &String) -> usize {
@@ -68,1015 +71,337 @@ pub fn get_function_label(fname: &String, classname: &String) -> usize {
         }
     }
 }
-
-/*
- * Internally, functions are have different key value
- */
-fn __get_table_id(fname: &String) -> String {
-    let cname = CLASSNAME.lock().unwrap();
-    fname.clone() + "#" + cname.as_str()
-}
 //Wrap error and write to file
 fn write_line(mut writer: &File, args: std::fmt::Arguments) {
     if let Err(e) = writeln!(writer, "{}", args) {
         exit_on_err(e.to_string());
     }
 }
-/*
- * Get the size of the local declaration
- */
-fn __get_function_storage(fname: &String) -> i64 {
-    let ft = FUNCTION_TABLE.lock().unwrap();
-    let mut max_size = 0;
-    if let Some(entry) = ft.get(&__get_table_id(fname)) {
-        for (
-            _k,
-            LSymbol::Var {
-                vartype: _, varid, ..
-            },
-        ) in entry.iter()
-        {
-            max_size = max(varid.clone(), max_size);
-        }
-        return max_size;
-    } else {
-        return 0;
-    }
-}
-/*
- * Function to assign a register which has the lowest index
- */
-pub fn get_reg() -> usize {
-    let mut register = REGISTERS.lock().unwrap();
-    for i in 0..MAX_REGISTERS {
-        //lowest register number free is returned
-        if register[i].0 == false {
-            register[i].0 = true;
-            return i.try_into().unwrap();
-        }
-    }
-    return MAX_REGISTERS.try_into().unwrap();
-}
 // * Error handler
 pub fn exit_on_err(err: String) {
     log::error!("{}", err);
     std::process::exit(-1);
 }
-/*
- * Function to free a given register, typically the highest
- * index is passed
- */
-pub fn free_reg(register: usize) -> u64 {
-    if register > 21 {
-        return MAX_REGISTERS.try_into().unwrap();
-    }
-    let mut registers = REGISTERS.lock().unwrap();
-    if registers[register].0 == false {
-        log::warn!("Reg{} double free warning", register);
-    }
-    registers[register].0 = false;
-    return MAX_REGISTERS.try_into().unwrap();
+fn __new_label() -> usize {
+    let mut label_count = LABEL_COUNT.lock().unwrap();
+    *label_count += 1;
+    *label_count - 1
 }
-//function to push arguments
-fn __push_args(file: &File, arglist: &LinkedList<ASTNode>, refr: bool) {
-    for arg in arglist {
-        let argreg = __code_gen(&arg, file, refr);
-        write_line(file, format_args!("PUSH R{}", argreg));
-        free_reg(argreg);
-    }
+
+struct Lowering<'a> {
+    file: &'a File,
+    locations: Vec<Location>,
+    intervals: Vec<Interval>,
+    callee_saved: Vec<usize>,
+    frame: i64,
+    //words reserved above BP, locals and spill slots
+    storage: i64,
+    labels: HashMap<BlockId, usize>,
 }
-//function to backup live registers
-fn __backup_registers(file: &File) {
-    let mut registers = REGISTERS.lock().unwrap();
-    let mut rs = REGISTER_STACK.lock().unwrap();
-    rs.push(registers.clone());
 
-    for i in 0..MAX_REGISTERS {
-        if registers[i].0 == true {
-            write_line(file, format_args!("PUSH R{}", i));
-        }
-        registers[i].0 = false;
+impl<'a> Lowering<'a> {
+    fn line(&self, args: std::fmt::Arguments) {
+        write_line(self.file, args);
     }
-}
-//function to get a safe register for return_value of a function
-fn __get_safe_register() -> usize {
-    let mut rs = REGISTER_STACK.lock().unwrap();
-    let registers = rs.last_mut().unwrap();
-    for i in 0..MAX_REGISTERS {
-        //lowest register number free is returned
-        if registers[i].0 == false {
-            registers[i].0 = true;
-            return i;
-        }
+    /*
+     * Leaves the address of a spill slot in SCRATCH_ADDR
+     */
+    fn slot_addr(&self, slot: i64) {
+        self.line(format_args!(
+            "MOV R{}, BP\nADD R{}, {}",
+            SCRATCH_ADDR,
+            SCRATCH_ADDR,
+            self.frame + 1 + slot
+        ));
     }
-    exit_on_err("Out of registers".to_string());
-    0
-}
-//function to restore register context
-fn __restore_registers(file: &File, safe_register: usize) {
-    let mut rs = REGISTER_STACK.lock().unwrap();
-    let mut registers = rs.last().unwrap().clone();
-    for i in (0..MAX_REGISTERS).rev() {
-        if registers[i].0 == true && i != safe_register {
-            write_line(file, format_args!("POP R{}", i));
+    /*
+     * Register holding the operand, loaded into scratch when it is not in one
+     */
+    fn read(&self, operand: &Operand, scratch: usize) -> usize {
+        match operand {
+            Operand::Reg(v) => match self.locations[*v] {
+                Location::Reg(r) => return r,
+                Location::Slot(s) => {
+                    self.slot_addr(s);
+                    self.line(format_args!("MOV R{}, [R{}]", scratch, SCRATCH_ADDR));
+                }
+            },
+            Operand::Imm(n) => self.line(format_args!("MOV R{}, {}", scratch, n)),
+            Operand::Str(s) => self.line(format_args!("MOV R{}, \"{}\"", scratch, s)),
+            Operand::Label(l) => self.line(format_args!("MOV R{}, L{}", scratch, l)),
         }
+        scratch
     }
-    rs.pop();
-    registers[safe_register].0 = true;
-    let mut reg = REGISTERS.lock().unwrap();
-    *reg = registers.clone();
-    std::mem::drop(registers);
-    //reset to not used
-}
-fn __copy_struct(file: &File, name: &String, left_register: usize, right_register: usize) {
-    if let ASTExprType::Struct(s) = getvartype(name).unwrap() {
-        for _ in 0..s.size - 1 {
-            write_line(
-                file,
-                format_args!("MOV [R{}], R{}", left_register, right_register),
-            );
-            write_line(file, format_args!("ADD R{}, 1", left_register));
-            write_line(file, format_args!("ADD R{}, 1", right_register));
+    fn read_into(&self, operand: &Operand, register: usize) {
+        let r = self.read(operand, register);
+        if r != register {
+            self.line(format_args!("MOV R{}, R{}", register, r));
         }
     }
-}
-fn __load_variable(mut file: &File, vname: &String) -> usize {
-    let lst = LOCALSYMBOLTABLE.lock().unwrap();
-    if let Some(LSymbol::Var {
-        vartype: _,
-        varid,
-        varindices: _,
-    }) = lst.get(vname)
-    {
-        let vreg = get_reg();
-        write_line(file, format_args!("MOV R{}, BP", vreg));
-        if *varid < 0 {
-            write_line(file, format_args!("SUB R{}, {}", vreg, -1 * varid));
-        } else {
-            write_line(file, format_args!("ADD R{}, {}", vreg, varid));
+    /*
+     * Register a result is computed in, stored by write_back when spilled
+     */
+    fn target(&self, dst: VReg) -> usize {
+        match self.locations[dst] {
+            Location::Reg(r) => r,
+            Location::Slot(_) => SCRATCH,
         }
-        return vreg;
     }
-    let gst = GLOBALSYMBOLTABLE.lock().unwrap();
-    if let Some(GSymbol::Var {
-        vartype: _, varid, ..
-    }) = gst.get(vname)
-    {
-        let vreg = get_reg();
-        if let Err(e) = writeln!(
-            file,
-            "MOV R{}, {}",
-            vreg,
-            XSM_STACK_OFFSET + i64::try_from(varid.clone()).unwrap()
-        ) {
-            exit_on_err(e.to_string())
+    fn write_back(&self, dst: VReg, register: usize) {
+        if let Location::Slot(s) = self.locations[dst] {
+            self.slot_addr(s);
+            self.line(format_args!("MOV [R{}], R{}", SCRATCH_ADDR, register));
         }
-        return vreg;
     }
-
-    exit_on_err("Variable not declared".to_string());
-    0
-}
-/*
- * Class funccall
- */
-fn __gen_class_func_call(
-    baseaddrreg: usize,
-    classname: &String,
-    fname: &String,
-    arglist: &Box<LinkedList<ASTNode>>,
-    file: &File,
-    refr: bool,
-) -> usize {
-    //Push Arguments
-    __push_args(file, arglist, refr);
-    //Push return value
-    write_line(file, format_args!("ADD SP, {}", 1));
-    write_line(
-        file,
-        format_args!("CALL L{}", get_function_label(fname, &classname)),
-    );
-    let ret_reg = __get_safe_register();
-    //extract return register
-    write_line(file, format_args!("POP R{}", ret_reg));
-    //remove arguments
-    write_line(file, format_args!("SUB SP, {}", (&**arglist).len() + 1));
-    //Restore live registers except_ret_reg
-    __restore_registers(file, ret_reg);
-    free_reg(baseaddrreg);
-    return ret_reg;
-}
-/*
- * Meta function which recursively generates assembly lines
- * in xsm for arithmetic operations
- */
-fn __code_gen(root: &ASTNode, mut file: &File, refr: bool) -> usize {
-    match root {
-        ASTNode::ClassNode { cname, methods } => {
-            //gen code for every method inside class
-            let mut cn = CLASSNAME.lock().unwrap();
-            *cn = cname.clone();
-            std::mem::drop(cn);
-
-            for i in methods.iter() {
-                __code_gen(i, file, false);
-            }
-
-            let mut cn = CLASSNAME.lock().unwrap();
-            *cn = "".to_owned();
-            std::mem::drop(cn);
-            CONN_RETURN
-        }
-        ASTNode::ErrorNode { err } => {
-            let err: String = match err {
-                ASTError::TypeError(s) => s.to_owned(),
-            };
-            exit_on_err(err);
-            unreachable!()
-        }
-        ASTNode::BreakpointNode => {
-            write_line(file, format_args!("BRKP"));
-            CONN_RETURN
-        }
-        ASTNode::STR(s) => {
-            let register = get_reg();
-            let mut registers = REGISTERS.lock().unwrap();
-            write_line(file, format_args!("MOV R{}, {}", register, s));
-            registers[register].1 = 0;
-            register
+    /*
+     * Registers holding values needed after the call at position
+     * Compiled functions keep the callee saved registers, the library
+     * and interrupts may clobber every register
+     */
+    fn live_across(&self, position: usize, clobbers_all: bool) -> Vec<usize> {
+        let mut registers: Vec<usize> = self
+            .intervals
+            .iter()
+            .filter(|i| i.start < position && i.end > position)
+            .filter_map(|i| match self.locations[i.vreg] {
+                Location::Reg(r) if clobbers_all || r < CALLER_SAVED => Some(r),
+                _ => None,
+            })
+            .collect();
+        registers.sort();
+        registers
+    }
+    fn push_args(&self, args: &[Operand]) {
+        for arg in args.iter() {
+            let r = self.read(arg, SCRATCH);
+            self.line(format_args!("PUSH R{}", r));
         }
-        ASTNode::INT(n) => {
-            let register = get_reg();
-            let mut registers = REGISTERS.lock().unwrap();
-            write_line(file, format_args!("MOV R{}, {}", register, n));
-            registers[register].1 = *n;
-            register
+    }
+    fn pop_result(&self, dst: VReg) {
+        let r = self.target(dst);
+        self.line(format_args!("POP R{}", r));
+        self.write_back(dst, r);
+    }
+    /*
+     * Library routine with its name and the arguments on the stack
+     */
+    fn lib_call(&self, name: &str, args: &[Operand]) {
+        self.line(format_args!(
+            "MOV R{}, \"{}\"\nPUSH R{}",
+            SCRATCH, name, SCRATCH
+        ));
+        self.push_args(args);
+        self.line(format_args!("ADD SP, {}\nCALL 0", 4 - args.len()));
+    }
+    fn inst(&self, inst: &Inst, position: usize) {
+        let saved = if inst.is_call() {
+            self.live_across(position, !matches!(inst, Inst::Call { .. }))
+        } else {
+            vec![]
+        };
+        for r in saved.iter() {
+            self.line(format_args!("PUSH R{}", r));
         }
-        ASTNode::VAR {
-            name,
-            array_access: indices,
-            dot_field_access,
-            arrow_field_access,
-        } => {
-            let varid = getvarid(name).expect("Error in variable tables");
-            let varindices = getvarindices(name).expect("Error in variable tables");
-
-            let baseaddrreg = __load_variable(file, name);
-
-            let mut registers = REGISTERS.lock().unwrap();
-            registers[baseaddrreg].1 =
-                i64::try_from(XSM_STACK_OFFSET).unwrap() + i64::try_from(varid).unwrap();
-            std::mem::drop(registers);
-
-            for i in 0..indices.len() {
-                //Generate code for first index
-                let offsetreg = __code_gen(&*indices[i], file, false);
-                //Multiple unless its the last index
-                //varindices because we need to handle a[2][2] with a[1] access as pointer
-                if i != varindices.len() - 1 {
-                    //Get register for multiplication
-                    let indexmulreg = get_reg();
-                    //Multiply with the corresponding declared index
-                    write_line(file, format_args!("MUL R{}, {}", offsetreg, varindices[i]));
-                    //free this for reuse
-                    free_reg(indexmulreg);
-                }
-                //Add the offset
-                let mut registers = REGISTERS.lock().unwrap();
-                write_line(file, format_args!("ADD R{}, R{}", baseaddrreg, offsetreg));
-                registers[baseaddrreg].1 += registers[offsetreg].1;
-                std::mem::drop(registers);
-                //Free this for reuse
-                free_reg(offsetreg);
+        match inst {
+            Inst::Copy { dst, src } => {
+                let d = self.target(*dst);
+                self.read_into(src, d);
+                self.write_back(*dst, d);
             }
-            let mut dotptr = &**dot_field_access;
-            let mut arrowptr = &**arrow_field_access;
-            let mut currtype = getvartype(name).unwrap();
-            for _ in 0..indices.len() {
-                currtype = currtype.derefr().unwrap();
+            Inst::Binary { op, dst, lhs, rhs } => {
+                let d = self.target(*dst);
+                let immediate = matches!(op, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div);
+                let right = match rhs {
+                    Operand::Imm(n) if immediate => n.to_string(),
+                    _ => format!("R{}", self.read(rhs, SCRATCH2)),
+                };
+                self.read_into(lhs, d);
+                let mnemonic = match op {
+                    BinOp::Add => "ADD",
+                    BinOp::Sub => "SUB",
+                    BinOp::Mul => "MUL",
+                    BinOp::Div => "DIV",
+                    BinOp::Mod => "MOD",
+                    BinOp::Gt => "GT",
+                    BinOp::Lt => "LT",
+                    BinOp::Ge => "GE",
+                    BinOp::Le => "LE",
+                    BinOp::Eq => "EQ",
+                    BinOp::Ne => "NE",
+                };
+                self.line(format_args!("{} R{}, {}", mnemonic, d, right));
+                self.write_back(*dst, d);
             }
-            loop {
-                if dotptr == &ASTNode::Void && arrowptr == &ASTNode::Void {
-                    break;
+            Inst::FrameAddr { dst, offset } => {
+                let d = self.target(*dst);
+                self.line(format_args!("MOV R{}, BP", d));
+                if *offset < 0 {
+                    self.line(format_args!("SUB R{}, {}", d, -offset));
+                } else {
+                    self.line(format_args!("ADD R{}, {}", d, offset));
                 }
-                match dotptr {
-                    ASTNode::VAR {
-                        name: nname,
-                        array_access: _,
-                        dot_field_access,
-                        arrow_field_access,
-                    } => {
-                        let field_offset = currtype.get_field_id(nname).unwrap();
-                        currtype = currtype.get_field_type(nname).unwrap();
-                        write_line(file, format_args!("ADD R{}, {}", baseaddrreg, field_offset));
-                        dotptr = &**dot_field_access;
-                        arrowptr = &**arrow_field_access;
-                        continue;
-                    }
-                    ASTNode::FuncCallNode { fname, arglist } => {
-                        //push contents of baseaddr reg first
-                        //Save Live registers except ret_reg
-                        let classname = currtype.get_type_name().unwrap();
-                        __backup_registers(file);
-                        //Push Self addr
-                        write_line(file, format_args!("PUSH R{}", baseaddrreg));
-                        return __gen_class_func_call(
-                            baseaddrreg,
-                            &classname,
-                            fname,
-                            arglist,
-                            file,
-                            refr,
-                        );
-                    }
-                    ASTNode::Void => {}
+                self.write_back(*dst, d);
+            }
+            Inst::Load { dst, addr } => {
+                let d = self.target(*dst);
+                match addr {
+                    Operand::Imm(n) => self.line(format_args!("MOV R{}, [{}]", d, n)),
                     _ => {
-                        unreachable!();
+                        let a = self.read(addr, SCRATCH2);
+                        self.line(format_args!("MOV R{}, [R{}]", d, a));
                     }
                 }
-                match arrowptr {
-                    ASTNode::VAR {
-                        name: nname,
-                        array_access: _,
-                        dot_field_access,
-                        arrow_field_access,
-                    } => {
-                        if let ASTExprType::Pointer(etype) = &currtype {
-                            write_line(
-                                file,
-                                format_args!("MOV R{}, [R{}]", baseaddrreg, baseaddrreg),
-                            );
-                            let field_offset = etype.get_field_id(nname).unwrap();
-                            currtype = etype.get_field_type(nname).unwrap();
-                            write_line(
-                                file,
-                                format_args!("ADD R{}, {}", baseaddrreg, field_offset),
-                            );
-                            dotptr = &**dot_field_access;
-                            arrowptr = &**arrow_field_access;
-                            continue;
-                        }
-                    }
-                    ASTNode::FuncCallNode { fname, arglist } => {
-                        //push contents of baseaddr reg first
-                        //Save Live registers except ret_reg
-                        if let ASTExprType::Pointer(etype) = &currtype {
-                            write_line(
-                                file,
-                                format_args!("MOV R{}, [R{}]", baseaddrreg, baseaddrreg),
-                            );
-                            let classname = etype.get_type_name().unwrap();
-                            __backup_registers(file);
-                            //Push Self addr
-                            write_line(file, format_args!("PUSH R{}", baseaddrreg));
-                            //Push Arguments
-                            return __gen_class_func_call(
-                                baseaddrreg,
-                                &classname,
-                                fname,
-                                arglist,
-                                file,
-                                refr,
-                            );
-                        }
-                    }
+                self.write_back(*dst, d);
+            }
+            Inst::Store { addr, src } => {
+                let s = self.read(src, SCRATCH2);
+                match addr {
+                    Operand::Imm(n) => self.line(format_args!("MOV [{}], R{}", n, s)),
                     _ => {
-                        unreachable!();
+                        let a = self.read(addr, SCRATCH);
+                        self.line(format_args!("MOV [R{}], R{}", a, s));
                     }
                 }
             }
-            if refr == false && varindices.len() == indices.len() {
-                write_line(
-                    file,
-                    format_args!("MOV R{}, [R{}]", baseaddrreg, baseaddrreg),
-                );
-            }
-            return baseaddrreg;
-        }
-        ASTNode::BinaryNode {
-            op,
-            exprtype: _,
-            lhs,
-            rhs,
-        } => {
-            let result = match op {
-                ASTNodeType::Gt => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("GT R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = (registers[left_register].1 > registers[right_register].1)
-                        .try_into()
-                        .unwrap();
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Lt => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("LT R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = (registers[left_register].1 < registers[right_register].1)
-                        .try_into()
-                        .unwrap();
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Gte => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("GE R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = (registers[left_register].1 >= registers[right_register].1)
-                        .try_into()
-                        .unwrap();
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Lte => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("LE R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = (registers[left_register].1 <= registers[right_register].1)
-                        .try_into()
-                        .unwrap();
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Ee => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("EQ R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = (registers[left_register].1 == registers[right_register].1)
-                        .try_into()
-                        .unwrap();
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Ne => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("NE R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = (registers[left_register].1 != registers[right_register].1)
-                        .try_into()
-                        .unwrap();
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Plus => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("ADD R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = registers[left_register].1 + registers[right_register].1;
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Minus => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("SUB R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = registers[left_register].1 - registers[right_register].1;
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Star => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("MUL R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = registers[left_register].1 * registers[right_register].1;
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Slash => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("DIV R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = registers[left_register].1 / registers[right_register].1;
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Mod => {
-                    let left_register: usize = __code_gen(lhs, file, false).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    let mut registers = REGISTERS.lock().unwrap();
-                    write_line(
-                        file,
-                        format_args!("MOD R{}, R{}", left_register, right_register),
-                    );
-                    let result: i64 = registers[left_register].1 % registers[right_register].1;
-                    let lower_register = min(left_register, right_register);
-                    registers[lower_register].1 = result;
-                    // release mutex for global array so that register can be freed
-                    std::mem::drop(registers);
-                    free_reg(left_register + right_register - lower_register);
-                    lower_register
-                }
-                ASTNodeType::Equals => {
-                    let left_register: usize = __code_gen(lhs, file, true).try_into().unwrap();
-                    let right_register: usize = __code_gen(rhs, file, false).try_into().unwrap();
-                    match &**lhs {
-                        ASTNode::VAR { name, .. } => {
-                            __copy_struct(file, name, left_register, right_register);
-                        }
-                        ASTNode::UnaryNode {
-                            op: _,
-                            exprtype: _,
-                            ptr,
-                            depth: _,
-                        } => {
-                            if let ASTNode::VAR { name, .. } = &**ptr {
-                                __copy_struct(file, name, left_register, right_register);
-                            }
-                        }
-                        _ => {}
+            Inst::Call { callee, args, rets } => {
+                self.push_args(args);
+                self.line(format_args!("ADD SP, {}", rets.len()));
+                match callee {
+                    Callee::Direct(l) => self.line(format_args!("CALL L{}", l)),
+                    Callee::Indirect(f) => {
+                        let r = self.read(f, SCRATCH);
+                        self.line(format_args!("CALL R{}", r));
                     }
-                    write_line(
-                        file,
-                        format_args!("MOV [R{}], R{}", left_register, right_register),
-                    );
-                    free_reg(left_register);
-                    free_reg(right_register);
-                    CONN_RETURN
                 }
-                ASTNodeType::Connector => {
-                    free_reg(__code_gen(lhs, file, false));
-                    free_reg(__code_gen(rhs, file, false));
-                    CONN_RETURN
+                //the first return slot is on top
+                for ret in rets.iter() {
+                    self.pop_result(*ret);
+                }
+                if !args.is_empty() {
+                    self.line(format_args!("SUB SP, {}", args.len()));
                 }
-                _ => 0,
-            };
-            result
-        }
-        ASTNode::Null => {
-            let reg = get_reg();
-            write_line(file, format_args!("MOV R{}, \"\"", reg));
-            reg
-        }
-        ASTNode::UnaryNode {
-            op,
-            exprtype: _,
-            ptr,
-            depth,
-        } => match op {
-            ASTNodeType::Alloc => {
-                //let mut unopt = (&**ptr).clone();
-                let mptr =
-                    __xsm_alloc_syscall(file /*unopt.getexprtype().unwrap().size().unwrap()*/);
-                let p = __code_gen(&**ptr, file, true);
-                write_line(file, format_args!("MOV [R{}], R{}", p, mptr));
-                free_reg(mptr);
-                free_reg(p);
-                CONN_RETURN
-            }
-            ASTNodeType::Free => {
-                free_reg(__xsm_free_syscall(file, __code_gen(&**ptr, file, refr)));
-                CONN_RETURN
-            }
-            ASTNodeType::Initialize => {
-                free_reg(__xsm_heapset_syscall(file));
-                CONN_RETURN
-            }
-            ASTNodeType::Read => {
-                __backup_registers(file);
-                let register = get_reg();
-                write_line(file, format_args!("MOV R{}, \"Read\"", register));
-                write_line(file, format_args!("PUSH R{}", register));
-                write_line(file, format_args!("MOV R{}, -1", register));
-                write_line(file, format_args!("PUSH R{}", register));
-                free_reg(register);
-                let register: usize = __code_gen(ptr, file, true).try_into().unwrap();
-                write_line(file, format_args!("PUSH R{}", register));
-                write_line(file, format_args!("ADD SP, 2"));
-                write_line(file, format_args!("CALL 0"));
-                let ret_reg = __get_safe_register();
-                write_line(file, format_args!("POP R{}", ret_reg));
-                write_line(file, format_args!("SUB SP, 4"));
-                __restore_registers(file, ret_reg);
-                ret_reg
             }
-            ASTNodeType::Write => {
-                __backup_registers(file);
-                let register = get_reg();
-                write_line(file, format_args!("MOV R{}, \"Write\"", register));
-                write_line(file, format_args!("PUSH R{}", register));
-                write_line(file, format_args!("MOV R{}, -2", register));
-                write_line(file, format_args!("PUSH R{}", register));
-                free_reg(register);
-                let variable: usize = __code_gen(ptr, file, false).try_into().unwrap();
-                write_line(file, format_args!("PUSH R{}", variable));
-                write_line(file, format_args!("ADD SP, 2"));
-                write_line(file, format_args!("CALL 0"));
-                let ret_reg = __get_safe_register();
-                write_line(file, format_args!("POP R{}", ret_reg));
-                write_line(file, format_args!("SUB SP, 4"));
-                __restore_registers(file, ret_reg);
-                ret_reg
+            Inst::LibCall { func, args, dst } => {
+                self.lib_call(&format!("{:?}", func), args);
+                self.pop_result(*dst);
+                self.line(format_args!("SUB SP, 4"));
             }
-            ASTNodeType::Ref => match &**ptr {
-                ASTNode::VAR { .. } => {
-                    let regaddr: usize = __code_gen(ptr, file, true).try_into().unwrap();
-                    return regaddr;
-                }
-                _ => {
-                    unreachable!();
-                }
-            },
-            ASTNodeType::Deref => match &**ptr {
-                ASTNode::VAR { .. } => {
-                    let regaddr: usize = __code_gen(ptr, file, refr).try_into().unwrap();
-                    for _i in 0..depth.unwrap() {
-                        write_line(file, format_args!("MOV R{},[R{}]", regaddr, regaddr));
-                    }
-                    return regaddr;
+            Inst::Syscall { num, args, dst } => {
+                self.push_args(args);
+                self.line(format_args!("ADD SP, 1\nINT {}", num));
+                self.pop_result(*dst);
+                if !args.is_empty() {
+                    self.line(format_args!("SUB SP, {}", args.len()));
                 }
-                _ => {
-                    unreachable!();
-                }
-            },
-            _ => {
-                unreachable!();
             }
-        },
-        ASTNode::StdFuncCallNode { func, arglist } => match func {
-            STDLibFunction::Syscall => {
-                __backup_registers(file);
-                let mut c = 0;
-                let mut interruptval = 0;
-                for i in arglist.iter() {
-                    if c != 1 {
-                        let reg = __code_gen(i, file, false);
-                        write_line(file, format_args!("PUSH R{}", reg));
-                        free_reg(reg);
-                    } else {
-                        if let ASTNode::INT(p) = i {
-                            interruptval = *p;
-                        } else {
-                            unreachable!()
-                        }
-                    }
-                    c = c + 1;
+            Inst::Breakpoint => self.line(format_args!("BRKP")),
+        }
+        for r in saved.iter().rev() {
+            self.line(format_args!("POP R{}", r));
+        }
+    }
+    /*
+     * next is the block laid out after this one, jumps to it fall through
+     */
+    fn terminator(&self, term: &Terminator, next: Option<BlockId>) {
+        match term {
+            Terminator::Jump(b) => {
+                if Some(*b) != next {
+                    self.line(format_args!("JMP L{}", self.labels[b]));
                 }
-                let reg = __get_safe_register();
-                write_line(file, format_args!("ADD SP, 1"));
-                write_line(file, format_args!("INT {}", interruptval));
-                write_line(file, format_args!("POP R{}", reg));
-                write_line(file, format_args!("SUB SP, 4"));
-                __restore_registers(file, reg);
-                reg
-            }
-            STDLibFunction::Getaddr => {
-                let reg = __code_gen((&**arglist).front().unwrap(), file, false);
-                write_line(file, format_args!("MOV R{}, [R{}]", reg, reg));
-                reg
             }
-            STDLibFunction::Setaddr => {
-                let mut c = 0;
-                let mut reg1 = 5;
-                let mut reg2 = 5;
-                for i in &**arglist {
-                    if c == 0 {
-                        reg1 = __code_gen(i, file, false);
-                    } else {
-                        reg2 = __code_gen(i, file, false);
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let c = self.read(cond, SCRATCH);
+                if Some(*otherwise) == next {
+                    self.line(format_args!("JNZ R{}, L{}", c, self.labels[then]));
+                } else {
+                    self.line(format_args!("JZ R{}, L{}", c, self.labels[otherwise]));
+                    if Some(*then) != next {
+                        self.line(format_args!("JMP L{}", self.labels[then]));
                     }
-                    c = c + 1;
                 }
-                write_line(file, format_args!("MOV [R{}], R{}", reg1, reg2));
-                free_reg(reg1);
-                free_reg(reg2);
-                CONN_RETURN
             }
-            _ => unreachable!(),
-        },
-        ASTNode::FuncCallNode { fname, arglist } => {
-            //Save Live registers except ret_reg
-            __backup_registers(file);
-            //Push Arguments
-            __push_args(file, arglist, refr);
-            //Push return value
-            write_line(file, format_args!("ADD SP, {}", 1));
-            let gst = GLOBALSYMBOLTABLE.lock().unwrap();
-            if let Some(entry) = gst.get(fname) {
-                match entry {
-                    GSymbol::Func {
-                        ret_type: _,
-                        paramlist: _,
-                        flabel,
-                    } => {
-                        write_line(file, format_args!("CALL L{}", flabel));
-                    }
-                    _ => exit_on_err("Function not declared".to_string()),
+            //the i-th value goes to [BP-2-i]
+            Terminator::Return(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    let r = self.read(value, SCRATCH);
+                    self.line(format_args!(
+                        "MOV R{}, BP\nSUB R{}, {}\nMOV [R{}], R{}",
+                        SCRATCH_ADDR,
+                        SCRATCH_ADDR,
+                        2 + i,
+                        SCRATCH_ADDR,
+                        r
+                    ));
                 }
+                for r in self.callee_saved.iter().rev() {
+                    self.line(format_args!("POP R{}", r));
+                }
+                self.line(format_args!("SUB SP, {}\nPOP BP\nRET", self.storage));
             }
-            let ret_reg = __get_safe_register();
-            //extract return register
-            write_line(file, format_args!("POP R{}", ret_reg));
-            //remove arguments
-            write_line(file, format_args!("SUB SP, {}", (&**arglist).len()));
-            //Restore live registers except_ret_reg
-            __restore_registers(file, ret_reg);
-            ret_reg
-        }
-        ASTNode::ReturnNode { expr } => {
-            let fs = FSTACK.lock().unwrap();
-            let (_fname, storage) = fs.clone();
-            std::mem::drop(fs);
-
-            let retreg = __code_gen(expr, file, refr);
-            let reg = get_reg();
-
-            write_line(file, format_args!("MOV R{}, BP", reg));
-            write_line(file, format_args!("SUB R{}, 2", reg));
-            write_line(file, format_args!("MOV [R{}], R{}", reg, retreg));
-            write_line(file, format_args!("SUB SP, {}\nPOP BP", storage));
-            write_line(file, format_args!("RET"));
-
-            let mut registers = REGISTERS.lock().unwrap();
-            *registers = vec![(false, 0); MAX_REGISTERS];
-            std::mem::drop(registers);
-            CONN_RETURN
-        }
-        ASTNode::MainNode { body } => {
-            //this node is traverse after all function def nodes,
-            write_line(
-                file,
-                format_args!(
-                    "L{}:",
-                    get_function_label(&"main".to_owned(), &String::default())
-                ),
-            );
-            let ft = FUNCTION_TABLE.lock().unwrap();
-            if let Some(local_table) = ft.get("main#") {
-                let mut lst = LOCALSYMBOLTABLE.lock().unwrap();
-                *lst = local_table.clone();
-                std::mem::drop(ft);
-                std::mem::drop(lst);
-
-                write_line(file, format_args!("PUSH BP\nMOV BP, SP",));
-                write_line(
-                    file,
-                    format_args!("ADD SP, {}", __get_function_storage(&"main".to_owned())),
-                );
-                //idk
-                let mut fs = FSTACK.lock().unwrap();
-                *fs = (
-                    "main".to_string(),
-                    __get_function_storage(&"main".to_owned()),
-                );
-                std::mem::drop(fs);
-
-                __backup_registers(file);
-
-                __code_gen(body, file, false);
-
-                let mut registers = REGISTERS.lock().unwrap();
-                *registers = vec![(false, 0); MAX_REGISTERS];
-                std::mem::drop(registers);
-            } else {
-                unreachable!();
-            }
-            CONN_RETURN
-        }
-        /*
-         * L{funclabel}:
-         *    Subtract SP by declvars.size()
-         *    <body>
-         *    ret
-         */
-        ASTNode::FuncDefNode {
-            fname,
-            ret_type: _,
-            paramlist: _,
-            body,
-        } => {
-            write_line(
-                file,
-                format_args!(
-                    "L{}:\nPUSH BP\nMOV BP, SP",
-                    get_function_label(fname, &*CLASSNAME.lock().unwrap())
-                ),
-            );
-            write_line(
-                file,
-                format_args!("ADD SP, {}", __get_function_storage(fname)),
-            );
-            let ft = FUNCTION_TABLE.lock().unwrap();
-            if let Some(_local_table) = ft.get(&__get_table_id(fname)) {
-                let mut lst = LOCALSYMBOLTABLE.lock().unwrap();
-                *lst = _local_table.clone();
-                let mut fs = FSTACK.lock().unwrap();
-                std::mem::drop(ft);
-                std::mem::drop(lst);
-                *fs = (fname.clone(), __get_function_storage(fname));
-                std::mem::drop(fs);
-
-                __code_gen(&**body, file, false);
+            //the program stops here, every register can be clobbered
+            Terminator::Trap { message, line } => {
+                for text in [message.clone(), format!("line {}", line)] {
+                    self.lib_call("Write", &[Operand::Imm(-2), Operand::Str(text)]);
+                    self.line(format_args!("SUB SP, 5"));
+                }
+                self.line(format_args!("PUSH R0\nINT 10"));
             }
-            CONN_RETURN
         }
-        /*
-         * <expr>
-         * <cond>
-         * <jz> L1
-         * <if>
-         * jmp L2
-         * L1:
-         * <else>
-         * L2:
-         */
-        ASTNode::IfElseNode { expr, xif, xelse } => {
-            let mut label_count = LABEL_COUNT.lock().unwrap();
-            let l1 = label_count.clone();
-            (*label_count) += 1;
-            let l2 = label_count.clone();
-            (*label_count) += 1;
-            //drop for handling nested cases
-            std::mem::drop(label_count);
-            let result: usize = __code_gen(expr, file, false).try_into().unwrap();
-            //Generate code for the expression
-            write_line(file, format_args!("JZ R{}, L{}", result, l1));
-            //Free the register
-            free_reg(result);
-            //Drop label_count so that nested cases can be handled
-            //generate if case flow
-            __code_gen(xif, file, false);
-            //result is 0 as xif is a stmtlist
-            //Jmp to L2 if its else case
-            write_line(file, format_args!("JMP L{}", l2));
-            //add label count for exit case
-            write_line(file, format_args!("L{}:", l1));
-            __code_gen(xelse, file, false);
-            write_line(file, format_args!("L{}:", l2));
-            CONN_RETURN
-        }
-        /* While Node
-         * L1:
-         * <expr>
-         * <cond>
-         * <jz> L2
-         * <do>
-         * <jmp> L1
-         * L2:
-         */
-        ASTNode::WhileNode { expr, xdo } => {
-            let mut label_count = LABEL_COUNT.lock().unwrap();
-            let mut while_tracker = WHILE_TRACKER.lock().unwrap();
-
-            let l1 = label_count.clone();
-            //Create a new label
-            write_line(file, format_args!("L{}:", l1));
-            (*label_count) += 1;
-
-            let l2 = label_count.clone();
-            (*label_count) += 1;
-
-            while_tracker.push(l1);
-            while_tracker.push(l2);
-            //Drop label_count so that nested cases can be handled
-            std::mem::drop(label_count);
-            std::mem::drop(while_tracker);
-            let result: usize = __code_gen(expr, file, false).try_into().unwrap();
-            //Generate code for the expression
-            write_line(file, format_args!("JZ R{}, L{}", result, l2));
-            //Free the register
-            free_reg(result);
-            //generate if case flow
-            //result is 0 as xif is a stmtlist
-            __code_gen(xdo, file, false);
-            //while loop it back to top condition
-
-            let mut while_tracker = WHILE_TRACKER.lock().unwrap();
-            while_tracker.pop();
-            while_tracker.pop();
-            write_line(file, format_args!("JMP L{}", l1));
-            //add label count for exit case
-            write_line(file, format_args!("L{}:", l2));
-            //increment label_count
-            CONN_RETURN
-        }
-        /* If Node
-         * <expr>
-         * <cond>
-         * <jz> L1
-         * <ifcase>
-         * L1:
-         */
-        ASTNode::IfNode { expr, xif } => {
-            let mut label_count = LABEL_COUNT.lock().unwrap();
-            let l1 = label_count.clone();
-            (*label_count) += 1;
-            //Drop label_count so that nested cases can be handled
-            std::mem::drop(label_count);
-            let result: usize = __code_gen(expr, file, false).try_into().unwrap();
-            //Generate code for the expression
-            write_line(file, format_args!("JZ R{}, L{}", result, l1));
-            //Free the register
-            free_reg(result);
-            //generate if case flow
-            __code_gen(xif, file, false);
-            //result is 0 as xif is a stmtlist
-            write_line(file, format_args!("L{}:", l1));
-            //increment label_count
-            CONN_RETURN
+    }
+}
+/*
+ * L{funclabel}:
+ *    PUSH BP, reserve the locals and spill slots
+ *    PUSH the callee saved registers it uses
+ *    <blocks>
+ */
+fn __lower_function(file: &File, function: &Function) {
+    let reachable = function.reachable();
+    let order: Vec<BlockId> = (0..function.blocks.len())
+        .filter(|b| reachable[*b])
+        .collect();
+    let allocation = allocate(function, &order);
+    let mut labels = HashMap::new();
+    for b in order.iter() {
+        for s in function.blocks[*b].term.successors() {
+            labels.entry(s).or_insert_with(__new_label);
         }
-        /*
-         * L1:
-         * ...
-         * <expr>
-         * <cond>
-         * <jz> L1
-         * L2:
-         */
-        ASTNode::BreakNode => {
-            let while_tracker = WHILE_TRACKER.lock().unwrap();
-            writeln!(file, "JMP L{}", while_tracker[while_tracker.len() - 1])
-                .expect("[code_gen] Write error");
-            CONN_RETURN
+    }
+    let lowering = Lowering {
+        file,
+        locations: allocation.locations,
+        intervals: allocation.intervals,
+        callee_saved: allocation.callee_saved,
+        frame: function.frame,
+        storage: function.frame + allocation.slots,
+        labels,
+    };
+    lowering.line(format_args!(
+        "L{}:\nPUSH BP\nMOV BP, SP\nADD SP, {}",
+        function.label, lowering.storage
+    ));
+    for r in lowering.callee_saved.iter() {
+        lowering.line(format_args!("PUSH R{}", r));
+    }
+    //positions are numbered as in the allocator, blocks have an entry position
+    let mut position = 0;
+    for (i, b) in order.iter().enumerate() {
+        if let Some(l) = lowering.labels.get(b) {
+            lowering.line(format_args!("L{}:", l));
         }
-        ASTNode::ContinueNode => {
-            let while_tracker = WHILE_TRACKER.lock().unwrap();
-            writeln!(file, "JMP L{}", while_tracker[while_tracker.len() - 2])
-                .expect("[code_gen] Write error");
-            CONN_RETURN
+        position += 1;
+        let block = &function.blocks[*b];
+        for inst in block.insts.iter() {
+            lowering.inst(inst, position);
+            position += 1;
         }
-        ASTNode::Void => CONN_RETURN,
+        lowering.terminator(&block.term, order.get(i + 1).copied());
+        position += 1;
     }
 }
 
@@ -1089,6 +414,7 @@ fn __header_gen(mut file: &File) {
     let mut label_count = LABEL_COUNT.lock().unwrap();
     let l = label_count.clone();
     *label_count += 1;
+    std::mem::drop(label_count);
     //in case main() is recursively called, we need the label of main
     gst.insert(
         "main".to_string(),
@@ -1098,91 +424,133 @@ fn __header_gen(mut file: &File) {
             flabel: (l),
         },
     );
-    let mut baseaddr = 0;
-    for (_k, v) in gst.iter() {
-        match v {
-            GSymbol::Var {
-                vartype: _,
-                varid: _,
-                varindices,
-            } => {
-                let mut size = 1;
-                for index in varindices {
-                    size *= index;
-                }
-                baseaddr = baseaddr + size;
-            }
-            _ => continue,
+    //globals are laid out back to back by install_to_gst
+    let baseaddr = *VARID.lock().unwrap();
+    writeln!(
+        file,
+        "0\n2056\n0\n0\n0\n0\n0\n0\nBRKP\nMOV SP, 4095\nADD SP, {baseaddr}",
+    )
+    .unwrap();
+    //constant initializers of globals are stored into the data area before main
+    let git = GLOBAL_INIT_TABLE.lock().unwrap();
+    let mut inits: Vec<(usize, &Vec<ASTNode>)> = Vec::default();
+    for (name, values) in git.iter() {
+        if let Some(GSymbol::Var { varid, .. }) = gst.get(name) {
+            inits.push((*varid, values));
+        }
+    }
+    inits.sort_by_key(|(varid, _)| *varid);
+    for (varid, values) in inits {
+        for (i, value) in values.iter().enumerate() {
+            let literal = match value {
+                ASTNode::INT(n) => n.to_string(),
+                ASTNode::EnumConst { etype: _, value } => value.to_string(),
+                ASTNode::STR(s) => s.clone(),
+                _ => "\"\"".to_owned(),
+            };
+            write_line(file, format_args!("MOV R0, {}", literal));
+            write_line(
+                file,
+                format_args!(
+                    "MOV [{}], R0",
+                    XSM_STACK_OFFSET + i64::try_from(varid + i).unwrap()
+                ),
+            );
         }
     }
+    if get_allocator() == Allocator::Builtin {
+        __builtin_heap_reset(file, 0);
+    }
     writeln!(
         file,
-        "0\n2056\n0\n0\n0\n0\n0\n0\nBRKP\nMOV SP, 4095\nADD SP, {baseaddr}\nMOV BP, SP\nADD SP, 1\nCALL L{l}\nSUB SP, 1\nPUSH R0\nINT 10",
+        "MOV BP, SP\nADD SP, 1\nCALL L{l}\nSUB SP, 1\nPUSH R0\nINT 10",
     )
     .unwrap();
-}
-
-/*
- * Meta function to generate xsm code for Alloc Syscall
- */
-fn __xsm_alloc_syscall(file: &File) -> usize {
-    __backup_registers(file);
-    let register = __get_safe_register();
-    write_line(file, format_args!("MOV R{}, \"Alloc\"", register));
-    write_line(file, format_args!("PUSH R{}", register));
-    write_line(file, format_args!("MOV R{}, {}", register, 8));
-    write_line(file, format_args!("PUSH R{}", register));
-    write_line(file, format_args!("ADD SP, 3"));
-    write_line(file, format_args!("CALL 0"));
-    write_line(file, format_args!("POP R{}", register));
-    write_line(file, format_args!("SUB SP, 4"));
-    __restore_registers(file, register);
-    register
+    if get_allocator() == Allocator::Builtin {
+        __builtin_allocator_gen(file);
+    }
 }
 /*
- * Meta function to generate xsm code for Free Syscall
+ * The builtin heap keeps the next unused address at HEAP_START
+ * and the list of freed blocks at HEAP_START+1
  */
-fn __xsm_free_syscall(file: &File, _varreg: usize) -> usize {
-    __backup_registers(file);
-    let register = __get_safe_register();
-    write_line(file, format_args!("MOV R{}, \"Free\"", register));
-    write_line(file, format_args!("PUSH R{}", register));
-    write_line(file, format_args!("MOV R{}, {}", register, 8));
-    write_line(file, format_args!("PUSH R{}", register));
-    write_line(file, format_args!("ADD SP, 3"));
-    write_line(file, format_args!("CALL 0"));
-    write_line(file, format_args!("POP R{}", register));
-    write_line(file, format_args!("SUB SP, 4"));
-    __restore_registers(file, register);
-    register
+fn __builtin_heap_reset(file: &File, register: usize) {
+    write_line(file, format_args!("MOV R{}, {}", register, HEAP_START + 2));
+    write_line(file, format_args!("MOV [{}], R{}", HEAP_START, register));
+    write_line(file, format_args!("MOV R{}, 0", register));
+    write_line(
+        file,
+        format_args!("MOV [{}], R{}", HEAP_START + 1, register),
+    );
 }
 /*
- * Meta function to generate xsm code for Initialize (Heapset) Syscall
+ * Routines of the builtin allocator, called with the argument and the return slot pushed
+ * Every block has its size one word before it, freed blocks are linked through their first word
+ * alloc takes the first freed block that fits, else cuts a new block from the unused heap
  */
-fn __xsm_heapset_syscall(file: &File) -> usize {
-    __backup_registers(file);
-    let register = __get_safe_register();
-    write_line(file, format_args!("MOV R{}, \"Heapset\"", register));
-    write_line(file, format_args!("PUSH R{}", register));
-    write_line(file, format_args!("ADD SP, 4"));
-    write_line(file, format_args!("CALL 0"));
-    write_line(file, format_args!("POP R{}", register));
-    write_line(file, format_args!("SUB SP, 4"));
-    __restore_registers(file, register);
-    register
+fn __builtin_allocator_gen(file: &File) {
+    let mut label_count = LABEL_COUNT.lock().unwrap();
+    let l = *label_count;
+    (*label_count) += 7;
+    std::mem::drop(label_count);
+    let (alloc, search, found, fresh, full, ret, free) =
+        (l, l + 1, l + 2, l + 3, l + 4, l + 5, l + 6);
+    *ALLOCATOR_LABELS.lock().unwrap() = (alloc, free);
+    //alloc(size)
+    write_line(file, format_args!("L{}:", alloc));
+    write_line(file, format_args!("MOV R0, SP\nSUB R0, 2\nMOV R0, [R0]"));
+    write_line(file, format_args!("MOV R1, {}", HEAP_START + 1));
+    write_line(file, format_args!("L{}:", search));
+    write_line(file, format_args!("MOV R2, [R1]"));
+    write_line(file, format_args!("JZ R2, L{}", fresh));
+    write_line(
+        file,
+        format_args!("MOV R3, R2\nSUB R3, 1\nMOV R3, [R3]\nGE R3, R0"),
+    );
+    write_line(file, format_args!("JNZ R3, L{}", found));
+    write_line(file, format_args!("MOV R1, R2\nJMP L{}", search));
+    write_line(file, format_args!("L{}:", found));
+    write_line(
+        file,
+        format_args!("MOV R3, [R2]\nMOV [R1], R3\nJMP L{}", ret),
+    );
+    write_line(file, format_args!("L{}:", fresh));
+    write_line(
+        file,
+        format_args!(
+            "MOV R2, [{}]\nMOV R3, R2\nADD R3, R0\nADD R3, 1",
+            HEAP_START
+        ),
+    );
+    write_line(file, format_args!("MOV R1, {}\nLT R1, R3", HEAP_END));
+    write_line(file, format_args!("JNZ R1, L{}", full));
+    write_line(
+        file,
+        format_args!("MOV [R2], R0\nMOV [{}], R3\nADD R2, 1", HEAP_START),
+    );
+    write_line(file, format_args!("JMP L{}", ret));
+    //out of heap gives back -1 like the library
+    write_line(file, format_args!("L{}:", full));
+    write_line(file, format_args!("MOV R2, -1"));
+    write_line(file, format_args!("L{}:", ret));
+    write_line(
+        file,
+        format_args!("MOV R1, SP\nSUB R1, 1\nMOV [R1], R2\nRET"),
+    );
+    //free(ptr)
+    write_line(file, format_args!("L{}:", free));
+    write_line(file, format_args!("MOV R0, SP\nSUB R0, 2\nMOV R0, [R0]"));
+    write_line(
+        file,
+        format_args!("MOV R1, [{}]\nMOV [R0], R1", HEAP_START + 1),
+    );
+    write_line(file, format_args!("MOV [{}], R0\nRET", HEAP_START + 1));
 }
 /*
  * Meta function to generate xsm code for Exit Syscall
  */
 fn __xsm_exit_syscall(file: &File) {
-    let register = get_reg();
     write_line(file, format_args!("PUSH R0\nINT 10"));
-    free_reg(register);
-}
-fn __print_gst() {
-    let gst = GLOBALSYMBOLTABLE.lock().unwrap();
-
-    log::info!("Global symbol table has {} symbols", gst.len());
 }
 pub fn code_gen(root: &ASTNode, filename: String) -> usize {
     let f = OpenOptions::new()
@@ -1195,11 +563,21 @@ pub fn code_gen(root: &ASTNode, filename: String) -> usize {
         Ok(file) => {
             file.set_len(0)
                 .expect("[code_gen] Error truncating existing file");
+            //the header gives main its label
             __header_gen(&file);
-            if __code_gen(root, &file, false) != CONN_RETURN {
-                log::error!("[code_gen] Invalid register returned.");
+            let mut program = build_program(root);
+            run_ir_passes(&mut program);
+            if emit_ir() {
+                print!("{}", program);
+            }
+            for function in program.functions.iter() {
+                __lower_function(&file, function);
             }
             __xsm_exit_syscall(&file);
+            run_object_passes(filename.as_str());
+            if time_passes() {
+                report_pass_times();
+            }
             log::trace!("Generated Object file: {}", filename.as_str());
             0
         }
@@ -0,0 +1,120 @@
+//constant folding and algebraic simplification of the IR
+use crate::ir::*;
+use std::collections::HashMap;
+
+/*
+ * Value of op on two constants, None when it would overflow or divide by zero
+ * Division truncates towards zero like XSM
+ */
+fn __evaluate(op: BinOp, lhs: i64, rhs: i64) -> Option<i64> {
+    match op {
+        BinOp::Add => lhs.checked_add(rhs),
+        BinOp::Sub => lhs.checked_sub(rhs),
+        BinOp::Mul => lhs.checked_mul(rhs),
+        BinOp::Div => lhs.checked_div(rhs),
+        BinOp::Mod => lhs.checked_rem(rhs),
+        BinOp::Gt => Some((lhs > rhs) as i64),
+        BinOp::Lt => Some((lhs < rhs) as i64),
+        BinOp::Ge => Some((lhs >= rhs) as i64),
+        BinOp::Le => Some((lhs <= rhs) as i64),
+        BinOp::Eq => Some((lhs == rhs) as i64),
+        BinOp::Ne => Some((lhs != rhs) as i64),
+    }
+}
+/*
+ * Value of a binary instruction when it reduces to one operand
+ * x+0, 0+x, x-0, x*1, 1*x, x/1 give x and x*0, 0*x give 0
+ */
+fn __simplify(op: BinOp, lhs: &Operand, rhs: &Operand) -> Option<Operand> {
+    if let (Operand::Imm(l), Operand::Imm(r)) = (lhs, rhs) {
+        return __evaluate(op, *l, *r).map(Operand::Imm);
+    }
+    //only integers take part in the identities, strings stay as they are
+    let is_reg = |o: &Operand| matches!(o, Operand::Reg(_));
+    match (op, lhs, rhs) {
+        (BinOp::Add | BinOp::Sub, x, Operand::Imm(0)) if is_reg(x) => Some(x.clone()),
+        (BinOp::Add, Operand::Imm(0), x) if is_reg(x) => Some(x.clone()),
+        (BinOp::Mul | BinOp::Div, x, Operand::Imm(1)) if is_reg(x) => Some(x.clone()),
+        (BinOp::Mul, Operand::Imm(1), x) if is_reg(x) => Some(x.clone()),
+        (BinOp::Mul, x, Operand::Imm(0)) | (BinOp::Mul, Operand::Imm(0), x) if is_reg(x) => {
+            Some(Operand::Imm(0))
+        }
+        _ => None,
+    }
+}
+/*
+ * Registers defined once by a constant are replaced by the constant,
+ * repeated until nothing folds
+ * Branches on a constant become jumps and the blocks they no longer
+ * reach are dropped
+ */
+pub fn fold_constants(function: &mut Function) {
+    loop {
+        let mut defs = vec![0; function.vregs];
+        for block in function.blocks.iter() {
+            for inst in block.insts.iter() {
+                for v in inst.defs() {
+                    defs[v] += 1;
+                }
+            }
+        }
+        let mut constants: HashMap<VReg, i64> = HashMap::new();
+        for block in function.blocks.iter() {
+            for inst in block.insts.iter() {
+                if let Inst::Copy {
+                    dst,
+                    src: Operand::Imm(n),
+                } = inst
+                {
+                    if defs[*dst] == 1 {
+                        constants.insert(*dst, *n);
+                    }
+                }
+            }
+        }
+        let mut changed = false;
+        let substitute = |o: &mut Operand| {
+            if let Operand::Reg(v) = o {
+                if let Some(n) = constants.get(v) {
+                    *o = Operand::Imm(*n);
+                    return true;
+                }
+            }
+            false
+        };
+        for block in function.blocks.iter_mut() {
+            //the constants themselves are no longer read
+            block.insts.retain(|inst| match inst {
+                Inst::Copy { dst, .. } => !constants.contains_key(dst),
+                _ => true,
+            });
+            for inst in block.insts.iter_mut() {
+                for o in inst.operands_mut() {
+                    changed |= substitute(o);
+                }
+                if let Inst::Binary { op, dst, lhs, rhs } = inst {
+                    if let Some(src) = __simplify(*op, lhs, rhs) {
+                        *inst = Inst::Copy { dst: *dst, src };
+                        changed = true;
+                    }
+                }
+            }
+            for o in block.term.operands_mut() {
+                changed |= substitute(o);
+            }
+            if let Terminator::Branch {
+                cond: Operand::Imm(n),
+                then,
+                otherwise,
+            } = block.term
+            {
+                block.term = Terminator::Jump(if n != 0 { then } else { otherwise });
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    function.remove_unreachable();
+}
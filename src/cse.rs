@@ -0,0 +1,116 @@
+//common subexpression elimination within basic blocks
+use crate::ir::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Expr {
+    Binary(BinOp, Operand, Operand),
+    FrameAddr(i64),
+    Load(Operand),
+}
+
+impl Expr {
+    fn reads(&self, v: VReg) -> bool {
+        match self {
+            Expr::Binary(_, lhs, rhs) => *lhs == Operand::Reg(v) || *rhs == Operand::Reg(v),
+            Expr::FrameAddr(_) => false,
+            Expr::Load(addr) => *addr == Operand::Reg(v),
+        }
+    }
+}
+
+fn __commutative(op: BinOp) -> bool {
+    matches!(op, BinOp::Add | BinOp::Mul | BinOp::Eq | BinOp::Ne)
+}
+/*
+ * Two addresses may be the same word unless both are distinct constants
+ */
+fn __may_alias(a: &Operand, b: &Operand) -> bool {
+    match (a, b) {
+        (Operand::Imm(a), Operand::Imm(b)) => a == b,
+        _ => true,
+    }
+}
+/*
+ * Value already computed for the expression, if any
+ */
+fn __lookup(available: &HashMap<Expr, Operand>, expr: &Expr) -> Option<Operand> {
+    if let Some(value) = available.get(expr) {
+        return Some(value.clone());
+    }
+    match expr {
+        Expr::Binary(op, lhs, rhs) if __commutative(*op) => available
+            .get(&Expr::Binary(*op, rhs.clone(), lhs.clone()))
+            .cloned(),
+        _ => None,
+    }
+}
+/*
+ * An expression computed again in the same block, with none of its
+ * operands redefined in between, becomes a copy of the first result
+ * Copies are propagated first so copies of an operand match it
+ * Loads are reused until a store that may reach the same word or a call,
+ * and a load after a store of the same address takes the stored value
+ */
+pub fn eliminate_common_subexpressions(function: &mut Function) {
+    for block in function.blocks.iter_mut() {
+        let mut available: HashMap<Expr, Operand> = HashMap::new();
+        let mut copies: HashMap<VReg, Operand> = HashMap::new();
+        let propagate = |copies: &HashMap<VReg, Operand>, o: &mut Operand| {
+            if let Operand::Reg(v) = o {
+                if let Some(src) = copies.get(v) {
+                    *o = src.clone();
+                }
+            }
+        };
+        for inst in block.insts.iter_mut() {
+            for o in inst.operands_mut() {
+                propagate(&copies, o);
+            }
+            let expr = match inst {
+                Inst::Binary { op, lhs, rhs, .. } => {
+                    Some(Expr::Binary(*op, lhs.clone(), rhs.clone()))
+                }
+                Inst::FrameAddr { offset, .. } => Some(Expr::FrameAddr(*offset)),
+                Inst::Load { addr, .. } => Some(Expr::Load(addr.clone())),
+                _ => None,
+            };
+            let dst = inst.defs().first().copied();
+            if let (Some(expr), Some(dst)) = (&expr, dst) {
+                if let Some(value) = __lookup(&available, expr) {
+                    *inst = Inst::Copy { dst, src: value };
+                }
+            }
+            //a redefined register invalidates what was computed from it or held in it
+            for v in inst.defs() {
+                available.retain(|e, value| !e.reads(v) && *value != Operand::Reg(v));
+                copies.retain(|d, src| *d != v && *src != Operand::Reg(v));
+            }
+            if inst.is_call() {
+                available.retain(|e, _| !matches!(e, Expr::Load(_)));
+            }
+            match inst {
+                Inst::Copy { dst, src } if *src != Operand::Reg(*dst) => {
+                    copies.insert(*dst, src.clone());
+                }
+                Inst::Store { addr, src } => {
+                    available.retain(|e, _| match e {
+                        Expr::Load(a) => !__may_alias(a, addr),
+                        _ => true,
+                    });
+                    available.insert(Expr::Load(addr.clone()), src.clone());
+                }
+                Inst::Binary { dst, .. } | Inst::FrameAddr { dst, .. } | Inst::Load { dst, .. } => {
+                    //an expression reading its own result no longer holds
+                    if let Some(expr) = expr.filter(|e| !e.reads(*dst)) {
+                        available.insert(expr, Operand::Reg(*dst));
+                    }
+                }
+                _ => {}
+            }
+        }
+        for o in block.term.operands_mut() {
+            propagate(&copies, o);
+        }
+    }
+}
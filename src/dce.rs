@@ -0,0 +1,135 @@
+//dead code elimination over the IR
+use crate::ir::*;
+use crate::regalloc::liveness;
+use std::collections::HashSet;
+
+/*
+ * Instructions without effects beyond their result
+ * Division is kept unless the divisor is a nonzero constant, XSM stops on zero
+ */
+fn __is_pure(inst: &Inst) -> bool {
+    match inst {
+        Inst::Copy { .. } | Inst::FrameAddr { .. } | Inst::Load { .. } => true,
+        Inst::Binary {
+            op: BinOp::Div | BinOp::Mod,
+            rhs,
+            ..
+        } => matches!(rhs, Operand::Imm(n) if *n != 0),
+        Inst::Binary { .. } => true,
+        _ => false,
+    }
+}
+/*
+ * A block reached only by a jump from the block before it is appended to it
+ */
+fn __merge_blocks(function: &mut Function) {
+    loop {
+        let mut predecessors = vec![0; function.blocks.len()];
+        for block in function.blocks.iter() {
+            for s in block.term.successors() {
+                predecessors[s] += 1;
+            }
+        }
+        let merge = (0..function.blocks.len()).find_map(|b| match function.blocks[b].term {
+            Terminator::Jump(t) if t != b && t != 0 && predecessors[t] == 1 => Some((b, t)),
+            _ => None,
+        });
+        match merge {
+            Some((b, t)) => {
+                let mut target = std::mem::replace(
+                    &mut function.blocks[t],
+                    Block {
+                        insts: vec![],
+                        term: Terminator::Return(vec![]),
+                    },
+                );
+                function.blocks[b].insts.append(&mut target.insts);
+                function.blocks[b].term = target.term;
+                //the emptied block is no longer reached
+                function.remove_unreachable();
+            }
+            None => break,
+        }
+    }
+}
+/*
+ * Removes blocks no path reaches and instructions whose result is never read
+ */
+pub fn eliminate_dead_code(function: &mut Function) {
+    function.remove_unreachable();
+    __merge_blocks(function);
+    loop {
+        let (_, live_out) = liveness(function);
+        let mut changed = false;
+        for (b, block) in function.blocks.iter_mut().enumerate() {
+            let mut live = live_out[b].clone();
+            live.extend(block.term.uses());
+            let mut kept = vec![];
+            for inst in block.insts.drain(..).rev() {
+                let defs = inst.defs();
+                if __is_pure(&inst) && defs.iter().all(|v| !live.contains(v)) {
+                    changed = true;
+                    continue;
+                }
+                for v in defs {
+                    live.remove(&v);
+                }
+                live.extend(inst.uses());
+                kept.push(inst);
+            }
+            kept.reverse();
+            block.insts = kept;
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+/*
+ * Labels of the functions an instruction or terminator refers to
+ */
+fn __referenced_labels(function: &Function) -> Vec<usize> {
+    let mut labels = vec![];
+    for block in function.blocks.iter() {
+        for inst in block.insts.iter() {
+            if let Inst::Call {
+                callee: Callee::Direct(l),
+                ..
+            } = inst
+            {
+                labels.push(*l);
+            }
+        }
+        //functions whose address is taken may be called through it
+        let operands = block.insts.iter().flat_map(|i| i.operands());
+        for o in operands.chain(block.term.operands()) {
+            if let Operand::Label(l) = o {
+                labels.push(*l);
+            }
+        }
+    }
+    labels
+}
+/*
+ * Keeps only the functions and methods reachable from main
+ * Methods are called directly by label, so a method nobody calls is dropped
+ */
+pub fn remove_unreferenced(program: &mut Program, main_label: usize) {
+    let mut reachable: HashSet<usize> = HashSet::from([main_label]);
+    let mut worklist = vec![main_label];
+    while let Some(label) = worklist.pop() {
+        if let Some(function) = program.functions.iter().find(|f| f.label == label) {
+            for l in __referenced_labels(function) {
+                if reachable.insert(l) {
+                    worklist.push(l);
+                }
+            }
+        }
+    }
+    program.functions.retain(|f| {
+        if !reachable.contains(&f.label) {
+            log::info!("Function [{}] is never called, not generated", f.name);
+        }
+        reachable.contains(&f.label)
+    });
+}
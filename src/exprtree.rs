@@ -1,4 +1,6 @@
-use crate::parserlib::ASTNodeType;
+use crate::parserlib::{
+    ASTExprType, ASTNode, ASTNodeType, GSymbol, PrimitiveType, GLOBALSYMBOLTABLE, GLOBAL_INIT_TABLE,
+};
 use std::fmt::Formatter;
 
 impl std::fmt::Display for ASTNodeType {
@@ -58,3 +60,91 @@ impl std::fmt::Display for ASTNodeType {
 //        },
 //    }
 //}
+
+/*
+ * Evaluate an expression at compile time
+ * Only literals, arithmetic and globals with constant initializers are allowed
+ */
+pub fn evaluate_const(root: &ASTNode) -> Result<ASTNode, String> {
+    match root {
+        ASTNode::INT(_) | ASTNode::STR(_) | ASTNode::Null | ASTNode::EnumConst { .. } => {
+            Ok(root.clone())
+        }
+        ASTNode::UnaryNode {
+            op: ASTNodeType::Cast,
+            exprtype: Some(t),
+            ptr,
+            ..
+        } => match (evaluate_const(ptr)?, t) {
+            (
+                ASTNode::EnumConst { etype: _, value },
+                ASTExprType::Primitive(PrimitiveType::Int),
+            ) => Ok(ASTNode::INT(value)),
+            (ASTNode::INT(value), ASTExprType::Enum(_)) => Ok(ASTNode::EnumConst {
+                etype: t.clone(),
+                value,
+            }),
+            (c, _) => Ok(c),
+        },
+        ASTNode::BinaryNode { op, lhs, rhs, .. } => {
+            let l = evaluate_const(lhs)?;
+            let r = evaluate_const(rhs)?;
+            match (l, r) {
+                (ASTNode::INT(a), ASTNode::INT(b)) => match op {
+                    ASTNodeType::Plus => Ok(ASTNode::INT(a + b)),
+                    ASTNodeType::Minus => Ok(ASTNode::INT(a - b)),
+                    ASTNodeType::Star => Ok(ASTNode::INT(a * b)),
+                    ASTNodeType::Slash | ASTNodeType::Mod if b == 0 => {
+                        Err("Division by zero in constant expression.".to_owned())
+                    }
+                    ASTNodeType::Slash => Ok(ASTNode::INT(a / b)),
+                    ASTNodeType::Mod => Ok(ASTNode::INT(a % b)),
+                    _ => Err("Operator [".to_owned()
+                        + op.to_string().as_str()
+                        + "] is not allowed in a constant expression."),
+                },
+                _ => Err("Operator [".to_owned()
+                    + op.to_string().as_str()
+                    + "] expects int operands in a constant expression."),
+            }
+        }
+        ASTNode::VAR {
+            name,
+            array_access,
+            dot_field_access,
+            arrow_field_access,
+        } => {
+            if **dot_field_access != ASTNode::Void || **arrow_field_access != ASTNode::Void {
+                return Err("Field access is not a constant expression.".to_owned());
+            }
+            let varindices = match GLOBALSYMBOLTABLE.lock().unwrap().get(name) {
+                Some(GSymbol::Var { varindices, .. }) => varindices.clone(),
+                _ => return Err("[".to_owned() + name.as_str() + "] is not a constant."),
+            };
+            if array_access.len() != varindices.len() {
+                return Err("[".to_owned() + name.as_str() + "] is not a constant.");
+            }
+            let mut position: usize = 0;
+            for (i, index) in array_access.iter().enumerate() {
+                match evaluate_const(index)? {
+                    ASTNode::INT(k) if k >= 0 && (k as usize) < varindices[i] => {
+                        position = position * varindices[i] + k as usize;
+                    }
+                    _ => {
+                        return Err("Invalid constant index for [".to_owned()
+                            + name.as_str()
+                            + "].")
+                    }
+                }
+            }
+            let git = GLOBAL_INIT_TABLE.lock().unwrap();
+            match git.get(name) {
+                Some(values) => Ok(values[position].clone()),
+                None => {
+                    Err("[".to_owned() + name.as_str() + "] is not initialized with a constant.")
+                }
+            }
+        }
+        _ => Err("Expression is not a constant.".to_owned()),
+    }
+}
@@ -0,0 +1,201 @@
+//inlining of small functions at their call sites
+use crate::ir::*;
+use crate::options::inline_threshold;
+use std::collections::HashMap;
+
+/*
+ * Instructions of the function, terminators included
+ */
+fn __size(function: &Function) -> usize {
+    function.blocks.iter().map(|b| b.insts.len() + 1).sum()
+}
+fn __calls(function: &Function, label: Option<usize>) -> bool {
+    function
+        .blocks
+        .iter()
+        .flat_map(|b| b.insts.iter())
+        .any(|i| match i {
+            Inst::Call {
+                callee: Callee::Direct(l),
+                ..
+            } => label.is_none_or(|label| *l == label),
+            Inst::Call { .. } => label.is_none(),
+            _ => false,
+        })
+}
+/*
+ * Functions marked inline are inlined unless they call themselves, others
+ * only when they call no function and are below the threshold
+ */
+fn __inlinable(function: &Function, threshold: usize) -> bool {
+    if !function.one_word_params() || __calls(function, Some(function.label)) {
+        return false;
+    }
+    match function.inline {
+        Some(inline) => inline,
+        None => !__calls(function, None) && __size(function) <= threshold,
+    }
+}
+/*
+ * Functions ordered so that callees come before their callers, the bodies
+ * copied into a caller then hold the calls already inlined into them
+ */
+fn __bottom_up(program: &Program, index: &HashMap<usize, usize>) -> Vec<usize> {
+    fn visit(
+        f: usize,
+        program: &Program,
+        index: &HashMap<usize, usize>,
+        seen: &mut Vec<bool>,
+        order: &mut Vec<usize>,
+    ) {
+        if seen[f] {
+            return;
+        }
+        seen[f] = true;
+        for inst in program.functions[f]
+            .blocks
+            .iter()
+            .flat_map(|b| b.insts.iter())
+        {
+            if let Inst::Call {
+                callee: Callee::Direct(l),
+                ..
+            } = inst
+            {
+                if let Some(c) = index.get(l) {
+                    visit(*c, program, index, seen, order);
+                }
+            }
+        }
+        order.push(f);
+    }
+    let mut seen = vec![false; program.functions.len()];
+    let mut order = vec![];
+    for f in 0..program.functions.len() {
+        visit(f, program, index, &mut seen, &mut order);
+    }
+    order
+}
+/*
+ * Replaces the call at position of block b by a copy of the callee
+ * The parameters and locals of the callee get words of their own in the
+ * frame of the caller, the arguments are stored to them, so a parameter
+ * the callee assigns never changes a variable of the caller
+ * Returns the block holding the instructions after the call
+ */
+fn __inline_call(caller: &mut Function, b: BlockId, position: usize, callee: &Function) -> BlockId {
+    let (args, rets) = match caller.blocks[b].insts.remove(position) {
+        Inst::Call { args, rets, .. } => (args, rets),
+        _ => unreachable!(),
+    };
+    let rest = caller.blocks[b].insts.split_off(position);
+    let nparams = i64::try_from(callee.params.len()).unwrap();
+    let base = caller.frame;
+    caller.frame += nparams + callee.frame;
+    let offset = |o: i64| match callee.params.iter().position(|p| *p == o) {
+        Some(i) => base + 1 + i64::try_from(i).unwrap(),
+        None => base + nparams + o,
+    };
+    for (i, arg) in args.into_iter().enumerate() {
+        let addr = caller.vregs;
+        caller.vregs += 1;
+        caller.blocks[b].insts.push(Inst::FrameAddr {
+            dst: addr,
+            offset: base + 1 + i64::try_from(i).unwrap(),
+        });
+        caller.blocks[b].insts.push(Inst::Store {
+            addr: Operand::Reg(addr),
+            src: arg,
+        });
+    }
+    let vbase = caller.vregs;
+    caller.vregs += callee.vregs;
+    let first = b + 1;
+    let after = first + callee.blocks.len();
+    let mut blocks = vec![];
+    for block in callee.blocks.iter() {
+        let mut block = block.clone();
+        for inst in block.insts.iter_mut() {
+            for o in inst.operands_mut() {
+                if let Operand::Reg(v) = o {
+                    *v += vbase;
+                }
+            }
+            for v in inst.defs_mut() {
+                *v += vbase;
+            }
+            if let Inst::FrameAddr { dst: _, offset: o } = inst {
+                *o = offset(*o);
+            }
+        }
+        for o in block.term.operands_mut() {
+            if let Operand::Reg(v) = o {
+                *v += vbase;
+            }
+        }
+        for t in block.term.successors_mut() {
+            *t += first;
+        }
+        //a return hands its values to the results of the call
+        if let Terminator::Return(values) = &block.term {
+            for (i, dst) in rets.iter().enumerate() {
+                let src = values.get(i).cloned().unwrap_or(Operand::Imm(0));
+                block.insts.push(Inst::Copy { dst: *dst, src });
+            }
+            block.term = Terminator::Jump(after);
+        }
+        blocks.push(block);
+    }
+    blocks.push(Block {
+        insts: rest,
+        term: Terminator::Return(vec![]),
+    });
+    caller.insert_blocks(first, blocks);
+    //the continuation takes over the terminator of the split block
+    caller.blocks[after].term =
+        std::mem::replace(&mut caller.blocks[b].term, Terminator::Jump(first));
+    after
+}
+/*
+ * Inlines the calls to small or inline marked functions, a function is
+ * never inlined into itself
+ */
+pub fn inline_functions(program: &mut Program) {
+    let threshold = inline_threshold();
+    let index: HashMap<usize, usize> = program
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.label, i))
+        .collect();
+    for f in __bottom_up(program, &index) {
+        let mut caller = program.functions[f].clone();
+        let mut b = 0;
+        while b < caller.blocks.len() {
+            let site = caller.blocks[b]
+                .insts
+                .iter()
+                .enumerate()
+                .find_map(|(p, inst)| match inst {
+                    Inst::Call {
+                        callee: Callee::Direct(l),
+                        ..
+                    } if *l != caller.label => index
+                        .get(l)
+                        .filter(|c| __inlinable(&program.functions[**c], threshold))
+                        .map(|c| (p, *c)),
+                    _ => None,
+                });
+            match site {
+                Some((position, c)) => {
+                    let callee = &program.functions[c];
+                    log::info!("Function [{}] inlined into [{}]", callee.name, caller.name);
+                    //the body of the callee is not searched again
+                    b = __inline_call(&mut caller, b, position, callee);
+                }
+                None => b += 1,
+            }
+        }
+        program.functions[f] = caller;
+    }
+}
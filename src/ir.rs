@@ -0,0 +1,468 @@
+//three address intermediate representation between the AST and XSM
+use std::fmt;
+
+//virtual registers are numbered from 0 in every function
+pub type VReg = usize;
+//index of a block in Function::blocks, the entry block is 0
+pub type BlockId = usize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Reg(VReg),
+    Imm(i64),
+    //string literal without the quotes, null is the empty string
+    Str(String),
+    //address of the function at a label
+    Label(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callee {
+    //label of the function
+    Direct(usize),
+    //function pointer
+    Indirect(Operand),
+}
+
+//routines of the xsm library, reached through CALL 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibFunc {
+    Read,
+    Write,
+    Alloc,
+    Free,
+    Heapset,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inst {
+    Copy {
+        dst: VReg,
+        src: Operand,
+    },
+    Binary {
+        op: BinOp,
+        dst: VReg,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    //dst = BP + offset, locals have positive offsets and parameters negative ones
+    FrameAddr {
+        dst: VReg,
+        offset: i64,
+    },
+    Load {
+        dst: VReg,
+        addr: Operand,
+    },
+    Store {
+        addr: Operand,
+        src: Operand,
+    },
+    //args are pushed in order, rets receive the return slots
+    Call {
+        callee: Callee,
+        args: Vec<Operand>,
+        rets: Vec<VReg>,
+    },
+    LibCall {
+        func: LibFunc,
+        args: Vec<Operand>,
+        dst: VReg,
+    },
+    //INT num with args on the stack
+    Syscall {
+        num: i64,
+        args: Vec<Operand>,
+        dst: VReg,
+    },
+    Breakpoint,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Jump(BlockId),
+    //nonzero cond goes to then
+    Branch {
+        cond: Operand,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    Return(Vec<Operand>),
+    //failed runtime check, writes the message and the source line then halts
+    Trap {
+        message: String,
+        line: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub term: Terminator,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub label: usize,
+    //words of locals above BP
+    pub frame: i64,
+    //frame offsets of the parameters in argument order
+    pub params: Vec<i64>,
+    //Some(true) when marked inline, Some(false) when marked noinline
+    pub inline: Option<bool>,
+    //blocks in layout order
+    pub blocks: Vec<Block>,
+    pub vregs: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub functions: Vec<Function>,
+}
+
+fn __regs(operands: &[Operand]) -> Vec<VReg> {
+    operands
+        .iter()
+        .filter_map(|o| match o {
+            Operand::Reg(v) => Some(*v),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Inst {
+    pub fn defs(&self) -> Vec<VReg> {
+        match self {
+            Inst::Copy { dst, .. }
+            | Inst::Binary { dst, .. }
+            | Inst::FrameAddr { dst, .. }
+            | Inst::Load { dst, .. }
+            | Inst::LibCall { dst, .. }
+            | Inst::Syscall { dst, .. } => vec![*dst],
+            Inst::Call { rets, .. } => rets.clone(),
+            Inst::Store { .. } | Inst::Breakpoint => vec![],
+        }
+    }
+    pub fn uses(&self) -> Vec<VReg> {
+        match self {
+            Inst::Copy { dst: _, src } => __regs(std::slice::from_ref(src)),
+            Inst::Binary { lhs, rhs, .. } => __regs(&[lhs.clone(), rhs.clone()]),
+            Inst::FrameAddr { .. } | Inst::Breakpoint => vec![],
+            Inst::Load { dst: _, addr } => __regs(std::slice::from_ref(addr)),
+            Inst::Store { addr, src } => __regs(&[addr.clone(), src.clone()]),
+            Inst::Call { callee, args, .. } => {
+                let mut uses = __regs(args);
+                if let Callee::Indirect(f) = callee {
+                    uses.extend(__regs(std::slice::from_ref(f)));
+                }
+                uses
+            }
+            Inst::LibCall { args, .. } | Inst::Syscall { args, .. } => __regs(args),
+        }
+    }
+    //operands read by the instruction
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Inst::Copy { dst: _, src } => vec![src],
+            Inst::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Inst::FrameAddr { .. } | Inst::Breakpoint => vec![],
+            Inst::Load { dst: _, addr } => vec![addr],
+            Inst::Store { addr, src } => vec![addr, src],
+            Inst::Call { callee, args, .. } => {
+                let mut operands: Vec<&Operand> = args.iter().collect();
+                if let Callee::Indirect(f) = callee {
+                    operands.push(f);
+                }
+                operands
+            }
+            Inst::LibCall { args, .. } | Inst::Syscall { args, .. } => args.iter().collect(),
+        }
+    }
+    //the same operands, for rewriting them in place
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Inst::Copy { dst: _, src } => vec![src],
+            Inst::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Inst::FrameAddr { .. } | Inst::Breakpoint => vec![],
+            Inst::Load { dst: _, addr } => vec![addr],
+            Inst::Store { addr, src } => vec![addr, src],
+            Inst::Call { callee, args, .. } => {
+                let mut operands: Vec<&mut Operand> = args.iter_mut().collect();
+                if let Callee::Indirect(f) = callee {
+                    operands.push(f);
+                }
+                operands
+            }
+            Inst::LibCall { args, .. } | Inst::Syscall { args, .. } => args.iter_mut().collect(),
+        }
+    }
+    //the same results, for renaming them in place
+    pub fn defs_mut(&mut self) -> Vec<&mut VReg> {
+        match self {
+            Inst::Copy { dst, .. }
+            | Inst::Binary { dst, .. }
+            | Inst::FrameAddr { dst, .. }
+            | Inst::Load { dst, .. }
+            | Inst::LibCall { dst, .. }
+            | Inst::Syscall { dst, .. } => vec![dst],
+            Inst::Call { rets, .. } => rets.iter_mut().collect(),
+            Inst::Store { .. } | Inst::Breakpoint => vec![],
+        }
+    }
+    //calls clobber every register that is not saved around them
+    pub fn is_call(&self) -> bool {
+        matches!(
+            self,
+            Inst::Call { .. } | Inst::LibCall { .. } | Inst::Syscall { .. }
+        )
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(b) => vec![*b],
+            Terminator::Branch {
+                cond: _,
+                then,
+                otherwise,
+            } => vec![*then, *otherwise],
+            Terminator::Return(_) | Terminator::Trap { .. } => vec![],
+        }
+    }
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(b) => vec![b],
+            Terminator::Branch {
+                cond: _,
+                then,
+                otherwise,
+            } => vec![then, otherwise],
+            Terminator::Return(_) | Terminator::Trap { .. } => vec![],
+        }
+    }
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![cond],
+            Terminator::Return(values) => values.iter().collect(),
+            Terminator::Jump(_) | Terminator::Trap { .. } => vec![],
+        }
+    }
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![cond],
+            Terminator::Return(values) => values.iter_mut().collect(),
+            Terminator::Jump(_) | Terminator::Trap { .. } => vec![],
+        }
+    }
+    pub fn uses(&self) -> Vec<VReg> {
+        match self {
+            Terminator::Branch { cond, .. } => __regs(std::slice::from_ref(cond)),
+            Terminator::Return(values) => __regs(values),
+            Terminator::Jump(_) | Terminator::Trap { .. } => vec![],
+        }
+    }
+}
+
+impl Function {
+    /*
+     * Blocks reachable from the entry block
+     */
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut stack = vec![0];
+        while let Some(b) = stack.pop() {
+            if seen[b] {
+                continue;
+            }
+            seen[b] = true;
+            stack.extend(self.blocks[b].term.successors());
+        }
+        seen
+    }
+    /*
+     * Whether every parameter takes one word, the i-th argument is then
+     * the i-th parameter
+     */
+    pub fn one_word_params(&self) -> bool {
+        self.params.windows(2).all(|w| w[1] == w[0] + 1)
+    }
+    /*
+     * Places blocks before the block at, existing targets from at on move
+     * along, the inserted blocks already use the new numbering
+     */
+    pub fn insert_blocks(&mut self, at: BlockId, blocks: Vec<Block>) {
+        let n = blocks.len();
+        for block in self.blocks.iter_mut() {
+            for t in block.term.successors_mut() {
+                if *t >= at {
+                    *t += n;
+                }
+            }
+        }
+        self.blocks.splice(at..at, blocks);
+    }
+    /*
+     * Drops the blocks no path from the entry reaches, keeping the layout order
+     */
+    pub fn remove_unreachable(&mut self) {
+        let reachable = self.reachable();
+        let mut position = vec![0; self.blocks.len()];
+        let mut next = 0;
+        for (b, r) in reachable.iter().enumerate() {
+            if *r {
+                position[b] = next;
+                next += 1;
+            }
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        for (b, mut block) in blocks.into_iter().enumerate() {
+            if !reachable[b] {
+                continue;
+            }
+            match &mut block.term {
+                Terminator::Jump(t) => *t = position[*t],
+                Terminator::Branch {
+                    cond: _,
+                    then,
+                    otherwise,
+                } => {
+                    *then = position[*then];
+                    *otherwise = position[*otherwise];
+                }
+                Terminator::Return(_) | Terminator::Trap { .. } => {}
+            }
+            self.blocks.push(block);
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Reg(v) => write!(f, "v{}", v),
+            Operand::Imm(n) => write!(f, "{}", n),
+            Operand::Str(s) => write!(f, "\"{}\"", s),
+            Operand::Label(l) => write!(f, "L{}", l),
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Gt => ">",
+            BinOp::Lt => "<",
+            BinOp::Ge => ">=",
+            BinOp::Le => "<=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+fn __list<T: fmt::Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inst::Copy { dst, src } => write!(f, "v{} = {}", dst, src),
+            Inst::Binary { op, dst, lhs, rhs } => write!(f, "v{} = {} {} {}", dst, lhs, op, rhs),
+            Inst::FrameAddr { dst, offset } => write!(f, "v{} = &frame[{}]", dst, offset),
+            Inst::Load { dst, addr } => write!(f, "v{} = [{}]", dst, addr),
+            Inst::Store { addr, src } => write!(f, "[{}] = {}", addr, src),
+            Inst::Call { callee, args, rets } => {
+                if !rets.is_empty() {
+                    let rets: Vec<String> = rets.iter().map(|r| format!("v{}", r)).collect();
+                    write!(f, "{} = ", rets.join(", "))?;
+                }
+                match callee {
+                    Callee::Direct(l) => write!(f, "call L{}({})", l, __list(args)),
+                    Callee::Indirect(p) => write!(f, "call *{}({})", p, __list(args)),
+                }
+            }
+            Inst::LibCall { func, args, dst } => {
+                write!(f, "v{} = lib {:?}({})", dst, func, __list(args))
+            }
+            Inst::Syscall { num, args, dst } => {
+                write!(f, "v{} = int {}({})", dst, num, __list(args))
+            }
+            Inst::Breakpoint => write!(f, "brkp"),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Jump(b) => write!(f, "jump bb{}", b),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => write!(f, "branch {}, bb{}, bb{}", cond, then, otherwise),
+            Terminator::Return(values) if values.is_empty() => write!(f, "return"),
+            Terminator::Return(values) => write!(f, "return {}", __list(values)),
+            Terminator::Trap { message, line } => write!(f, "trap \"{}\" line {}", message, line),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "function {} L{} frame {}",
+            self.name, self.label, self.frame
+        )?;
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "bb{}:", id)?;
+            for inst in block.insts.iter() {
+                writeln!(f, "    {}", inst)?;
+            }
+            writeln!(f, "    {}", block.term)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
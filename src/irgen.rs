@@ -0,0 +1,974 @@
+//translation of the AST into the three address IR
+use crate::codegen::*;
+use crate::ir::*;
+use crate::options::*;
+use crate::parserlib::*;
+use crate::validation::*;
+use std::collections::LinkedList;
+
+struct Builder {
+    insts: Vec<Vec<Inst>>,
+    terms: Vec<Option<Terminator>>,
+    //blocks in the order they were started
+    layout: Vec<BlockId>,
+    //None after a jump or return, code emitted then goes to a new unreachable block
+    current: Option<BlockId>,
+    vregs: usize,
+    //(continue, break) targets of the enclosing loops
+    loops: Vec<(BlockId, BlockId)>,
+    //line of the statement being translated, reported by runtime checks
+    line: usize,
+}
+
+/*
+ * Internally, functions are have different key value
+ */
+fn __get_table_id(fname: &str) -> String {
+    let cname = CLASSNAME.lock().unwrap();
+    fname.to_owned() + "#" + cname.as_str()
+}
+/*
+ * Get the size of the local declaration
+ */
+fn __get_function_storage(fname: &str) -> i64 {
+    let ft = FUNCTION_TABLE.lock().unwrap();
+    let mut max_size = 0;
+    if let Some(entry) = ft.get(&__get_table_id(fname)) {
+        for (
+            _k,
+            LSymbol::Var {
+                vartype,
+                varid,
+                varindices,
+            },
+        ) in entry.iter()
+        {
+            //storage ends at the last word of the variable
+            let mut size = vartype.size().unwrap();
+            for i in varindices.iter() {
+                size *= i;
+            }
+            max_size = max_size.max(varid + i64::try_from(size).unwrap() - 1);
+        }
+        max_size
+    } else {
+        0
+    }
+}
+/*
+ * Size of one element of an array, given the type left after indexing
+ * and the number of dimensions that were not indexed
+ */
+fn __element_size(currtype: &ASTExprType, unindexed: usize) -> usize {
+    let mut elemtype = currtype.clone();
+    for _ in 0..unindexed {
+        elemtype = elemtype.derefr().unwrap();
+    }
+    elemtype.size().unwrap()
+}
+/*
+ * Size of the pointee if the expression is a pointer
+ */
+fn __pointee_size(expr: &ASTNode) -> Option<usize> {
+    match expr.clone().getexprtype() {
+        Some(ASTExprType::Pointer(p)) => Some(p.size().unwrap()),
+        _ => None,
+    }
+}
+
+impl Builder {
+    fn new() -> Self {
+        let mut builder = Builder {
+            insts: vec![],
+            terms: vec![],
+            layout: vec![],
+            current: None,
+            vregs: 0,
+            loops: vec![],
+            line: 0,
+        };
+        let entry = builder.new_block();
+        builder.start(entry);
+        builder
+    }
+    fn new_block(&mut self) -> BlockId {
+        self.insts.push(vec![]);
+        self.terms.push(None);
+        self.insts.len() - 1
+    }
+    fn new_vreg(&mut self) -> VReg {
+        self.vregs += 1;
+        self.vregs - 1
+    }
+    /*
+     * Continue in block, an unfinished current block falls through to it
+     */
+    fn start(&mut self, block: BlockId) {
+        self.terminate(Terminator::Jump(block));
+        self.layout.push(block);
+        self.current = Some(block);
+    }
+    fn terminate(&mut self, term: Terminator) {
+        if let Some(b) = self.current.take() {
+            self.terms[b] = Some(term);
+        }
+    }
+    fn emit(&mut self, inst: Inst) {
+        if self.current.is_none() {
+            let b = self.new_block();
+            self.start(b);
+        }
+        self.insts[self.current.unwrap()].push(inst);
+    }
+    fn binary(&mut self, op: BinOp, lhs: Operand, rhs: Operand) -> Operand {
+        let dst = self.new_vreg();
+        self.emit(Inst::Binary { op, dst, lhs, rhs });
+        Operand::Reg(dst)
+    }
+    fn load(&mut self, addr: Operand) -> Operand {
+        let dst = self.new_vreg();
+        self.emit(Inst::Load { dst, addr });
+        Operand::Reg(dst)
+    }
+    fn call(&mut self, callee: Callee, args: Vec<Operand>) -> Operand {
+        let dst = self.new_vreg();
+        self.emit(Inst::Call {
+            callee,
+            args,
+            rets: vec![dst],
+        });
+        Operand::Reg(dst)
+    }
+    fn lib_call(&mut self, func: LibFunc, args: Vec<Operand>) -> Operand {
+        let dst = self.new_vreg();
+        self.emit(Inst::LibCall { func, args, dst });
+        Operand::Reg(dst)
+    }
+    /*
+     * Blocks are renumbered in layout order, blocks never started are dropped
+     */
+    fn finish(mut self, name: String, label: usize, frame: i64, params: Vec<i64>) -> Function {
+        self.terminate(Terminator::Return(vec![]));
+        let mut position = vec![usize::MAX; self.insts.len()];
+        for (i, b) in self.layout.iter().enumerate() {
+            position[*b] = i;
+        }
+        let renumber = |b: BlockId| position[b];
+        let mut blocks = vec![];
+        for b in self.layout.iter() {
+            let term = match self.terms[*b].take().unwrap() {
+                Terminator::Jump(t) => Terminator::Jump(renumber(t)),
+                Terminator::Branch {
+                    cond,
+                    then,
+                    otherwise,
+                } => Terminator::Branch {
+                    cond,
+                    then: renumber(then),
+                    otherwise: renumber(otherwise),
+                },
+                term => term,
+            };
+            blocks.push(Block {
+                insts: std::mem::take(&mut self.insts[*b]),
+                term,
+            });
+        }
+        Function {
+            name,
+            label,
+            frame,
+            params,
+            inline: None,
+            blocks,
+            vregs: self.vregs,
+        }
+    }
+    /*
+     * Runtime check of --checked mode, cond is nonzero when the check passed
+     */
+    fn check(&mut self, cond: Operand, message: &str) {
+        let ok = self.new_block();
+        let fail = self.new_block();
+        self.terminate(Terminator::Branch {
+            cond,
+            then: ok,
+            otherwise: fail,
+        });
+        self.start(fail);
+        self.terminate(Terminator::Trap {
+            message: message.to_owned(),
+            line: self.line,
+        });
+        self.start(ok);
+    }
+    /*
+     * Index must lie in [0, dim), dimensions of pointers are unknown (0)
+     */
+    fn bounds_check(&mut self, index: &Operand, dim: usize) {
+        if !runtime_checks() || dim == 0 {
+            return;
+        }
+        let below = self.binary(
+            BinOp::Lt,
+            index.clone(),
+            Operand::Imm(i64::try_from(dim).unwrap()),
+        );
+        let above = self.binary(BinOp::Gt, index.clone(), Operand::Imm(-1));
+        let inside = self.binary(BinOp::Mul, below, above);
+        self.check(inside, "Out of bounds");
+    }
+    /*
+     * ptr is an address about to be loaded from or stored to
+     */
+    fn null_check(&mut self, ptr: &Operand) {
+        if !runtime_checks() {
+            return;
+        }
+        let nonnull = self.binary(BinOp::Ne, ptr.clone(), Operand::Str(String::default()));
+        self.check(nonnull, "Null pointer");
+    }
+    fn alloc(&mut self, size: Operand) -> Operand {
+        match get_allocator() {
+            Allocator::Library => self.lib_call(LibFunc::Alloc, vec![size]),
+            Allocator::Builtin => {
+                let (alloc_label, _) = *ALLOCATOR_LABELS.lock().unwrap();
+                self.call(Callee::Direct(alloc_label), vec![size])
+            }
+        }
+    }
+    fn free(&mut self, ptr: Operand) -> Operand {
+        match get_allocator() {
+            Allocator::Library => self.lib_call(LibFunc::Free, vec![ptr]),
+            Allocator::Builtin => {
+                let (_, free_label) = *ALLOCATOR_LABELS.lock().unwrap();
+                self.call(Callee::Direct(free_label), vec![ptr])
+            }
+        }
+    }
+    fn heapset(&mut self) -> Operand {
+        match get_allocator() {
+            Allocator::Library => self.lib_call(LibFunc::Heapset, vec![]),
+            //the builtin heap keeps the next unused address and the list of freed blocks
+            Allocator::Builtin => {
+                self.emit(Inst::Store {
+                    addr: Operand::Imm(HEAP_START),
+                    src: Operand::Imm(HEAP_START + 2),
+                });
+                self.emit(Inst::Store {
+                    addr: Operand::Imm(HEAP_START + 1),
+                    src: Operand::Imm(0),
+                });
+                Operand::Imm(0)
+            }
+        }
+    }
+    fn args(&mut self, arglist: &LinkedList<ASTNode>) -> Vec<Operand> {
+        arglist.iter().map(|arg| self.gen(arg, false)).collect()
+    }
+    /*
+     * Address of a local, a parameter or a global
+     */
+    fn var_base(&mut self, vname: &String) -> Operand {
+        let local = LOCALSYMBOLTABLE.lock().unwrap().get(vname).cloned();
+        if let Some(LSymbol::Var {
+            vartype: _,
+            varid,
+            varindices,
+        }) = local
+        {
+            let dst = self.new_vreg();
+            self.emit(Inst::FrameAddr { dst, offset: varid });
+            //array parameters hold the base address of the array
+            if varid < 0 && !varindices.is_empty() {
+                return self.load(Operand::Reg(dst));
+            }
+            return Operand::Reg(dst);
+        }
+        let global = GLOBALSYMBOLTABLE.lock().unwrap().get(vname).cloned();
+        if let Some(GSymbol::Var { varid, .. }) = global {
+            return Operand::Imm(XSM_STACK_OFFSET + i64::try_from(varid).unwrap());
+        }
+        exit_on_err("Variable not declared".to_string());
+        unreachable!()
+    }
+    /*
+     * Adds the offset of an array access to the address in base
+     * Each index is scaled by the size of the dimensions after it
+     */
+    fn array_offset(
+        &mut self,
+        base: Operand,
+        indices: &[Box<ASTNode>],
+        dims: &[usize],
+        elemsize: usize,
+    ) -> Operand {
+        let mut addr = base;
+        for i in 0..indices.len() {
+            let mut offset = self.gen(&indices[i], false);
+            self.bounds_check(&offset, dims[i]);
+            let mut stride = elemsize;
+            for d in dims.iter().skip(i + 1) {
+                stride *= d;
+            }
+            if stride != 1 {
+                offset = self.binary(
+                    BinOp::Mul,
+                    offset,
+                    Operand::Imm(i64::try_from(stride).unwrap()),
+                );
+            }
+            addr = self.binary(BinOp::Add, addr, offset);
+        }
+        addr
+    }
+    fn method_call(
+        &mut self,
+        selfaddr: Operand,
+        classname: &String,
+        fname: &String,
+        arglist: &LinkedList<ASTNode>,
+    ) -> Operand {
+        let mut args = vec![selfaddr];
+        args.extend(self.args(arglist));
+        self.call(Callee::Direct(get_function_label(fname, classname)), args)
+    }
+    /*
+     * Address of the variable, or its value when it is not refr and can be loaded
+     */
+    fn var(
+        &mut self,
+        name: &String,
+        indices: &[Box<ASTNode>],
+        dot_field_access: &ASTNode,
+        arrow_field_access: &ASTNode,
+        refr: bool,
+    ) -> Operand {
+        let varindices = getvarindices(name).expect("Error in variable tables");
+        let mut addr = self.var_base(name);
+
+        let mut dotptr = dot_field_access;
+        let mut arrowptr = arrow_field_access;
+        let mut currtype = getvartype(name).unwrap();
+        for _ in 0..indices.len() {
+            currtype = currtype.derefr().unwrap();
+        }
+        let mut dims = varindices.clone();
+        if is_pointer_index(name, indices.len()) {
+            //index from the address held in the pointer
+            addr = self.load(addr);
+            self.null_check(&addr);
+            dims = vec![0];
+        }
+        addr = self.array_offset(
+            addr,
+            indices,
+            &dims,
+            __element_size(&currtype, dims.len() - indices.len()),
+        );
+        //only a fully indexed array or a scalar can be loaded
+        let mut loadable = dims.len() == indices.len();
+        loop {
+            if dotptr == &ASTNode::Void && arrowptr == &ASTNode::Void {
+                break;
+            }
+            match dotptr {
+                ASTNode::VAR {
+                    name: nname,
+                    array_access,
+                    dot_field_access,
+                    arrow_field_access,
+                } => {
+                    let field_offset = currtype.get_field_id(nname).unwrap();
+                    let field_indices = currtype.get_field_indices(nname).unwrap();
+                    currtype = currtype.get_field_type(nname).unwrap();
+                    addr = self.binary(
+                        BinOp::Add,
+                        addr,
+                        Operand::Imm(i64::try_from(field_offset).unwrap()),
+                    );
+                    for _ in 0..array_access.len() {
+                        currtype = currtype.derefr().unwrap();
+                    }
+                    addr = self.array_offset(
+                        addr,
+                        array_access,
+                        &field_indices,
+                        __element_size(&currtype, field_indices.len() - array_access.len()),
+                    );
+                    loadable = field_indices.len() == array_access.len();
+                    dotptr = &**dot_field_access;
+                    arrowptr = &**arrow_field_access;
+                    continue;
+                }
+                ASTNode::FuncCallNode { fname, arglist } => {
+                    let classname = currtype.get_type_name().unwrap();
+                    return self.method_call(addr, &classname, fname, arglist);
+                }
+                ASTNode::Void => {}
+                _ => {
+                    unreachable!();
+                }
+            }
+            match arrowptr {
+                ASTNode::VAR {
+                    name: nname,
+                    array_access,
+                    dot_field_access,
+                    arrow_field_access,
+                } => {
+                    if let ASTExprType::Pointer(etype) = &currtype {
+                        addr = self.load(addr);
+                        self.null_check(&addr);
+                        let field_offset = etype.get_field_id(nname).unwrap();
+                        let field_indices = etype.get_field_indices(nname).unwrap();
+                        currtype = etype.get_field_type(nname).unwrap();
+                        addr = self.binary(
+                            BinOp::Add,
+                            addr,
+                            Operand::Imm(i64::try_from(field_offset).unwrap()),
+                        );
+                        for _ in 0..array_access.len() {
+                            currtype = currtype.derefr().unwrap();
+                        }
+                        addr = self.array_offset(
+                            addr,
+                            array_access,
+                            &field_indices,
+                            __element_size(&currtype, field_indices.len() - array_access.len()),
+                        );
+                        loadable = field_indices.len() == array_access.len();
+                        dotptr = &**dot_field_access;
+                        arrowptr = &**arrow_field_access;
+                        continue;
+                    }
+                    unreachable!();
+                }
+                ASTNode::FuncCallNode { fname, arglist } => {
+                    if let ASTExprType::Pointer(etype) = &currtype {
+                        addr = self.load(addr);
+                        self.null_check(&addr);
+                        let classname = etype.get_type_name().unwrap();
+                        return self.method_call(addr, &classname, fname, arglist);
+                    }
+                    unreachable!();
+                }
+                _ => {
+                    unreachable!();
+                }
+            }
+        }
+        if !refr && loadable {
+            return self.load(addr);
+        }
+        addr
+    }
+    fn binary_node(&mut self, op: &ASTNodeType, lhs: &ASTNode, rhs: &ASTNode) -> Operand {
+        let binop = match op {
+            ASTNodeType::Plus => BinOp::Add,
+            ASTNodeType::Minus => BinOp::Sub,
+            ASTNodeType::Star => BinOp::Mul,
+            ASTNodeType::Slash => BinOp::Div,
+            ASTNodeType::Mod => BinOp::Mod,
+            ASTNodeType::Gt => BinOp::Gt,
+            ASTNodeType::Lt => BinOp::Lt,
+            ASTNodeType::Gte => BinOp::Ge,
+            ASTNodeType::Lte => BinOp::Le,
+            ASTNodeType::Ee => BinOp::Eq,
+            ASTNodeType::Ne => BinOp::Ne,
+            _ => unreachable!(),
+        };
+        let mut left = self.gen(lhs, false);
+        let mut right = self.gen(rhs, false);
+        //the int operand of pointer +- is scaled by the size of the pointee
+        if binop == BinOp::Add || binop == BinOp::Sub {
+            match (__pointee_size(lhs), __pointee_size(rhs)) {
+                (Some(size), None) if size != 1 => {
+                    right = self.binary(BinOp::Mul, right, Operand::Imm(size as i64))
+                }
+                (None, Some(size)) if size != 1 => {
+                    left = self.binary(BinOp::Mul, left, Operand::Imm(size as i64))
+                }
+                _ => {}
+            }
+        }
+        if (binop == BinOp::Div || binop == BinOp::Mod)
+            && runtime_checks()
+            && !matches!(right, Operand::Imm(n) if n != 0)
+        {
+            self.check(right.clone(), "Division by zero");
+        }
+        let result = self.binary(binop, left, right);
+        //difference of two pointers is in elements
+        if binop == BinOp::Sub {
+            if let (Some(size), Some(_)) = (__pointee_size(lhs), __pointee_size(rhs)) {
+                if size != 1 {
+                    return self.binary(BinOp::Div, result, Operand::Imm(size as i64));
+                }
+            }
+        }
+        result
+    }
+    fn unary_node(
+        &mut self,
+        op: &ASTNodeType,
+        exprtype: &Option<ASTExprType>,
+        ptr: &ASTNode,
+        depth: &Option<usize>,
+        refr: bool,
+    ) -> Operand {
+        match op {
+            ASTNodeType::Alloc => {
+                //the library allocator always hands out 8 words
+                let size = match get_allocator() {
+                    Allocator::Library => 8,
+                    Allocator::Builtin => match ptr.clone().getexprtype() {
+                        Some(ASTExprType::Pointer(t)) => get_alloc_size(&t),
+                        _ => 8,
+                    },
+                };
+                let mptr = self.alloc(Operand::Imm(i64::try_from(size).unwrap()));
+                let p = self.gen(ptr, true);
+                self.emit(Inst::Store { addr: p, src: mptr });
+                Operand::Imm(0)
+            }
+            ASTNodeType::AllocArray => {
+                let mut size = self.gen(ptr, false);
+                if let Some(ASTExprType::Pointer(t)) = exprtype {
+                    let elemsize = t.size().unwrap();
+                    if elemsize != 1 {
+                        size = self.binary(BinOp::Mul, size, Operand::Imm(elemsize as i64));
+                    }
+                }
+                if get_allocator() == Allocator::Builtin {
+                    return self.alloc(size);
+                }
+                //more than 8 words cannot come from the library, trap when checked
+                if runtime_checks() {
+                    let fits = self.binary(BinOp::Le, size.clone(), Operand::Imm(8));
+                    self.check(
+                        fits,
+                        "Array larger than the 8 words of the library allocator",
+                    );
+                    return self.alloc(size);
+                }
+                //and otherwise give back -1 like the library when it is out of heap
+                let mptr = self.new_vreg();
+                let (small, big, end) = (self.new_block(), self.new_block(), self.new_block());
+                let toobig = self.binary(BinOp::Lt, Operand::Imm(8), size.clone());
+                self.terminate(Terminator::Branch {
+                    cond: toobig,
+                    then: big,
+                    otherwise: small,
+                });
+                self.start(small);
+                self.emit(Inst::LibCall {
+                    func: LibFunc::Alloc,
+                    args: vec![size],
+                    dst: mptr,
+                });
+                self.terminate(Terminator::Jump(end));
+                self.start(big);
+                self.emit(Inst::Copy {
+                    dst: mptr,
+                    src: Operand::Imm(-1),
+                });
+                self.start(end);
+                Operand::Reg(mptr)
+            }
+            ASTNodeType::Free => {
+                let p = self.gen(ptr, refr);
+                self.free(p)
+            }
+            ASTNodeType::Initialize => self.heapset(),
+            ASTNodeType::Read => {
+                let addr = self.gen(ptr, true);
+                self.lib_call(LibFunc::Read, vec![Operand::Imm(-1), addr])
+            }
+            ASTNodeType::Write => {
+                let value = self.gen(ptr, false);
+                self.lib_call(LibFunc::Write, vec![Operand::Imm(-2), value])
+            }
+            ASTNodeType::Ref => match ptr {
+                ASTNode::VAR { .. } => self.gen(ptr, true),
+                _ => unreachable!(),
+            },
+            //the value is kept as it is, only its type changes
+            ASTNodeType::Cast => self.gen(ptr, false),
+            ASTNodeType::Deref => match ptr {
+                ASTNode::VAR { .. } => {
+                    let mut addr = self.gen(ptr, refr);
+                    for _i in 0..depth.unwrap() {
+                        //as a target the first load reads the variable itself
+                        if !refr {
+                            self.null_check(&addr);
+                        }
+                        addr = self.load(addr);
+                        if refr {
+                            self.null_check(&addr);
+                        }
+                    }
+                    addr
+                }
+                _ => {
+                    //the expression already evaluates to an address
+                    let mut addr = self.gen(ptr, false);
+                    let loads = if refr {
+                        depth.unwrap() - 1
+                    } else {
+                        depth.unwrap()
+                    };
+                    for _i in 0..loads {
+                        self.null_check(&addr);
+                        addr = self.load(addr);
+                    }
+                    if refr {
+                        self.null_check(&addr);
+                    }
+                    addr
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+    /*
+     * Translates a statement or an expression, expressions give back their value
+     * or their address when refr is set
+     */
+    fn gen(&mut self, root: &ASTNode, refr: bool) -> Operand {
+        match root {
+            ASTNode::ErrorNode { err } => {
+                let err: String = match err {
+                    ASTError::TypeError(s) => s.to_owned(),
+                };
+                exit_on_err(err);
+                unreachable!()
+            }
+            //tuples only appear inside return statements
+            ASTNode::TupleNode { elems: _ } => unreachable!(),
+            ASTNode::StmtNode { pos, stmt } => {
+                self.line = pos.0;
+                self.gen(stmt, refr)
+            }
+            ASTNode::BreakpointNode => {
+                self.emit(Inst::Breakpoint);
+                Operand::Imm(0)
+            }
+            //string literals keep their quotes in the AST
+            ASTNode::STR(s) => Operand::Str(s[1..s.len() - 1].to_owned()),
+            ASTNode::EnumConst { etype: _, value } => Operand::Imm(*value),
+            ASTNode::INT(n) => Operand::Imm(*n),
+            ASTNode::Null => Operand::Str(String::default()),
+            ASTNode::VAR {
+                name,
+                array_access,
+                dot_field_access,
+                arrow_field_access,
+            } => self.var(
+                name,
+                array_access,
+                dot_field_access,
+                arrow_field_access,
+                refr,
+            ),
+            ASTNode::BinaryNode {
+                op: ASTNodeType::Connector,
+                exprtype: _,
+                lhs,
+                rhs,
+            } => {
+                self.gen(lhs, false);
+                self.gen(rhs, false);
+                Operand::Imm(0)
+            }
+            ASTNode::BinaryNode {
+                op: ASTNodeType::Equals,
+                exprtype: _,
+                lhs,
+                rhs,
+            } => {
+                let left = self.gen(lhs, true);
+                //structs are assigned by value, word by word
+                if let Some(ASTExprType::Struct(s)) = (**lhs).clone().getexprtype() {
+                    let right = self.gen(rhs, true);
+                    for i in 0..s.size {
+                        let (mut to, mut from) = (left.clone(), right.clone());
+                        if i != 0 {
+                            to = self.binary(BinOp::Add, to, Operand::Imm(i as i64));
+                            from = self.binary(BinOp::Add, from, Operand::Imm(i as i64));
+                        }
+                        let word = self.load(from);
+                        self.emit(Inst::Store {
+                            addr: to,
+                            src: word,
+                        });
+                    }
+                    return Operand::Imm(0);
+                }
+                let right = self.gen(rhs, false);
+                self.emit(Inst::Store {
+                    addr: left,
+                    src: right,
+                });
+                Operand::Imm(0)
+            }
+            ASTNode::BinaryNode {
+                op,
+                exprtype: _,
+                lhs,
+                rhs,
+            } => self.binary_node(op, lhs, rhs),
+            ASTNode::UnaryNode {
+                op,
+                exprtype,
+                ptr,
+                depth,
+            } => self.unary_node(op, exprtype, ptr, depth, refr),
+            ASTNode::StdFuncCallNode { func, arglist } => match func {
+                STDLibFunction::Syscall => {
+                    //the second argument is the interrupt number
+                    let mut args = vec![];
+                    let mut interruptval = 0;
+                    for (c, i) in arglist.iter().enumerate() {
+                        if c != 1 {
+                            args.push(self.gen(i, false));
+                        } else if let ASTNode::INT(p) = i {
+                            interruptval = *p;
+                        } else {
+                            unreachable!()
+                        }
+                    }
+                    let dst = self.new_vreg();
+                    self.emit(Inst::Syscall {
+                        num: interruptval,
+                        args,
+                        dst,
+                    });
+                    Operand::Reg(dst)
+                }
+                STDLibFunction::Getaddr => {
+                    let addr = self.gen(arglist.front().unwrap(), false);
+                    self.load(addr)
+                }
+                STDLibFunction::Setaddr => {
+                    let args = self.args(arglist);
+                    self.emit(Inst::Store {
+                        addr: args[0].clone(),
+                        src: args[1].clone(),
+                    });
+                    Operand::Imm(0)
+                }
+                _ => unreachable!(),
+            },
+            ASTNode::FuncCallNode { fname, arglist } => {
+                let args = self.args(arglist);
+                let flabel = get_function_label(fname, &String::default());
+                if flabel == LABEL_NOT_FOUND {
+                    exit_on_err("Function not declared".to_string());
+                }
+                self.call(Callee::Direct(flabel), args)
+            }
+            //the linker replaces the label with the function address
+            ASTNode::FuncRefNode { fname } => {
+                Operand::Label(get_function_label(fname, &String::default()))
+            }
+            ASTNode::IndirectCallNode { fptr, arglist } => {
+                let args = self.args(arglist);
+                let fptr = self.gen(fptr, false);
+                self.call(Callee::Indirect(fptr), args)
+            }
+            ASTNode::TupleAssgNode { vars, call } => {
+                if let ASTNode::FuncCallNode { fname, arglist } = &**call {
+                    let args = self.args(arglist);
+                    //one return slot for every value
+                    let rets: Vec<VReg> = vars.iter().map(|_| self.new_vreg()).collect();
+                    self.emit(Inst::Call {
+                        callee: Callee::Direct(get_function_label(fname, &String::default())),
+                        args,
+                        rets: rets.clone(),
+                    });
+                    for (var, ret) in vars.iter().zip(rets) {
+                        let addr = self.gen(var, true);
+                        self.emit(Inst::Store {
+                            addr,
+                            src: Operand::Reg(ret),
+                        });
+                    }
+                }
+                Operand::Imm(0)
+            }
+            ASTNode::ReturnNode { expr } => {
+                //the i-th value of a tuple goes to the i-th return slot
+                let values = match &**expr {
+                    ASTNode::TupleNode { elems } => elems.iter().collect(),
+                    ASTNode::Void => vec![],
+                    _ => vec![&**expr],
+                };
+                let values = values.into_iter().map(|v| self.gen(v, refr)).collect();
+                self.terminate(Terminator::Return(values));
+                Operand::Imm(0)
+            }
+            ASTNode::IfElseNode { expr, xif, xelse } => {
+                let cond = self.gen(expr, false);
+                let (then, otherwise, end) = (self.new_block(), self.new_block(), self.new_block());
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then,
+                    otherwise,
+                });
+                self.start(then);
+                self.gen(xif, false);
+                self.terminate(Terminator::Jump(end));
+                self.start(otherwise);
+                self.gen(xelse, false);
+                self.start(end);
+                Operand::Imm(0)
+            }
+            ASTNode::IfNode { expr, xif } => {
+                let cond = self.gen(expr, false);
+                let (then, end) = (self.new_block(), self.new_block());
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then,
+                    otherwise: end,
+                });
+                self.start(then);
+                self.gen(xif, false);
+                self.start(end);
+                Operand::Imm(0)
+            }
+            ASTNode::WhileNode { expr, xdo } => {
+                let header = self.new_block();
+                self.start(header);
+                let cond = self.gen(expr, false);
+                let (body, end) = (self.new_block(), self.new_block());
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then: body,
+                    otherwise: end,
+                });
+                self.loops.push((header, end));
+                self.start(body);
+                self.gen(xdo, false);
+                self.terminate(Terminator::Jump(header));
+                self.loops.pop();
+                self.start(end);
+                Operand::Imm(0)
+            }
+            //every case value is compared in turn, break leaves the enclosing loop
+            ASTNode::SwitchNode {
+                expr,
+                cases,
+                default,
+            } => {
+                let value = self.gen(expr, false);
+                let bodies: Vec<BlockId> = cases.iter().map(|_| self.new_block()).collect();
+                let (ldefault, end) = (self.new_block(), self.new_block());
+                for (i, (case, _)) in cases.iter().enumerate() {
+                    let case = self.gen(case, false);
+                    let matched = self.binary(BinOp::Eq, case, value.clone());
+                    let next = self.new_block();
+                    self.terminate(Terminator::Branch {
+                        cond: matched,
+                        then: bodies[i],
+                        otherwise: next,
+                    });
+                    self.start(next);
+                }
+                self.terminate(Terminator::Jump(ldefault));
+                for (i, (_, body)) in cases.iter().enumerate() {
+                    self.start(bodies[i]);
+                    self.gen(body, false);
+                    self.terminate(Terminator::Jump(end));
+                }
+                self.start(ldefault);
+                self.gen(default, false);
+                self.start(end);
+                Operand::Imm(0)
+            }
+            ASTNode::BreakNode => {
+                let (_, end) = *self.loops.last().unwrap();
+                self.terminate(Terminator::Jump(end));
+                Operand::Imm(0)
+            }
+            ASTNode::ContinueNode => {
+                let (header, _) = *self.loops.last().unwrap();
+                self.terminate(Terminator::Jump(header));
+                Operand::Imm(0)
+            }
+            ASTNode::Void => Operand::Imm(0),
+            ASTNode::ClassNode { .. } | ASTNode::FuncDefNode { .. } | ASTNode::MainNode { .. } => {
+                unreachable!()
+            }
+        }
+    }
+}
+/*
+ * Translates the body of a function with the local symbol table of key
+ */
+fn __build_function(name: &str, key: &str, label: usize, body: &ASTNode) -> Function {
+    let ft = FUNCTION_TABLE.lock().unwrap();
+    let local_table = ft.get(key).cloned().unwrap_or_default();
+    std::mem::drop(ft);
+    //parameters sit below BP, the first one lowest
+    let mut params: Vec<i64> = local_table
+        .values()
+        .map(|LSymbol::Var { varid, .. }| *varid)
+        .filter(|varid| *varid < 0)
+        .collect();
+    params.sort();
+    *LOCALSYMBOLTABLE.lock().unwrap() = local_table;
+    let mut builder = Builder::new();
+    builder.gen(body, false);
+    let mut function = builder.finish(name.to_owned(), label, __get_function_storage(name), params);
+    function.inline = INLINE_HINTS.lock().unwrap().get(key).copied();
+    function
+}
+fn __build(root: &ASTNode, program: &mut Program) {
+    match root {
+        ASTNode::BinaryNode {
+            op: ASTNodeType::Connector,
+            exprtype: _,
+            lhs,
+            rhs,
+        } => {
+            __build(lhs, program);
+            __build(rhs, program);
+        }
+        ASTNode::ClassNode { cname, methods } => {
+            *CLASSNAME.lock().unwrap() = cname.clone();
+            for method in methods.iter() {
+                __build(method, program);
+            }
+            *CLASSNAME.lock().unwrap() = "".to_owned();
+        }
+        //void functions return when control reaches the end of the body
+        ASTNode::FuncDefNode {
+            fname,
+            ret_type: _,
+            paramlist: _,
+            body,
+        } => {
+            let cname = CLASSNAME.lock().unwrap().clone();
+            let label = get_function_label(fname, &cname);
+            let function = __build_function(fname, __get_table_id(fname).as_str(), label, body);
+            program.functions.push(function);
+        }
+        ASTNode::MainNode { body } => {
+            let main = "main".to_owned();
+            let label = get_function_label(&main, &String::default());
+            program
+                .functions
+                .push(__build_function(&main, "main#", label, body));
+        }
+        _ => {}
+    }
+}
+/*
+ * IR of every function of the program, methods and main included
+ */
+pub fn build_program(root: &ASTNode) -> Program {
+    let mut program = Program::default();
+    __build(root, &mut program);
+    program
+}
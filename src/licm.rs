@@ -0,0 +1,212 @@
+//loop invariant code motion over the IR
+use crate::ir::*;
+use crate::regalloc::liveness;
+use std::collections::HashSet;
+
+/*
+ * Dominators of every block, iterated to a fixed point
+ */
+fn __dominators(function: &Function) -> Vec<HashSet<BlockId>> {
+    let n = function.blocks.len();
+    let mut predecessors: Vec<Vec<BlockId>> = vec![vec![]; n];
+    for (b, block) in function.blocks.iter().enumerate() {
+        for s in block.term.successors() {
+            predecessors[s].push(b);
+        }
+    }
+    let all: HashSet<BlockId> = (0..n).collect();
+    let mut dominators: Vec<HashSet<BlockId>> = vec![all; n];
+    dominators[0] = HashSet::from([0]);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in 1..n {
+            let mut dom = predecessors[b]
+                .iter()
+                .map(|p| dominators[*p].clone())
+                .reduce(|a, d| a.intersection(&d).copied().collect())
+                .unwrap_or_default();
+            dom.insert(b);
+            if dom != dominators[b] {
+                dominators[b] = dom;
+                changed = true;
+            }
+        }
+    }
+    dominators
+}
+/*
+ * Natural loops as (header, blocks), the loops of back edges to the same
+ * header are merged
+ */
+fn __loops(function: &Function) -> Vec<(BlockId, HashSet<BlockId>)> {
+    let reachable = function.reachable();
+    let dominators = __dominators(function);
+    let mut predecessors: Vec<Vec<BlockId>> = vec![vec![]; function.blocks.len()];
+    for (b, block) in function.blocks.iter().enumerate() {
+        for s in block.term.successors() {
+            predecessors[s].push(b);
+        }
+    }
+    let mut loops: Vec<(BlockId, HashSet<BlockId>)> = vec![];
+    for (t, block) in function.blocks.iter().enumerate() {
+        if !reachable[t] {
+            continue;
+        }
+        for h in block.term.successors() {
+            if !dominators[t].contains(&h) {
+                continue;
+            }
+            //blocks reaching the back edge without passing the header
+            let mut body = HashSet::from([h]);
+            let mut stack = vec![t];
+            while let Some(b) = stack.pop() {
+                if body.insert(b) {
+                    stack.extend(predecessors[b].iter().copied());
+                }
+            }
+            match loops.iter_mut().find(|(header, _)| *header == h) {
+                Some((_, blocks)) => blocks.extend(body),
+                None => loops.push((h, body)),
+            }
+        }
+    }
+    loops
+}
+/*
+ * Instructions of the function and how many of them are inside a loop
+ */
+pub fn instruction_counts(function: &Function) -> (usize, usize) {
+    let in_loops: HashSet<BlockId> = __loops(function)
+        .into_iter()
+        .flat_map(|(_, blocks)| blocks)
+        .collect();
+    let size = |b: &BlockId| function.blocks[*b].insts.len() + 1;
+    let total = (0..function.blocks.len()).map(|b| size(&b)).sum();
+    (total, in_loops.iter().map(size).sum())
+}
+/*
+ * Instructions computing the same value on every iteration, loads and
+ * instructions that may trap stay in place
+ */
+fn __is_invariant(
+    inst: &Inst,
+    variant: &HashSet<VReg>,
+    defs: &[usize],
+    outside: &HashSet<VReg>,
+) -> bool {
+    let movable = match inst {
+        Inst::Copy { .. } | Inst::FrameAddr { .. } => true,
+        Inst::Binary {
+            op: BinOp::Div | BinOp::Mod,
+            rhs,
+            ..
+        } => matches!(rhs, Operand::Imm(n) if *n != 0),
+        Inst::Binary { .. } => true,
+        _ => false,
+    };
+    movable
+        && inst
+            .defs()
+            .iter()
+            .all(|v| defs[*v] == 1 && !outside.contains(v))
+        && inst.uses().iter().all(|v| !variant.contains(v))
+}
+/*
+ * Block the invariant instructions of the loop are moved to, the only
+ * block entering the header from outside, created when there is none
+ */
+fn __preheader(function: &mut Function, header: BlockId, blocks: &mut HashSet<BlockId>) -> BlockId {
+    let outside: Vec<BlockId> = (0..function.blocks.len())
+        .filter(|b| !blocks.contains(b) && function.blocks[*b].term.successors().contains(&header))
+        .collect();
+    if let [p] = outside[..] {
+        if function.blocks[p].term == Terminator::Jump(header) {
+            return p;
+        }
+    }
+    function.insert_blocks(
+        header,
+        vec![Block {
+            insts: vec![],
+            term: Terminator::Jump(header + 1),
+        }],
+    );
+    *blocks = blocks
+        .iter()
+        .map(|b| if *b >= header { b + 1 } else { *b })
+        .collect();
+    for p in outside {
+        let p = if p >= header { p + 1 } else { p };
+        for t in function.blocks[p].term.successors_mut() {
+            if *t == header + 1 {
+                *t = header;
+            }
+        }
+    }
+    header
+}
+/*
+ * Moves instructions whose operands do not change inside a while loop to
+ * a block run once before the loop
+ * Only registers defined once and read nowhere but inside the loop are
+ * moved, so a loop run zero times leaves every variable as it was
+ */
+pub fn hoist_loop_invariants(function: &mut Function) {
+    loop {
+        let mut defs = vec![0; function.vregs];
+        for block in function.blocks.iter() {
+            for inst in block.insts.iter() {
+                for v in inst.defs() {
+                    defs[v] += 1;
+                }
+            }
+        }
+        let (live_in, _) = liveness(function);
+        let mut moved = false;
+        for (header, blocks) in __loops(function) {
+            //registers read on entry to the loop or after leaving it
+            let mut outside: HashSet<VReg> = live_in[header].clone();
+            for b in blocks.iter() {
+                for s in function.blocks[*b].term.successors() {
+                    if !blocks.contains(&s) {
+                        outside.extend(live_in[s].iter().copied());
+                    }
+                }
+            }
+            let mut variant: HashSet<VReg> = blocks
+                .iter()
+                .flat_map(|b| function.blocks[*b].insts.iter())
+                .flat_map(|i| i.defs())
+                .collect();
+            let mut order: Vec<BlockId> = blocks.iter().copied().collect();
+            order.sort();
+            let mut hoisted = vec![];
+            for b in order {
+                let insts = std::mem::take(&mut function.blocks[b].insts);
+                for inst in insts {
+                    if __is_invariant(&inst, &variant, &defs, &outside) {
+                        for v in inst.defs() {
+                            variant.remove(&v);
+                        }
+                        hoisted.push(inst);
+                    } else {
+                        function.blocks[b].insts.push(inst);
+                    }
+                }
+            }
+            if hoisted.is_empty() {
+                continue;
+            }
+            let mut blocks = blocks;
+            let preheader = __preheader(function, header, &mut blocks);
+            function.blocks[preheader].insts.append(&mut hoisted);
+            moved = true;
+            //block numbers may have changed, loops are found again
+            break;
+        }
+        if !moved {
+            break;
+        }
+    }
+}
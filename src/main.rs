@@ -1,6 +1,5 @@
 // TODO Strict Ownership things for type system
 // Stage 5
-// TODO Return statement check for a function
 // Stage 6
 // TODO Deref for expressions dot arrow lists : needs alot of parser work and refactoring
 // TODO Buddy System
@@ -24,10 +23,24 @@ use std::{
 lrlex_mod!("lexer.l");
 lrpar_mod!("parser.y");
 
+mod analysis;
 mod codegen;
+mod constfold;
+mod cse;
+mod dce;
 mod exprtree;
+mod inline;
+mod ir;
+mod irgen;
+mod licm;
 mod linker;
+mod options;
+mod ownership;
 mod parserlib;
+mod passes;
+mod peephole;
+mod regalloc;
+mod tailcall;
 mod validation;
 
 fn read_file(path: &str) -> String {
@@ -62,10 +75,17 @@ fn main() {
         })
         .init();
 
-    let input = &read_file(&args[1]);
+    let path = match options::parse_args(&args) {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!("{}", e);
+            process::exit(1);
+        }
+    };
+    let input = &read_file(&path);
     let lexer_ = lexerdef.lexer(input);
 
-    let filename = *args[1]
+    let filename = *path
         .split('.')
         .collect::<Vec<&str>>()
         .first()
@@ -0,0 +1,124 @@
+//command line options of the compiler
+use crate::passes::is_pass;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Allocator {
+    //Alloc/Free/Heapset of the xsm library, fixed 8 word blocks
+    Library,
+    //first fit allocator emitted into the program, any block size
+    Builtin,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub allocator: Allocator,
+    //alloc()/free() ownership checks of pointer variables
+    pub ownership: bool,
+    //bounds, null and zero divisor checks emitted into the program
+    pub checked: bool,
+    //print the IR of every function to stdout
+    pub emit_ir: bool,
+    //-O level selecting the passes that run
+    pub opt_level: u8,
+    //passes turned on or off by name whatever the level
+    pub passes: HashMap<String, bool>,
+    //report the time spent in every pass
+    pub time_passes: bool,
+    //IR instructions a function may have to be inlined without being marked inline
+    pub inline_threshold: usize,
+}
+
+lazy_static! {
+    //every pass runs unless asked otherwise
+    pub static ref OPTIONS: Mutex<Options> = Mutex::new(Options {
+        allocator: Allocator::Library,
+        ownership: false,
+        checked: false,
+        emit_ir: false,
+        opt_level: 2,
+        passes: HashMap::new(),
+        time_passes: false,
+        inline_threshold: 24,
+    });
+}
+
+pub fn get_allocator() -> Allocator {
+    OPTIONS.lock().unwrap().allocator
+}
+pub fn ownership_checks() -> bool {
+    OPTIONS.lock().unwrap().ownership
+}
+pub fn runtime_checks() -> bool {
+    OPTIONS.lock().unwrap().checked
+}
+pub fn emit_ir() -> bool {
+    OPTIONS.lock().unwrap().emit_ir
+}
+pub fn opt_level() -> u8 {
+    OPTIONS.lock().unwrap().opt_level
+}
+pub fn pass_override(name: &str) -> Option<bool> {
+    OPTIONS.lock().unwrap().passes.get(name).copied()
+}
+pub fn time_passes() -> bool {
+    OPTIONS.lock().unwrap().time_passes
+}
+pub fn inline_threshold() -> usize {
+    OPTIONS.lock().unwrap().inline_threshold
+}
+/*
+ * Parse the command line flags into OPTIONS, returns the input file
+ */
+pub fn parse_args(args: &[String]) -> Result<String, String> {
+    let mut options = OPTIONS.lock().unwrap();
+    let mut input: Option<String> = None;
+    for arg in args.iter().skip(1) {
+        if let Some(flag) = arg.strip_prefix("--") {
+            match flag.split_once('=') {
+                Some(("allocator", "library")) => options.allocator = Allocator::Library,
+                Some(("allocator", "builtin")) => options.allocator = Allocator::Builtin,
+                None if flag == "ownership" => options.ownership = true,
+                None if flag == "checked" => options.checked = true,
+                Some(("emit", "ir")) => options.emit_ir = true,
+                None if flag == "no-peephole" => {
+                    options.passes.insert("peephole".to_owned(), false);
+                }
+                Some(("pass", name)) | Some(("no-pass", name)) => {
+                    if !is_pass(name) {
+                        return Err("Unknown pass [".to_owned() + name + "].");
+                    }
+                    options
+                        .passes
+                        .insert(name.to_owned(), flag.starts_with("pass="));
+                }
+                None if flag == "time-passes" => options.time_passes = true,
+                Some(("inline-threshold", n)) => {
+                    options.inline_threshold = n
+                        .parse()
+                        .map_err(|_| "Invalid inline threshold [".to_owned() + n + "].")?
+                }
+                _ => return Err("Unknown option [".to_owned() + arg.as_str() + "]."),
+            }
+        } else if let Some(level) = arg.strip_prefix("-O") {
+            options.opt_level = match level {
+                "0" => 0,
+                "1" => 1,
+                "2" => 2,
+                _ => return Err("Unknown optimization level [".to_owned() + arg.as_str() + "]."),
+            };
+        } else if input.is_none() {
+            input = Some(arg.clone());
+        } else {
+            return Err("Only one input file can be compiled, got [".to_owned()
+                + arg.as_str()
+                + "].");
+        }
+    }
+    input.ok_or(
+        "Usage: rexplc [--allocator=library|builtin] [--ownership] [--checked] [--emit=ir] [-O0|-O1|-O2] [--pass=name] [--no-pass=name] [--time-passes] [--inline-threshold=N] <file.expl>"
+            .to_owned(),
+    )
+}
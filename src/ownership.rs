@@ -0,0 +1,564 @@
+//opt-in alloc()/free() ownership checker for pointer locals, enabled with --ownership
+use crate::parserlib::*;
+use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet, LinkedList};
+use std::sync::Mutex;
+
+type Pos = (usize, usize);
+
+//a heap block is named by the statement allocating it, blocks of params by their position
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Origin {
+    Alloc(Pos),
+    Param(usize),
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Block {
+    Owned,
+    Freed(Pos),
+    //stored elsewhere, handed to a call or of unknown state, no longer ours to free
+    Escaped,
+}
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Heap {
+    vars: HashMap<String, Origin>,
+    blocks: HashMap<Origin, Block>,
+}
+//None once the path has left through return/break/continue
+type HeapState = Option<Heap>;
+
+//what a checked function does with the blocks it is handed, used at its call sites
+#[derive(Debug, Clone, Default)]
+struct Summary {
+    returns_owned: bool,
+    frees_params: HashSet<usize>,
+}
+lazy_static! {
+    static ref SUMMARIES: Mutex<HashMap<String, Summary>> = Mutex::new(HashMap::default());
+}
+
+struct Ownership<'a> {
+    fname: &'a String,
+    tracked: HashSet<String>,
+    pos: Pos,
+    summary: Summary,
+}
+fn __at(pos: &Pos) -> String {
+    "line ".to_owned() + pos.0.to_string().as_str() + " column " + pos.1.to_string().as_str()
+}
+fn __allocated_at(origin: &Origin) -> String {
+    match origin {
+        Origin::Alloc(pos) => " (allocated at ".to_owned() + __at(pos).as_str() + ")",
+        Origin::Param(_) => String::default(),
+    }
+}
+/*
+ * A plain pointer variable, no index or field access
+ */
+fn __plain_var(node: &ASTNode) -> Option<&String> {
+    match node {
+        ASTNode::VAR {
+            name,
+            array_access,
+            dot_field_access,
+            arrow_field_access,
+        } if array_access.is_empty()
+            && **dot_field_access == ASTNode::Void
+            && **arrow_field_access == ASTNode::Void =>
+        {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+/*
+ * Blocks that are in different states on the two paths are given up on,
+ * so are owned blocks no variable refers to anymore
+ */
+fn __merge(a: HeapState, b: HeapState) -> HeapState {
+    let (a, b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        (Some(a), None) => return Some(a),
+        (None, b) => return b,
+    };
+    let mut heap = Heap::default();
+    for (name, origin) in a.vars.iter() {
+        if b.vars.get(name) == Some(origin) {
+            heap.vars.insert(name.clone(), origin.clone());
+        }
+    }
+    for origin in a.blocks.keys().chain(b.blocks.keys()) {
+        let merged = match (a.blocks.get(origin), b.blocks.get(origin)) {
+            (Some(x), Some(y)) if x == y => x.clone(),
+            (Some(Block::Freed(p)), Some(Block::Freed(_))) => Block::Freed(*p),
+            _ => Block::Escaped,
+        };
+        let merged = match merged {
+            Block::Owned if !heap.vars.values().any(|o| o == origin) => Block::Escaped,
+            _ => merged,
+        };
+        heap.blocks.insert(origin.clone(), merged);
+    }
+    Some(heap)
+}
+/*
+ * Check the pointer locals of a function for double free, use after free and leaks
+ */
+pub fn check_ownership(
+    fname: &String,
+    classname: &str,
+    body: &ASTNode,
+    lst: &HashMap<String, LSymbol>,
+) -> Result<(), String> {
+    let mut checker = Ownership {
+        fname,
+        tracked: HashSet::default(),
+        pos: (0, 0),
+        summary: Summary::default(),
+    };
+    let mut heap = Heap::default();
+    let mut params: Vec<(i64, String)> = vec![];
+    for (name, entry) in lst.iter() {
+        let LSymbol::Var {
+            vartype,
+            varid,
+            varindices,
+        } = entry;
+        if let (ASTExprType::Pointer(_), 0) = (vartype, varindices.len()) {
+            checker.tracked.insert(name.clone());
+            if *varid < 0 {
+                params.push((*varid, name.clone()));
+            }
+        }
+    }
+    //the first param has the lowest id, params are borrowed from the caller
+    params.sort();
+    for (i, (_, name)) in params.iter().enumerate() {
+        heap.vars.insert(name.clone(), Origin::Param(i));
+        heap.blocks.insert(Origin::Param(i), Block::Escaped);
+    }
+    let mut state: HeapState = Some(heap);
+    checker.stmt(body, &mut state)?;
+    checker.leaks(
+        &state,
+        "at the end of function [".to_owned() + fname.as_str() + "]",
+    )?;
+    SUMMARIES
+        .lock()
+        .unwrap()
+        .insert(fname.clone() + "#" + classname, checker.summary);
+    Ok(())
+}
+impl<'a> Ownership<'a> {
+    fn leaks(&self, state: &HeapState, when: String) -> Result<(), String> {
+        if let Some(heap) = state {
+            let mut owned: Vec<&Pos> = heap
+                .blocks
+                .iter()
+                .filter_map(|(origin, block)| match (origin, block) {
+                    (Origin::Alloc(pos), Block::Owned) => Some(pos),
+                    _ => None,
+                })
+                .collect();
+            owned.sort();
+            if let Some(pos) = owned.first() {
+                return Err("Memory allocated at ".to_owned()
+                    + __at(pos).as_str()
+                    + " leaks "
+                    + when.as_str()
+                    + ".");
+            }
+        }
+        Ok(())
+    }
+    /*
+     * Reading a pointer that was freed
+     */
+    fn use_var(&self, name: &String, state: &HeapState) -> Result<(), String> {
+        let heap = match state {
+            Some(h) => h,
+            None => return Ok(()),
+        };
+        if let Some(origin) = heap.vars.get(name) {
+            if let Some(Block::Freed(freed)) = heap.blocks.get(origin) {
+                return Err("Use of [".to_owned()
+                    + name.as_str()
+                    + "] at "
+                    + __at(&self.pos).as_str()
+                    + " after it was freed at "
+                    + __at(freed).as_str()
+                    + __allocated_at(origin).as_str()
+                    + ".");
+            }
+        }
+        Ok(())
+    }
+    fn free_var(&mut self, name: &String, state: &mut HeapState) -> Result<(), String> {
+        let heap = match state {
+            Some(h) => h,
+            None => return Ok(()),
+        };
+        let origin = match heap.vars.get(name) {
+            Some(o) => o.clone(),
+            None => return Ok(()),
+        };
+        if let Some(Block::Freed(freed)) = heap.blocks.get(&origin) {
+            return Err("Double free of [".to_owned()
+                + name.as_str()
+                + "] at "
+                + __at(&self.pos).as_str()
+                + ", it was already freed at "
+                + __at(freed).as_str()
+                + __allocated_at(&origin).as_str()
+                + ".");
+        }
+        if let Origin::Param(i) = origin {
+            self.summary.frees_params.insert(i);
+        }
+        heap.blocks.insert(origin, Block::Freed(self.pos));
+        Ok(())
+    }
+    fn escape_var(&self, name: &String, state: &mut HeapState) {
+        if let Some(heap) = state {
+            if let Some(origin) = heap.vars.get(name) {
+                if heap.blocks.get(origin) == Some(&Block::Owned) {
+                    heap.blocks.insert(origin.clone(), Block::Escaped);
+                }
+            }
+        }
+    }
+    /*
+     * The variable is about to be overwritten, its block leaks if nothing else refers to it
+     */
+    fn release_var(&self, name: &String, state: &mut HeapState) -> Result<(), String> {
+        let heap = match state {
+            Some(h) => h,
+            None => return Ok(()),
+        };
+        if let Some(origin) = heap.vars.remove(name) {
+            let shared = heap.vars.values().any(|o| *o == origin);
+            if let (Origin::Alloc(pos), Some(Block::Owned), false) =
+                (&origin, heap.blocks.get(&origin), shared)
+            {
+                return Err("Pointer [".to_owned()
+                    + name.as_str()
+                    + "] allocated at "
+                    + __at(pos).as_str()
+                    + " is overwritten at "
+                    + __at(&self.pos).as_str()
+                    + " without being freed.");
+            }
+        }
+        Ok(())
+    }
+    fn bind_var(&self, name: &str, origin: Origin, block: Option<Block>, state: &mut HeapState) {
+        if let Some(heap) = state {
+            if let Some(block) = block {
+                heap.blocks.insert(origin.clone(), block);
+            }
+            heap.vars.insert(name.to_owned(), origin);
+        }
+    }
+    /*
+     * Arguments freed by the callee are freed here, other pointers may be kept by the callee
+     */
+    fn call(
+        &mut self,
+        fname: Option<&String>,
+        arglist: &LinkedList<ASTNode>,
+        state: &mut HeapState,
+    ) -> Result<(), String> {
+        let summary = match fname {
+            Some(f) => SUMMARIES.lock().unwrap().get(&(f.clone() + "#")).cloned(),
+            None => None,
+        };
+        for (i, arg) in arglist.iter().enumerate() {
+            match __plain_var(arg) {
+                Some(name) if self.tracked.contains(name) => {
+                    self.use_var(name, state)?;
+                    match &summary {
+                        Some(s) if s.frees_params.contains(&i) => self.free_var(name, state)?,
+                        _ => self.escape_var(name, state),
+                    }
+                }
+                _ => self.expr(arg, state)?,
+            }
+        }
+        Ok(())
+    }
+    fn expr(&mut self, node: &ASTNode, state: &mut HeapState) -> Result<(), String> {
+        match node {
+            ASTNode::VAR {
+                name,
+                array_access,
+                dot_field_access,
+                arrow_field_access,
+            } => {
+                if self.tracked.contains(name) {
+                    self.use_var(name, state)?;
+                }
+                for index in array_access {
+                    self.expr(index, state)?;
+                }
+                self.chain(dot_field_access, state)?;
+                self.chain(arrow_field_access, state)
+            }
+            ASTNode::BinaryNode {
+                op: _,
+                exprtype: _,
+                lhs,
+                rhs,
+            } => {
+                self.expr(lhs, state)?;
+                self.expr(rhs, state)
+            }
+            ASTNode::UnaryNode {
+                op: ASTNodeType::Free,
+                exprtype: _,
+                ptr,
+                depth: _,
+            } => match __plain_var(ptr) {
+                Some(name) if self.tracked.contains(name) => self.free_var(name, state),
+                _ => self.expr(ptr, state),
+            },
+            ASTNode::UnaryNode {
+                op: ASTNodeType::Ref,
+                exprtype: _,
+                ptr: _,
+                depth: _,
+            } => Ok(()),
+            ASTNode::UnaryNode {
+                op: _,
+                exprtype: _,
+                ptr,
+                depth: _,
+            } => self.expr(ptr, state),
+            ASTNode::FuncCallNode { fname, arglist } => self.call(Some(fname), arglist, state),
+            ASTNode::StdFuncCallNode { func: _, arglist } => self.call(None, arglist, state),
+            ASTNode::IndirectCallNode { fptr, arglist } => {
+                self.expr(fptr, state)?;
+                self.call(None, arglist, state)
+            }
+            ASTNode::TupleNode { elems } => {
+                for elem in elems {
+                    match __plain_var(elem) {
+                        Some(name) if self.tracked.contains(name) => {
+                            self.use_var(name, state)?;
+                            self.escape_var(name, state);
+                        }
+                        _ => self.expr(elem, state)?,
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+    fn chain(&mut self, node: &ASTNode, state: &mut HeapState) -> Result<(), String> {
+        match node {
+            ASTNode::VAR {
+                name: _,
+                array_access,
+                dot_field_access,
+                arrow_field_access,
+            } => {
+                for index in array_access {
+                    self.expr(index, state)?;
+                }
+                self.chain(dot_field_access, state)?;
+                self.chain(arrow_field_access, state)
+            }
+            //methods may keep their arguments
+            ASTNode::FuncCallNode { fname: _, arglist } => self.call(None, arglist, state),
+            _ => Ok(()),
+        }
+    }
+    fn assign(
+        &mut self,
+        lhs: &ASTNode,
+        rhs: &ASTNode,
+        state: &mut HeapState,
+    ) -> Result<(), String> {
+        let target = match __plain_var(lhs) {
+            Some(name) if self.tracked.contains(name) => name,
+            //storing a pointer into a field, through a pointer or into a global gives it away
+            _ => {
+                match __plain_var(rhs) {
+                    Some(name) if self.tracked.contains(name) => {
+                        self.use_var(name, state)?;
+                        self.escape_var(name, state);
+                    }
+                    _ => self.expr(rhs, state)?,
+                }
+                return self.expr(lhs, state);
+            }
+        };
+        match rhs {
+            ASTNode::UnaryNode {
+                op: ASTNodeType::AllocArray,
+                ..
+            } => {
+                self.expr(rhs, state)?;
+                self.release_var(target, state)?;
+                self.bind_var(target, Origin::Alloc(self.pos), Some(Block::Owned), state);
+            }
+            ASTNode::FuncCallNode { fname, arglist } => {
+                self.call(Some(fname), arglist, state)?;
+                let returns_owned = SUMMARIES
+                    .lock()
+                    .unwrap()
+                    .get(&(fname.clone() + "#"))
+                    .is_some_and(|s| s.returns_owned);
+                self.release_var(target, state)?;
+                if returns_owned {
+                    self.bind_var(target, Origin::Alloc(self.pos), Some(Block::Owned), state);
+                }
+            }
+            _ => match __plain_var(rhs) {
+                //both names refer to the same block now
+                Some(name) if self.tracked.contains(name) => {
+                    self.use_var(name, state)?;
+                    let origin = state.as_ref().and_then(|h| h.vars.get(name).cloned());
+                    if Some(name) != Some(target) {
+                        self.release_var(target, state)?;
+                    }
+                    if let Some(origin) = origin {
+                        self.bind_var(target, origin, None, state);
+                    }
+                }
+                _ => {
+                    self.expr(rhs, state)?;
+                    self.release_var(target, state)?;
+                }
+            },
+        }
+        Ok(())
+    }
+    fn stmt(&mut self, node: &ASTNode, state: &mut HeapState) -> Result<(), String> {
+        match node {
+            ASTNode::StmtNode { pos, stmt } => {
+                self.pos = *pos;
+                self.stmt(stmt, state)
+            }
+            ASTNode::BinaryNode {
+                op: ASTNodeType::Connector,
+                exprtype: _,
+                lhs,
+                rhs,
+            } => {
+                self.stmt(lhs, state)?;
+                self.stmt(rhs, state)
+            }
+            ASTNode::BinaryNode {
+                op: ASTNodeType::Equals,
+                exprtype: _,
+                lhs,
+                rhs,
+            } => self.assign(lhs, rhs, state),
+            ASTNode::UnaryNode {
+                op: ASTNodeType::Alloc,
+                exprtype: _,
+                ptr,
+                depth: _,
+            } => match __plain_var(ptr) {
+                Some(name) if self.tracked.contains(name) => {
+                    self.release_var(name, state)?;
+                    self.bind_var(name, Origin::Alloc(self.pos), Some(Block::Owned), state);
+                    Ok(())
+                }
+                _ => self.expr(ptr, state),
+            },
+            ASTNode::TupleAssgNode { vars, call } => {
+                self.expr(call, state)?;
+                for var in vars {
+                    match __plain_var(var) {
+                        Some(name) if self.tracked.contains(name) => {
+                            self.release_var(name, state)?
+                        }
+                        _ => self.expr(var, state)?,
+                    }
+                }
+                Ok(())
+            }
+            ASTNode::IfNode { expr, xif } => {
+                self.expr(expr, state)?;
+                let mut s = state.clone();
+                self.stmt(xif, &mut s)?;
+                *state = __merge(state.take(), s);
+                Ok(())
+            }
+            ASTNode::IfElseNode { expr, xif, xelse } => {
+                self.expr(expr, state)?;
+                let mut a = state.clone();
+                let mut b = state.clone();
+                self.stmt(xif, &mut a)?;
+                self.stmt(xelse, &mut b)?;
+                *state = __merge(a, b);
+                Ok(())
+            }
+            //the body runs zero or more times, the state at the end of one
+            //iteration enters the next until no new state turns up
+            ASTNode::WhileNode { expr, xdo } => {
+                self.expr(expr, state)?;
+                let mut seen: Vec<HeapState> = vec![state.clone()];
+                let mut entry = state.clone();
+                loop {
+                    self.stmt(xdo, &mut entry)?;
+                    self.expr(expr, &mut entry)?;
+                    *state = __merge(state.take(), entry.clone());
+                    if entry.is_none() || seen.contains(&entry) {
+                        break;
+                    }
+                    seen.push(entry.clone());
+                }
+                Ok(())
+            }
+            ASTNode::SwitchNode {
+                expr,
+                cases,
+                default,
+            } => {
+                self.expr(expr, state)?;
+                let mut merged: HeapState = None;
+                for (_, body) in cases {
+                    let mut s = state.clone();
+                    self.stmt(body, &mut s)?;
+                    merged = __merge(merged, s);
+                }
+                let mut s = state.clone();
+                self.stmt(default, &mut s)?;
+                *state = __merge(merged, s);
+                Ok(())
+            }
+            ASTNode::ReturnNode { expr } => {
+                match __plain_var(expr) {
+                    Some(name) if self.tracked.contains(name) => {
+                        self.use_var(name, state)?;
+                        let owned = state.as_ref().is_some_and(|h| {
+                            h.vars
+                                .get(name)
+                                .is_some_and(|o| h.blocks.get(o) == Some(&Block::Owned))
+                        });
+                        self.summary.returns_owned |= owned;
+                        self.escape_var(name, state);
+                    }
+                    _ => self.expr(expr, state)?,
+                }
+                self.leaks(
+                    state,
+                    "when function [".to_owned()
+                        + self.fname.as_str()
+                        + "] returns at "
+                        + __at(&self.pos).as_str(),
+                )?;
+                *state = None;
+                Ok(())
+            }
+            ASTNode::BreakNode | ASTNode::ContinueNode => {
+                *state = None;
+                Ok(())
+            }
+            _ => self.expr(node, state),
+        }
+    }
+}
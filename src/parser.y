@@ -5,6 +5,7 @@
 %nonassoc ">" "<" ">=" '<=' "==" "!="
 %left '+' '-'
 %left '*' '/' '%'
+%right "CAST"
 %%
 
 PtrPtr -> Result<ASTExprType,String>: 
@@ -28,6 +29,25 @@ ParamType -> Result<ASTExprType,String>:
 	| Type PtrPtr { let mut ptr = $2?;ptr.set_base_type($1?.get_base_type());Ok(ptr) }
     ;
 
+//parameter types of a function pointer, (int) and (T*) arrive as a single CAST token
+FuncParamTypes -> Result<Vec<ASTExprType>,String>:
+	'(' ')' { Ok(vec![]) }
+	| '(' ParamTypeList ')' { $2 }
+	| "CAST"
+	{
+		let v = $1.map_err(|_| "CAST Err".to_string())?;
+		Ok(vec![parse_cast_type($lexer.span_str(v.span()))?])
+	}
+	;
+ParamTypeList -> Result<Vec<ASTExprType>,String>:
+	ParamTypeList ',' ParamType { let mut params = $1?;params.push($3?);Ok(params) }
+	| ParamType { Ok(vec![$1?]) }
+	;
+
+TupleType -> Result<ASTExprType,String>:
+	'(' ParamTypeList ',' ParamType ')' { let mut t = $2?;t.push($4?);Ok(ASTExprType::Tuple(t)) }
+	;
+
 FType-> Result<ASTExprType,String>: 
 	Type
 	{
@@ -49,6 +69,22 @@ FType-> Result<ASTExprType,String>:
 		std::mem::drop(rt);
 		Ok(ptr)
 	}
+	| TupleType
+	{
+		let t = $1?;
+		let mut rt = RET_TYPE.lock().unwrap();
+		*rt = t.clone();
+		std::mem::drop(rt);
+		Ok(t)
+	}
+	| 'VOID_T'
+	{
+		let t = ASTExprType::Primitive(PrimitiveType::Void);
+		let mut rt = RET_TYPE.lock().unwrap();
+		*rt = t.clone();
+		std::mem::drop(rt);
+		Ok(t)
+	}
     ;
 DeclType -> Result<ASTExprType,String>: 
 	'INT_T'
@@ -63,6 +99,13 @@ DeclType -> Result<ASTExprType,String>:
 		*dt = ASTExprType::Primitive(PrimitiveType::String);
 		Ok(dt.clone())
 	}
+	//only functions can be declared void
+	| 'VOID_T'
+	{
+		let mut dt = DECL_TYPE.lock().unwrap();
+		*dt = ASTExprType::Primitive(PrimitiveType::Void);
+		Ok(dt.clone())
+	}
 	| 'VAR'
 	{
 		let v = $1.map_err(|_| "VAR Err".to_string())?; 
@@ -80,27 +123,34 @@ Start -> Result<ASTNode,String>:
 	{
 		$1?;
 		$3?;
+		let classes = $2?;
+		let functions = $4?;
+		let main = $5?;
+		warn_unused_globals();
 		Ok(ASTNode::BinaryNode{
 			op : ASTNodeType::Connector,
             exprtype : Some(ASTExprType::Primitive(PrimitiveType::Void)),
 			lhs : Box::new(ASTNode::BinaryNode{
 				op: ASTNodeType::Connector,
 				exprtype: Some(ASTExprType::Primitive(PrimitiveType::Void)),
-				lhs: Box::new($2?),
-				rhs: Box::new($4?),
+				lhs: Box::new(classes),
+				rhs: Box::new(functions),
 			}),
-			rhs : Box::new($5?),
+			rhs : Box::new(main),
 		})
 	}
 	| TypeDefBlock ClassDefBlock GDeclBlock MainBlock
 	{
 		$1?;
 		$3?;
+		let classes = $2?;
+		let main = $4?;
+		warn_unused_globals();
 		Ok(ASTNode::BinaryNode{
 			op : ASTNodeType::Connector,
             exprtype : Some(ASTExprType::Primitive(PrimitiveType::Void)),
-			lhs : Box::new($2?),
-			rhs : Box::new($4?),
+			lhs : Box::new(classes),
+			rhs : Box::new(main),
 		})
 	}
 	;
@@ -109,13 +159,27 @@ Start -> Result<ASTNode,String>:
 MainBlock -> Result<ASTNode,String>:
 	FType "MAIN" '('  ')' '{' LDeclBlock BeginBlock '}'
 	{
+		let init_ = $6?;
 		let body_ = $7?;
 		let type_ = $1?;
 		if type_ != ASTExprType::Primitive(PrimitiveType::Int) {
 			return Err("Main should return an integer".to_string());
 		}
+		let body = ASTNode::BinaryNode{
+			op: ASTNodeType::Connector,
+			exprtype: Some(ASTExprType::Primitive(PrimitiveType::Void)),
+			lhs: Box::new(init_),
+			rhs: Box::new(body_),
+		};
+		check_main_returns(&body);
+		let lst = LOCALSYMBOLTABLE.lock().unwrap().clone();
+		check_definite_assignment(&"main".to_owned(), &body, &lst);
+		warn_unused_locals(&"main".to_owned());
+		if ownership_checks() {
+			check_ownership(&"main".to_owned(), &"".to_owned(), &body, &lst)?;
+		}
 		let node = ASTNode::MainNode{
-			body: Box::new(body_),
+			body: Box::new(body),
 		};
 		let mut ft = FUNCTION_TABLE.lock().unwrap();
 		let mut lst = LOCALSYMBOLTABLE.lock().unwrap();
@@ -140,9 +204,17 @@ GDeclBlock -> Result<(),String>:
 	| { Ok(()) }
 	;
 
-LDeclList -> Result<(),String>:
-	LDeclList LDecl { $1?;$2?;Ok(()) }
-	| LDecl { $1?;Ok(()) }
+LDeclList -> Result<ASTNode,String>:
+	LDeclList LDecl
+	{
+		Ok(ASTNode::BinaryNode{
+			op: ASTNodeType::Connector,
+			exprtype: Some(ASTExprType::Primitive(PrimitiveType::Void)),
+			lhs: Box::new($1?),
+			rhs: Box::new($2?),
+		})
+	}
+	| LDecl { $1 }
 	;
 
 GDeclList -> Result<(),String>:
@@ -150,16 +222,23 @@ GDeclList -> Result<(),String>:
 	| GDecl { $1?;Ok(()) }
 	;
 
-LDecl ->  Result<(),String>:
-	DeclType LLine ';' { $1?;$2?;Ok(()) }
+LDecl ->  Result<ASTNode,String>:
+	DeclType LLine ';' { $1?;$2 }
 	;
 
 GDecl ->  Result<(),String>:
 	DeclType GLine ';' { $1?;$2?;Ok(()) }
+	| TupleType 'VAR' '(' GParamList ')' ';'
+	{
+		let v = $2.map_err(|_| "VAR Err".to_string())?;
+		let functionname= parse_string($lexer.span_str(v.span())).unwrap();
+		install_func_to_gst(functionname,$1?,&$4?);
+		Ok(())
+	}
 	;
 
 GLine -> Result<(),String>:
-	GItem ',' GLine { $1?;$3?;Ok(()) }
+	GLine ',' GItem { $1?;$3?;Ok(()) }
 	| GItem { $1?;Ok(()) }
 	;
 
@@ -189,38 +268,67 @@ GItem -> Result<(),String>:
 		install_func_to_gst(functionname,returntype,&paramlist);
 		Ok(())
 	}
-	| VarItem
+	| DeclItem
 	{
-		let mut node = $1?;
+		let (mut node, init) = $1?;
 		let dt = DECL_TYPE.lock().unwrap().clone();
 		node.vartype.set_base_type(dt.get_base_type());
-		node.install_to_gst();
-		Ok(())
+		__non_void(&node)?;
+		node.clone().install_to_gst();
+		node.global_initializer(init)
 	}
 	;
 
-LLine -> Result<(),String>:
-	VarItem ',' LLine
+LLine -> Result<ASTNode,String>:
+	LLine ',' DeclItem
 	{
-		let mut node = $1?;
+		let prev = $1?;
+		let (mut node, init) = $3?;
 		let dt = DECL_TYPE.lock().unwrap().clone();
 		node.vartype.set_base_type(dt.get_base_type());
+		__non_void(&node)?;
 		node.install_to_lst();
-		Ok(())
+		Ok(ASTNode::BinaryNode{
+			op: ASTNodeType::Connector,
+			exprtype: Some(ASTExprType::Primitive(PrimitiveType::Void)),
+			lhs: Box::new(prev),
+			rhs: Box::new(node.local_initializer(init)?),
+		})
 	}
-	| VarItem 
+	| DeclItem
 	{
-		let mut node =$1.unwrap();
+		let (mut node, init) = $1?;
 		let dt = DECL_TYPE.lock().unwrap().clone();
 		node.vartype.set_base_type(dt.get_base_type());
+		__non_void(&node)?;
 		node.install_to_lst();
-		Ok(())
+		node.local_initializer(init)
 	}
 	;
 
+DeclItem -> Result<(VarNode,Initializer),String>:
+	VarItem { Ok(($1?, Initializer::Empty)) }
+	| VarItem '=' Expr { Ok(($1?, Initializer::Expr($3?))) }
+	| VarItem '=' '{' ArgList '}' { Ok(($1?, Initializer::List($4?))) }
+	;
+
 VarItem -> Result<VarNode,String>: 
 	VariableDef { $1 } 
 	| PtrPtr VariableDef { let mut node= $2?;node.vartype = $1?;Ok(node) }
+	//the return type is filled in from the declaration type
+	| '(' '*' 'VAR' ')' FuncParamTypes
+	{
+		let v = $3.map_err(|_| "(*VAR) Err".to_string())?;
+		let var_ = parse_string($lexer.span_str(v.span())).unwrap();
+		Ok(VarNode{
+			varname: var_,
+			vartype: ASTExprType::Function(ASTFunctionType{
+				ret_type: Box::new(ASTExprType::Primitive(PrimitiveType::Void)),
+				params: $5?,
+			}),
+			varindices: vec![],
+		})
+	}
     ;
 
 FBlock -> Result<ASTNode,String>:
@@ -246,19 +354,40 @@ FDefBlock -> Result<ASTNode,String>:
 	| FDef { $1 }
 	;
 
-FDef ->Result<ASTNode,String>:
+FDef -> Result<ASTNode,String>:
+	FuncDef { $1 }
+	| 'INLINE' FuncDef { __inline_hint($2?, true) }
+	| 'NOINLINE' FuncDef { __inline_hint($2?, false) }
+	;
+
+FuncDef ->Result<ASTNode,String>:
 	FType 'VAR' '(' ParamListBlock ')' '{' LDeclBlock BeginBlock '}'
 	{
 		let v = $2.map_err(|_| "VAR Err".to_string())?; 
-		$7?;
+		let init = $7?;
 		let funcname = parse_string($lexer.span_str(v.span())).unwrap();
 		let mut node = ASTNode::FuncDefNode{
 			fname: funcname.clone(),
 			ret_type: $1?,
-			body: Box::new($8?),
+			body: Box::new(ASTNode::BinaryNode{
+				op: ASTNodeType::Connector,
+				exprtype: Some(ASTExprType::Primitive(PrimitiveType::Void)),
+				lhs: Box::new(init),
+				rhs: Box::new($8?),
+			}),
 			paramlist: $4?, 
 		};
 		node.validate()?;
+		if let ASTNode::FuncDefNode{ fname, ret_type, body, .. } = &node {
+			check_function_returns(fname, ret_type, body, $lexer.line_col($span).0)?;
+			let lst = LOCALSYMBOLTABLE.lock().unwrap().clone();
+			check_definite_assignment(fname, body, &lst);
+			warn_unused_locals(fname);
+			if ownership_checks() {
+				let cname = CLASSNAME.lock().unwrap().clone();
+				check_ownership(fname, &cname, body, &lst)?;
+			}
+		}
 
 		let mut lst = LOCALSYMBOLTABLE.lock().unwrap();
 		let mut ft = FUNCTION_TABLE.lock().unwrap();
@@ -275,10 +404,10 @@ FDef ->Result<ASTNode,String>:
 	}
 	;
 
-LDeclBlock -> Result<(),String>:
+LDeclBlock -> Result<ASTNode,String>:
 	"DECL" LDeclList "ENDDECL" { $2 }
-	| "DECL" "ENDDECL" { Ok(()) }
-	| { Ok(()) }
+	| "DECL" "ENDDECL" { Ok(ASTNode::Void) }
+	| { Ok(ASTNode::Void) }
 	;
 
 ParamListBlock -> Result<LinkedList<VarNode>,String>:
@@ -330,12 +459,45 @@ Param -> Result<LinkedList<VarNode>,String>:
 	ParamType VariableDef 
     {
 		let mut var = $2?;let vtype = $1?;
-		if var.varindices.len() != 0 {
-			return Err("Arrays cannot be used as a function parameter. Use a pointer instead.".to_owned());
-		}
 		var.vartype= vtype;
 		Ok(LinkedList::from(var))
     }
+	| ParamType '(' '*' 'VAR' ')' FuncParamTypes
+	{
+		let v = $4.map_err(|_| "(*VAR) Err".to_string())?;
+		let var_ = parse_string($lexer.span_str(v.span())).unwrap();
+		Ok(LinkedList::from(VarNode{
+			varname: var_,
+			vartype: ASTExprType::Function(ASTFunctionType{
+				ret_type: Box::new($1?),
+				params: $6?,
+			}),
+			varindices: vec![],
+		}))
+	}
+	//unsized arrays keep 0 as the first dimension
+	| ParamType 'VAR' '[' ']'
+	{
+		let v = $2.map_err(|_| "VAR[] Err".to_string())?;
+		let var_ = parse_string($lexer.span_str(v.span())).unwrap();
+		Ok(LinkedList::from(VarNode{
+			varname: var_,
+			vartype: $1?,
+			varindices: vec![0],
+		}))
+	}
+	| ParamType 'VAR' '[' ']' '[' 'INT' ']'
+	{
+		let v = $2.map_err(|_| "VAR[][] Err".to_string())?;
+		let var_ = parse_string($lexer.span_str(v.span())).unwrap();
+		let v = $6.map_err(|_| "VAR[][INT] Err".to_string())?;
+		let j = parse_usize($lexer.span_str(v.span())).unwrap();
+		Ok(LinkedList::from(VarNode{
+			varname: var_,
+			vartype: $1?,
+			varindices: vec![0,j],
+		}))
+	}
 	;
 
 ArgList -> Result<LinkedList<ASTNode>,String>:
@@ -372,7 +534,7 @@ VariableDef -> Result<VarNode,String>:
 		let var_ = parse_string($lexer.span_str(v.span())).unwrap();
 		let v = $3.map_err(|_| "VAR[INT] Err".to_string())?;
         let i= parse_usize($lexer.span_str(v.span())).unwrap();
-		let v = $3.map_err(|_| "VAR[][INT] Err".to_string())?;
+		let v = $6.map_err(|_| "VAR[][INT] Err".to_string())?;
         let j= parse_usize($lexer.span_str(v.span())).unwrap();
 		Ok(VarNode{
 			varname: var_,
@@ -383,7 +545,7 @@ VariableDef -> Result<VarNode,String>:
 	;
 //StateMents
 StmtList -> Result<ASTNode, String>:
-	StmtList Stmt 
+	StmtList LocatedStmt 
 	{
 		Ok(ASTNode::BinaryNode{
 			op : ASTNodeType::Connector,
@@ -392,7 +554,17 @@ StmtList -> Result<ASTNode, String>:
 			rhs : Box::new($2?),
 		})
 	}
-	| Stmt { $1 }
+	| LocatedStmt { $1 }
+	;
+//statements remember where they start for the diagnostics of later passes
+LocatedStmt -> Result<ASTNode, String>:
+	Stmt
+	{
+		Ok(ASTNode::StmtNode{
+			pos: $lexer.line_col($span).0,
+			stmt: Box::new($1?),
+		})
+	}
 	;
 Stmt -> Result<ASTNode,String>:
 	InputStmt { $1 }
@@ -400,9 +572,18 @@ Stmt -> Result<ASTNode,String>:
 	| AssgStmt { $1 }
 	| WhileStmt { $1 }
     | IfStmt { $1 }
+	| SwitchStmt { $1 }
 	| "BREAKPOINT" ';' { Ok(ASTNode::BreakpointNode) }
 	| "BREAK" ';' { Ok(ASTNode::BreakNode) }
 	| "CONTINUE" ';' { Ok(ASTNode::ContinueNode) }
+	| Variable ';'
+	{
+		let node = $1?;
+		if !__is_call(&node) {
+			return Err("Only function calls can be used as statements.".to_owned());
+		}
+		Ok(node)
+	}
 	| "RETURN" Expr ';'
 	{
 		let mut node = ASTNode::ReturnNode{
@@ -411,6 +592,24 @@ Stmt -> Result<ASTNode,String>:
 		node.validate()?;
 		Ok(node)
 	}
+	| "RETURN" ';'
+	{
+		let mut node = ASTNode::ReturnNode{
+			expr: Box::new(ASTNode::Void)
+		};
+		node.validate()?;
+		Ok(node)
+	}
+	| "RETURN" '(' Expr ',' ArgList ')' ';'
+	{
+		let mut elems = vec![$3?];
+		elems.extend($5?);
+		let mut node = ASTNode::ReturnNode{
+			expr: Box::new(ASTNode::TupleNode{ elems })
+		};
+		node.validate()?;
+		Ok(node)
+	}
 	| "INIT" '(' ')' ';'
 	{
 		let mut node = ASTNode::UnaryNode{
@@ -470,15 +669,48 @@ IfStmt -> Result<ASTNode,String>:
         })
 	}
 	;
+SwitchStmt -> Result<ASTNode,String>:
+	"SWITCH" '(' Expr ')' CaseList "ENDSWITCH" ';'
+	{
+		let mut node = ASTNode::SwitchNode{
+			expr: Box::new($3?),
+			cases: $5?,
+			default: Box::new(ASTNode::Void),
+		};
+		node.validate()?;
+		Ok(node)
+	}
+	| "SWITCH" '(' Expr ')' CaseList "DEFAULT" ':' StmtList "ENDSWITCH" ';'
+	{
+		let mut node = ASTNode::SwitchNode{
+			expr: Box::new($3?),
+			cases: $5?,
+			default: Box::new($8?),
+		};
+		node.validate()?;
+		Ok(node)
+	}
+	;
+CaseList -> Result<Vec<(ASTNode,ASTNode)>,String>:
+	CaseList "CASE" Expr ':' StmtList
+	{
+		let mut cases = $1?;
+		cases.push(($3?, $5?));
+		Ok(cases)
+	}
+	| "CASE" Expr ':' StmtList { Ok(vec![($2?, $4?)]) }
+	;
 OutputStmt -> Result<ASTNode,String>:
 	"WRITE" '(' Expr ')' ';' 
 	{
-		Ok(ASTNode::UnaryNode{
+		let mut node = ASTNode::UnaryNode{
 			op : ASTNodeType::Write,
 			exprtype : Some(ASTExprType::Primitive(PrimitiveType::Void)),
 			ptr : Box::new($3?),
 			depth : None,
-		})
+		};
+		node.validate()?;
+		Ok(node)
 	}
 	;
 AssgStmt -> Result<ASTNode,String>:
@@ -495,6 +727,15 @@ AssgStmt -> Result<ASTNode,String>:
 		node.validate()?;
 		Ok(node)
 	}
+	| '(' TupleVarList ')' '=' Expr ';'
+	{
+		let mut node = ASTNode::TupleAssgNode{
+			vars: $2?,
+			call: Box::new($5?),
+		};
+		node.validate()?;
+		Ok(node)
+	}
 	| VariableExpr '=' 'ALLOC' '(' ')' ';'
 	{
 		let mut node = ASTNode::UnaryNode{
@@ -507,6 +748,10 @@ AssgStmt -> Result<ASTNode,String>:
 		Ok(node)
 	}
 	;
+TupleVarList -> Result<Vec<ASTNode>,String>:
+	TupleVarList ',' VariableExpr { let mut vars = $1?;vars.push($3?);Ok(vars) }
+	| VariableExpr ',' VariableExpr { Ok(vec![$1?, $3?]) }
+	;
 InputStmt -> Result<ASTNode, String> :
 	"READ" '(' Variable ')' ';'
 	{
@@ -691,7 +936,47 @@ Expr -> Result<ASTNode,String>:
 		Ok(ASTNode::STR(str))
 	}
     | '(' Expr ')' { $2 } 
-	| VariableExpr { $1 } 
+	| "CAST" Expr
+	{
+		let v = $1.map_err(|_| "CAST Err".to_string())?;
+		let target = parse_cast_type($lexer.span_str(v.span()))?;
+		let mut node = ASTNode::UnaryNode{
+			op: ASTNodeType::Cast,
+			exprtype: Some(target),
+			ptr: Box::new($2?),
+			depth: None,
+		};
+		node.validate()?;
+		Ok(node)
+	}
+	| 'SIZEOF' '(' ParamType ')'
+	{
+		Ok(ASTNode::INT(i64::try_from($3?.size()?).unwrap()))
+	}
+	| 'SIZEOF' "CAST"
+	{
+		let v = $2.map_err(|_| "CAST Err".to_string())?;
+		Ok(ASTNode::INT(i64::try_from(parse_cast_type($lexer.span_str(v.span()))?.size()?).unwrap()))
+	}
+	| 'INT_T' '(' Expr ')'
+	{
+		let mut node = ASTNode::UnaryNode{
+			op: ASTNodeType::Cast,
+			exprtype: Some(ASTExprType::Primitive(PrimitiveType::Int)),
+			ptr: Box::new($3?),
+			depth: None,
+		};
+		node.validate()?;
+		Ok(node)
+	}
+	| VariableExpr
+	{
+		let mut node = $1?;
+		if node.getexprtype() == Some(ASTExprType::Primitive(PrimitiveType::Void)) {
+			return Err("The result of a void function call cannot be used in an expression.".to_owned());
+		}
+		Ok(node)
+	}
 	| 'NULL' { Ok(ASTNode::Null) }
 	| StdFuncCall { $1 }
 	;
@@ -701,10 +986,7 @@ FuncCall -> Result<ASTNode, String>:
 	{
 		let v = $1.map_err(|_| "VAR()".to_string())?;
 		let functionname= parse_string($lexer.span_str(v.span())).unwrap();
-		let mut node = ASTNode::FuncCallNode{
-			fname: functionname, 
-			arglist: Box::new(LinkedList::new()),
-		};
+		let mut node = __func_call(functionname, LinkedList::new());
 		node.validate()?;
 		Ok(node)
 	}
@@ -712,10 +994,23 @@ FuncCall -> Result<ASTNode, String>:
 	{
 		let v = $1.map_err(|_| "VAR( ArgList )".to_string())?;
 		let functionname= parse_string($lexer.span_str(v.span())).unwrap();
-		let mut node = ASTNode::FuncCallNode{
-			fname: functionname, 
-			arglist: Box::new($3?),
-		};
+		//Enum(expr) converts an int to the enum
+		let ftype = TYPE_TABLE.lock().unwrap().tt_get_type(&functionname).ok();
+		if let Some(ASTExprType::Enum(e)) = ftype {
+			let mut args = $3?;
+			if args.len() != 1 {
+				return Err("Conversion to enum [".to_owned() + functionname.as_str() + "] expects one argument.");
+			}
+			let mut node = ASTNode::UnaryNode{
+				op: ASTNodeType::Cast,
+				exprtype: Some(ASTExprType::Enum(e)),
+				ptr: Box::new(args.pop_front().unwrap()),
+				depth: None,
+			};
+			node.validate()?;
+			return Ok(node);
+		}
+		let mut node = __func_call(functionname, $3?);
 		node.validate()?;
 		Ok(node)
 	}
@@ -733,6 +1028,21 @@ StdFuncCall -> Result<ASTNode,String>:
 		node.validate()?;
 		Ok(node)
 	}
+	| 'ALLOC_ARRAY' '(' ParamType ',' Expr ')'
+	{
+		let mut node = ASTNode::UnaryNode{
+			op: ASTNodeType::AllocArray,
+			exprtype: Some(ASTExprType::Pointer(Box::new($3?))),
+			ptr: Box::new($5?),
+			depth: None,
+		};
+		node.validate()?;
+		Ok(node)
+	}
+	| 'LEN' '(' Variable ')'
+	{
+		Ok(ASTNode::INT(i64::try_from(getarraylen(&$3?)?).unwrap()))
+	}
 	| 'GETADDR' '(' ArgList ')'
 	{
 		let mut node = ASTNode::StdFuncCallNode{
@@ -771,10 +1081,25 @@ Variable -> Result<ASTNode,String>:
 	{
 		let v = $1.map_err(|_| "VAR Err".to_string())?;
 		let var = parse_string($lexer.span_str(v.span())).unwrap();
+		let field = $3?;
+		//Enum.MEMBER constants
+		let vtype = TYPE_TABLE.lock().unwrap().tt_get_type(&var).ok();
+		if let Some(ASTExprType::Enum(_)) = vtype {
+			return match &field {
+				ASTNode::VAR{ name, array_access, dot_field_access, arrow_field_access }
+					if array_access.len() == 0
+						&& **dot_field_access == ASTNode::Void
+						&& **arrow_field_access == ASTNode::Void =>
+				{
+					TYPE_TABLE.lock().unwrap().tt_get_enum_const(&var, name)
+				}
+				_ => Err("Enum [".to_owned() + var.as_str() + "] can only be accessed by a member name."),
+			};
+		}
 		Ok(ASTNode::VAR{
 			name: var,
 			array_access: vec![],
-			dot_field_access: Box::new($3?),
+			dot_field_access: Box::new(field),
 			arrow_field_access: Box::new(ASTNode::Void),
 		})
 	}
@@ -789,6 +1114,28 @@ Variable -> Result<ASTNode,String>:
 			arrow_field_access: Box::new($3?),
 		})
 	}
+	| 'VAR' VariableArray 'DOT' Variable
+	{
+		let v = $1.map_err(|_| "VAR Err".to_string())?;
+		let var = parse_string($lexer.span_str(v.span())).unwrap();
+		Ok(ASTNode::VAR{
+			name: var,
+			array_access: $2?,
+			dot_field_access: Box::new($4?),
+			arrow_field_access: Box::new(ASTNode::Void),
+		})
+	}
+	| 'VAR' VariableArray 'ARROW' Variable
+	{
+		let v = $1.map_err(|_| "VAR Err".to_string())?;
+		let var = parse_string($lexer.span_str(v.span())).unwrap();
+		Ok(ASTNode::VAR{
+			name: var,
+			array_access: $2?,
+			dot_field_access: Box::new(ASTNode::Void),
+			arrow_field_access: Box::new($4?),
+		})
+	}
 	| FuncCall
 	{
 		$1
@@ -833,10 +1180,22 @@ VariableExpr -> Result<ASTNode,String>:
 	}
 	| '&' Variable
 	{
+		let var = $2?;
+		if let ASTNode::VAR{ name, array_access, dot_field_access, arrow_field_access } = &var {
+			if array_access.len() == 0
+				&& **dot_field_access == ASTNode::Void
+				&& **arrow_field_access == ASTNode::Void
+				&& getfunctype(name).is_some()
+			{
+				let mut node = ASTNode::FuncRefNode{ fname: name.clone() };
+				node.validate()?;
+				return Ok(node);
+			}
+		}
 		let mut node = ASTNode::UnaryNode{
 			op: ASTNodeType::Ref,
 			exprtype: None,
-			ptr: Box::new($2?),
+			ptr: Box::new(var),
 			depth: None,
 		};
 		node.validate()?;
@@ -853,6 +1212,17 @@ VariableExpr -> Result<ASTNode,String>:
 		node.validate()?;
 		Ok(node)
 	}
+	| PtrPtr '(' Expr ')'
+	{
+		let mut node = ASTNode::UnaryNode{
+			op: ASTNodeType::Deref,
+			exprtype: None,
+			ptr: Box::new($3?),
+			depth: Some($1?.depth()),
+		};
+		node.validate()?;
+		Ok(node)
+	}
 	;
 
 //UserDefined Types
@@ -876,6 +1246,30 @@ TypeDef -> Result<(),String>:
 		mtt.tinstall_struct(typename, fields)?;
 		Ok(())
 	}
+	| 'ENUM' 'VAR' '{' EnumMemberList '}' ';'
+	{
+		let v = $2.map_err(|_| "VAR Err".to_string())?;
+		let typename = parse_string($lexer.span_str(v.span())).unwrap();
+		let members = $4?;
+		let mut mtt = TYPE_TABLE.lock().unwrap();
+		mtt.tinstall_enum(typename, members)?;
+		Ok(())
+	}
+	;
+
+EnumMemberList -> Result<Vec<String>,String>:
+	EnumMemberList ',' 'VAR'
+	{
+		let mut members = $1?;
+		let v = $3.map_err(|_| "VAR Err".to_string())?;
+		members.push(parse_string($lexer.span_str(v.span())).unwrap());
+		Ok(members)
+	}
+	| 'VAR'
+	{
+		let v = $1.map_err(|_| "VAR Err".to_string())?;
+		Ok(vec![parse_string($lexer.span_str(v.span())).unwrap()])
+	}
 	;
 
 FieldDeclList -> Result<LinkedList<Field>,String>:
@@ -890,13 +1284,14 @@ FieldDeclList -> Result<LinkedList<Field>,String>:
 	;
 
 FieldDecl -> Result<Field,String>:
-	CFType 'VAR' ';'
+	CFType VariableDef ';'
 	{
-		let v = $2.map_err(|_| "VAR Err".to_string())?; 
+		let var = $2?;
 		Ok(Field{
-			name:parse_string($lexer.span_str(v.span())).unwrap(),
+			name: var.varname,
 			field_type: $1?,
-			array_access: vec![],
+			varindices: var.varindices,
+			offset: 0,
 		})
 	}
 	;
@@ -982,13 +1377,13 @@ ClassFieldList -> Result<LinkedList<CSymbol>,String>:
 	;
 
 ClassField -> Result<LinkedList<CSymbol>, String>:
-	CFType 'VAR' ';' {
-		let v = $2.map_err(|_| "VAR Err".to_string())?; 
+	CFType VariableDef ';' {
+		let var = $2?;
 		Ok(LinkedList::from( CSymbol::Var{
-			name: $lexer.span_str(v.span()).to_owned(),
+			name: var.varname,
 			vartype: $1?,
 			varid: 0,
-			varindices: vec![],
+			varindices: var.varindices,
 		} ))
 	}
 	;
@@ -1045,5 +1440,62 @@ CFType-> Result<FieldType,String>:
     ;
 %%
 // Any functions here are in scope for all the grammar actions above.
+use crate::analysis::check_definite_assignment;
+use crate::options::ownership_checks;
+use crate::ownership::check_ownership;
 use crate::parserlib::{*};
+use crate::validation::{check_function_returns,check_main_returns,getarraylen,getfunctype,getvartype,warn_unused_globals,warn_unused_locals};
 use std::collections::{LinkedList,HashMap};
+
+/*
+ * Calls through a function pointer variable are indirect, everything else calls a declared function
+ */
+fn __func_call(fname: String, arglist: LinkedList<ASTNode>) -> ASTNode {
+	let ftype = getvartype(&fname);
+	match ftype {
+		Some(ASTExprType::Function(_)) => ASTNode::IndirectCallNode{
+			fptr: Box::new(ASTNode::VAR{
+				name: fname,
+				array_access: Vec::default(),
+				dot_field_access: Box::new(ASTNode::Void),
+				arrow_field_access: Box::new(ASTNode::Void),
+			}),
+			arglist: Box::new(arglist),
+		},
+		_ => ASTNode::FuncCallNode{
+			fname,
+			arglist: Box::new(arglist),
+		},
+	}
+}
+/*
+ * Variables can't take the void type
+ */
+fn __non_void(node: &VarNode) -> Result<(),String> {
+	if node.vartype == ASTExprType::Primitive(PrimitiveType::Void) {
+		return Err("Variable [".to_owned() + node.varname.as_str() + "] cannot be declared void.");
+	}
+	Ok(())
+}
+/*
+ * A call, or a field access chain ending in a method call
+ */
+fn __is_call(node: &ASTNode) -> bool {
+	match node {
+		ASTNode::FuncCallNode{..} | ASTNode::IndirectCallNode{..} => true,
+		ASTNode::VAR{ dot_field_access, arrow_field_access, .. } => {
+			__is_call(dot_field_access) || __is_call(arrow_field_access)
+		}
+		_ => false,
+	}
+}
+/*
+ * Records an inline or noinline mark of the function just defined
+ */
+fn __inline_hint(node: ASTNode, inline: bool) -> Result<ASTNode,String> {
+	if let ASTNode::FuncDefNode{ fname, .. } = &node {
+		let cname = CLASSNAME.lock().unwrap().clone();
+		INLINE_HINTS.lock().unwrap().insert(fname.clone() + "#" + cname.as_str(), inline);
+	}
+	Ok(node)
+}
@@ -6,6 +6,7 @@ use std::sync::Mutex;
 
 use crate::codegen::exit_on_err;
 use crate::codegen::LABEL_COUNT;
+use crate::exprtree::evaluate_const;
 use crate::validation::compare_arglist_paramlist;
 
 lazy_static! {
@@ -26,6 +27,22 @@ lazy_static! {
         Mutex::new(ASTExprType::Primitive(PrimitiveType::Null));
     pub static ref INITFLAG: Mutex<bool> = Mutex::new(false);
     pub static ref CLASSNAME: Mutex<String> = Mutex::new(String::new());
+    //constant initial values of globals, written into the data area by the header
+    pub static ref GLOBAL_INIT_TABLE: Mutex<HashMap<String, Vec<ASTNode>>> =
+        Mutex::new(HashMap::default());
+    //symbols referenced during validation, for the unused declaration warnings
+    pub static ref USES: Mutex<Uses> = Mutex::new(Uses::default());
+    //inline (true) and noinline (false) marks of functions, keyed like FUNCTION_TABLE
+    pub static ref INLINE_HINTS: Mutex<HashMap<String, bool>> = Mutex::new(HashMap::new());
+}
+#[derive(Debug, Default)]
+pub struct Uses {
+    //locals and params of the function being parsed
+    pub locals: HashSet<String>,
+    //global variables and functions
+    pub globals: HashSet<String>,
+    //reads of "type.field", assignments to a field don't count
+    pub fields: HashMap<String, i64>,
 }
 pub struct TypeTable {
     pub table: HashMap<String, ASTExprType>,
@@ -264,7 +281,7 @@ impl TypeTable {
                     name,
                     vartype,
                     varid,
-                    ..
+                    varindices,
                 } => {
                     self.validate_field_type(tname, &vartype)?;
                     if ctable.table.contains_key(name) {
@@ -275,7 +292,7 @@ impl TypeTable {
                             + "] is declared more than once.");
                     }
                     *varid = fieldid;
-                    fieldid += 1;
+                    fieldid += i64::try_from(self.field_size(vartype, varindices)?).unwrap();
                     ctable.table.insert(name.to_owned(), i.to_owned());
                 }
                 CSymbol::Func { .. } => unreachable!(),
@@ -294,10 +311,67 @@ impl TypeTable {
         );
         Ok(())
     }
+    /*
+     * Number of words a field occupies, arrays take every element
+     */
+    fn field_size(&self, t: &FieldType, varindices: &[usize]) -> Result<usize, String> {
+        let mut size = match t {
+            FieldType::Primitive(_) => 1,
+            FieldType::Pointer(_) => 1,
+            FieldType::Struct(s) | FieldType::Class(s) => self.tt_get_type(s)?.size()?,
+        };
+        for i in varindices.iter() {
+            size *= i;
+        }
+        Ok(size)
+    }
+    pub fn tinstall_enum(&mut self, tname: String, members: Vec<String>) -> Result<(), String> {
+        let map = &mut self.table;
+        if map.contains_key(&tname) {
+            return Err("Type [".to_owned() + &tname + "] is already declared.");
+        }
+        let mut membercheck: HashSet<String> = HashSet::new();
+        for m in members.iter() {
+            if !membercheck.insert(m.clone()) {
+                return Err("Enum [".to_owned()
+                    + &tname
+                    + "] declares member ["
+                    + m.as_str()
+                    + "] more than once.");
+            }
+        }
+        map.insert(
+            tname.clone(),
+            ASTExprType::Enum(ASTEnumType {
+                name: tname,
+                members,
+            }),
+        );
+        Ok(())
+    }
+    /*
+     * Constant for Enum.MEMBER
+     */
+    pub fn tt_get_enum_const(&self, tname: &String, member: &String) -> Result<ASTNode, String> {
+        match self.tt_get_type(tname)? {
+            ASTExprType::Enum(e) => match e.members.iter().position(|m| m == member) {
+                Some(value) => Ok(ASTNode::EnumConst {
+                    etype: ASTExprType::Enum(e.clone()),
+                    value: i64::try_from(value).unwrap(),
+                }),
+                None => Err("Enum [".to_owned()
+                    + tname.as_str()
+                    + "] has no member ["
+                    + member.as_str()
+                    + "]."),
+            },
+            _ => Err("Type [".to_owned() + tname.as_str() + "] is not an enum."),
+        }
+    }
     pub fn tinstall_struct(
         &mut self,
         tname: String,
-        tfields: LinkedList<Field>,
+        mut tfields: LinkedList<Field>,
     ) -> Result<(), String> {
         let map = &mut self.table;
         if map.contains_key(&tname) {
@@ -350,11 +424,17 @@ impl TypeTable {
             }
             fieldcheck.insert(i.name.clone());
         }
-        map.insert(
+        //fields are laid out in declaration order
+        let mut offset: usize = 0;
+        for i in tfields.iter_mut() {
+            i.offset = offset;
+            offset += self.field_size(&i.field_type, &i.varindices)?;
+        }
+        self.table.insert(
             tname.clone(),
             ASTExprType::Struct(ASTStructType {
                 name: tname.clone(),
-                size: tfields.len(),
+                size: offset,
                 fields: tfields,
             }),
         );
@@ -422,6 +502,7 @@ pub enum ASTNodeType {
     Write,
     //Heap
     Alloc,
+    AllocArray,
     Free,
     Initialize,
     //Connector/Blank Node
@@ -429,6 +510,8 @@ pub enum ASTNodeType {
     //Pointers
     Ref,
     Deref,
+    //Explicit conversion to exprtype
+    Cast,
     //Logical Operators
     Gt,
     Lt,
@@ -468,7 +551,8 @@ pub enum FieldType {
 pub struct Field {
     pub name: String,
     pub field_type: FieldType,
-    pub array_access: Vec<Box<Field>>,
+    pub varindices: Vec<usize>,
+    pub offset: usize,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -491,6 +575,18 @@ impl PartialEq for ASTClassType {
     }
 }
 impl Eq for ASTClassType {}
+//members are numbered from 0 in declaration order
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ASTEnumType {
+    pub name: String,
+    pub members: Vec<String>,
+}
+//signature of a function pointer, a variable of this type holds a function label address
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ASTFunctionType {
+    pub ret_type: Box<ASTExprType>,
+    pub params: Vec<ASTExprType>,
+}
 // an expression could be a primitive type or a pointer to a primitive type or so on..
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ASTExprType {
@@ -498,6 +594,10 @@ pub enum ASTExprType {
     Primitive(PrimitiveType),
     Pointer(Box<ASTExprType>),
     Struct(ASTStructType),
+    Enum(ASTEnumType),
+    Function(ASTFunctionType),
+    //return type of functions returning several values
+    Tuple(Vec<ASTExprType>),
     Error,
 }
 impl FieldType {
@@ -521,6 +621,9 @@ impl ASTExprType {
         match self {
             ASTExprType::Primitive(_) => Ok(1),
             ASTExprType::Pointer(_) => Ok(1),
+            ASTExprType::Enum(_) => Ok(1),
+            ASTExprType::Function(_) => Ok(1),
+            ASTExprType::Tuple(t) => Ok(t.len()),
             ASTExprType::Struct(s) => Ok(s.size),
             ASTExprType::Class(s) => Ok(usize::try_from(s.methodsize + s.fieldsize).unwrap()),
             ASTExprType::Error => {
@@ -565,7 +668,12 @@ impl ASTExprType {
             ASTExprType::Struct(s) => {
                 for i in s.fields.iter() {
                     if &i.name == fname {
-                        return i.field_type.as_astexprtype();
+                        //array fields are typed like array variables
+                        let mut ftype = i.field_type.as_astexprtype()?;
+                        for _ in i.varindices.iter() {
+                            ftype = ftype.refr().unwrap();
+                        }
+                        return Ok(ftype);
                     }
                 }
                 Err("Field [".to_owned()
@@ -578,8 +686,17 @@ impl ASTExprType {
                 if let Some(entry) = c.symbol_table.table.get(fname) {
                     match entry {
                         CSymbol::Var {
-                            name: _, vartype, ..
-                        } => vartype.as_astexprtype(),
+                            name: _,
+                            vartype,
+                            varid: _,
+                            varindices,
+                        } => {
+                            let mut ftype = vartype.as_astexprtype()?;
+                            for _ in varindices.iter() {
+                                ftype = ftype.refr().unwrap();
+                            }
+                            Ok(ftype)
+                        }
                         CSymbol::Func {
                             name: _, ret_type, ..
                         } => Ok(ret_type.clone()),
@@ -632,12 +749,10 @@ impl ASTExprType {
     pub fn get_field_id(&self, fname: &String) -> Result<usize, String> {
         match self {
             ASTExprType::Struct(s) => {
-                let mut len = 0;
                 for i in s.fields.iter() {
                     if &i.name == fname {
-                        return Ok(len);
+                        return Ok(i.offset);
                     }
-                    len += 1;
                 }
                 Err("Field [".to_owned()
                     + fname.as_str()
@@ -670,6 +785,31 @@ impl ASTExprType {
             _ => Err("Expression of this type cannot be accessed.".to_owned()),
         }
     }
+    /*
+     * Declared dimensions of a field, empty for non array fields
+     */
+    pub fn get_field_indices(&self, fname: &String) -> Result<Vec<usize>, String> {
+        match self {
+            ASTExprType::Struct(s) => {
+                for i in s.fields.iter() {
+                    if &i.name == fname {
+                        return Ok(i.varindices.clone());
+                    }
+                }
+                Err("Field [".to_owned()
+                    + fname.as_str()
+                    + "] not declared inside type ["
+                    + s.name.as_str()
+                    + "]")
+            }
+            ASTExprType::Class(c) => match c.symbol_table.table.get(fname) {
+                Some(CSymbol::Var { varindices, .. }) => Ok(varindices.clone()),
+                Some(CSymbol::Func { .. }) => Ok(vec![]),
+                None => Err("Symbol [".to_owned() + fname + "] not declared."),
+            },
+            _ => Err("Expression of this type cannot be accessed.".to_owned()),
+        }
+    }
     pub fn refr(&self) -> Option<ASTExprType> {
         match self {
             ASTExprType::Error => None,
@@ -682,6 +822,9 @@ impl ASTExprType {
             ASTExprType::Pointer(p) => Some((**p).clone()),
             ASTExprType::Struct { .. } => None,
             ASTExprType::Class { .. } => None,
+            ASTExprType::Enum { .. } => None,
+            ASTExprType::Function { .. } => None,
+            ASTExprType::Tuple { .. } => None,
             ASTExprType::Error => None,
         }
     }
@@ -693,6 +836,10 @@ impl ASTExprType {
             }
             ASTExprType::Struct { .. } => *self = p,
             ASTExprType::Class { .. } => *self = p,
+            ASTExprType::Enum { .. } => *self = p,
+            //the declared base type of a function pointer is its return type
+            ASTExprType::Function(f) => f.ret_type.set_base_type(p),
+            ASTExprType::Tuple { .. } => {}
             ASTExprType::Error => {}
         }
     }
@@ -702,6 +849,9 @@ impl ASTExprType {
             ASTExprType::Pointer(p) => Self::get_base_type(p),
             ASTExprType::Struct { .. } => self.clone(),
             ASTExprType::Class { .. } => self.clone(),
+            ASTExprType::Enum { .. } => self.clone(),
+            ASTExprType::Function { .. } => self.clone(),
+            ASTExprType::Tuple { .. } => self.clone(),
             ASTExprType::Error => ASTExprType::Primitive(PrimitiveType::Void),
         }
     }
@@ -711,9 +861,21 @@ impl ASTExprType {
             ASTExprType::Primitive(_) => 0,
             ASTExprType::Struct { .. } => 0,
             ASTExprType::Class { .. } => 0,
+            ASTExprType::Enum { .. } => 0,
+            ASTExprType::Function { .. } => 0,
+            ASTExprType::Tuple { .. } => 0,
             ASTExprType::Error => 0,
         }
     }
+    /*
+     * Words the caller reserves for the return value, one for each value of a tuple
+     */
+    pub fn ret_slots(&self) -> usize {
+        match self {
+            ASTExprType::Tuple(t) => t.len(),
+            _ => 1,
+        }
+    }
 }
 
 impl FieldType {
@@ -780,6 +942,14 @@ pub struct VarNode {
     pub varindices: Vec<usize>,
 }
 
+// right hand side of a declaration, `int x = 5` or `int a[3] = {1, 2, 3}`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Initializer {
+    Empty,
+    Expr(ASTNode),
+    List(LinkedList<ASTNode>),
+}
+
 impl VarNode {
     pub fn validate_locality(&mut self) {
         let lst = LOCALSYMBOLTABLE.lock().unwrap();
@@ -838,10 +1008,7 @@ impl VarNode {
                 varindices: (self.varindices.clone()),
             },
         );
-        let mut size = 1;
-        for i in self.varindices.iter() {
-            size *= i;
-        }
+        let size = self.vartype.size().unwrap() * self.element_count();
         *varid += i64::try_from(size).unwrap();
     }
     pub fn install_to_gst(self) {
@@ -871,6 +1038,9 @@ impl VarNode {
         let mut size = match &self.vartype {
             ASTExprType::Primitive(_) => 1,
             ASTExprType::Pointer(_) => 1,
+            ASTExprType::Enum(_) => 1,
+            ASTExprType::Function(_) => 1,
+            ASTExprType::Tuple(t) => t.len(),
             ASTExprType::Struct(s) => s.size.clone(),
             ASTExprType::Class(s) => {
                 usize::try_from(s.methodsize.clone() + s.fieldsize.clone()).unwrap()
@@ -882,6 +1052,146 @@ impl VarNode {
         }
         *varid += size;
     }
+    /*
+     * Number of elements this declaration holds, 1 for scalars
+     */
+    pub fn element_count(&self) -> usize {
+        let mut count = 1;
+        for i in self.varindices.iter() {
+            count *= i;
+        }
+        count
+    }
+    /*
+     * Flatten an initializer to one expression per element in row major order,
+     * elements left out of a list are zero filled
+     */
+    fn expand_initializer(&self, init: Initializer) -> Result<Vec<ASTNode>, String> {
+        match init {
+            Initializer::Empty => Ok(vec![]),
+            Initializer::Expr(e) => {
+                if !self.varindices.is_empty() {
+                    return Err("Array [".to_owned()
+                        + self.varname.as_str()
+                        + "] must be initialized with an initializer list.");
+                }
+                Ok(vec![e])
+            }
+            Initializer::List(l) => {
+                if self.varindices.is_empty() {
+                    return Err("Initializer list used for non array variable [".to_owned()
+                        + self.varname.as_str()
+                        + "].");
+                }
+                let count = self.element_count();
+                if l.len() > count {
+                    return Err("Too many initializers for array [".to_owned()
+                        + self.varname.as_str()
+                        + "].");
+                }
+                let mut values: Vec<ASTNode> = l.into_iter().collect();
+                while values.len() < count {
+                    match __zero_value(&self.vartype) {
+                        Some(z) => values.push(z),
+                        None => {
+                            return Err("Array [".to_owned()
+                                + self.varname.as_str()
+                                + "] needs an initializer for every element.")
+                        }
+                    }
+                }
+                Ok(values)
+            }
+        }
+    }
+    /*
+     * Access expression for the element at a row major position
+     */
+    fn element_access(&self, mut position: usize) -> Vec<Box<ASTNode>> {
+        let mut indices: Vec<Box<ASTNode>> = vec![];
+        for dim in self.varindices.iter().rev() {
+            indices.insert(
+                0,
+                Box::new(ASTNode::INT(i64::try_from(position % dim).unwrap())),
+            );
+            position /= dim;
+        }
+        indices
+    }
+    /*
+     * Assignment statements for a local initializer, these are emitted at function entry
+     * Must be called after the variable is installed to the local symbol table
+     */
+    pub fn local_initializer(&self, init: Initializer) -> Result<ASTNode, String> {
+        let mut stmts = ASTNode::Void;
+        for (position, value) in self.expand_initializer(init)?.into_iter().enumerate() {
+            let mut assg = ASTNode::BinaryNode {
+                op: ASTNodeType::Equals,
+                exprtype: Some(ASTExprType::Primitive(PrimitiveType::Void)),
+                lhs: Box::new(ASTNode::VAR {
+                    name: self.varname.clone(),
+                    array_access: self.element_access(position),
+                    dot_field_access: Box::new(ASTNode::Void),
+                    arrow_field_access: Box::new(ASTNode::Void),
+                }),
+                rhs: Box::new(value),
+            };
+            assg.validate()?;
+            stmts = ASTNode::BinaryNode {
+                op: ASTNodeType::Connector,
+                exprtype: Some(ASTExprType::Primitive(PrimitiveType::Void)),
+                lhs: Box::new(stmts),
+                rhs: Box::new(assg),
+            };
+        }
+        Ok(stmts)
+    }
+    /*
+     * Constant evaluate a global initializer into GLOBAL_INIT_TABLE
+     * Must be called after the variable is installed to the global symbol table
+     */
+    pub fn global_initializer(&self, init: Initializer) -> Result<(), String> {
+        let mut values: Vec<ASTNode> = vec![];
+        for value in self.expand_initializer(init)? {
+            let mut c = evaluate_const(&value).map_err(|e| {
+                "Initializer of global [".to_owned() + self.varname.as_str() + "]: " + e.as_str()
+            })?;
+            let ctype = c.getexprtype();
+            let valid = match (&self.vartype, &ctype) {
+                (ASTExprType::Pointer(_), Some(ASTExprType::Primitive(PrimitiveType::Null))) => {
+                    true
+                }
+                (t, Some(c)) => t == c,
+                _ => false,
+            };
+            if !valid {
+                return Err("Initializer of global [".to_owned()
+                    + self.varname.as_str()
+                    + "] has an invalid type.");
+            }
+            values.push(c);
+        }
+        if !values.is_empty() {
+            let mut git = GLOBAL_INIT_TABLE.lock().unwrap();
+            git.insert(self.varname.clone(), values);
+        }
+        Ok(())
+    }
+}
+/*
+ * Value used to fill array elements left out of an initializer list
+ */
+fn __zero_value(t: &ASTExprType) -> Option<ASTNode> {
+    match t {
+        ASTExprType::Primitive(PrimitiveType::Int) => Some(ASTNode::INT(0)),
+        ASTExprType::Primitive(PrimitiveType::String) => Some(ASTNode::STR("\"\"".to_owned())),
+        ASTExprType::Pointer(_) => Some(ASTNode::Null),
+        ASTExprType::Enum(_) => Some(ASTNode::EnumConst {
+            etype: t.clone(),
+            value: 0,
+        }),
+        _ => None,
+    }
 }
 
 pub fn install_func_to_gst(
@@ -916,6 +1226,10 @@ pub fn install_func_to_gst(
 pub enum ASTNode {
     INT(i64),
     STR(String),
+    EnumConst {
+        etype: ASTExprType,
+        value: i64,
+    },
     VAR {
         name: String,
         array_access: Vec<Box<ASTNode>>,
@@ -947,6 +1261,12 @@ pub enum ASTNode {
         expr: Box<ASTNode>,
         xdo: Box<ASTNode>,
     },
+    //cases hold (constant, body) pairs, default is Void when absent
+    SwitchNode {
+        expr: Box<ASTNode>,
+        cases: Vec<(ASTNode, ASTNode)>,
+        default: Box<ASTNode>,
+    },
     ClassNode {
         cname: String,
         methods: Box<LinkedList<ASTNode>>,
@@ -961,6 +1281,15 @@ pub enum ASTNode {
         fname: String,
         arglist: Box<LinkedList<ASTNode>>,
     },
+    //address of a declared function, &fname
+    FuncRefNode {
+        fname: String,
+    },
+    //call through a function pointer held by fptr
+    IndirectCallNode {
+        fptr: Box<ASTNode>,
+        arglist: Box<LinkedList<ASTNode>>,
+    },
     StdFuncCallNode {
         func: STDLibFunction,
         arglist: Box<LinkedList<ASTNode>>,
@@ -971,9 +1300,23 @@ pub enum ASTNode {
     ReturnNode {
         expr: Box<ASTNode>,
     },
+    //values of return (a, b);
+    TupleNode {
+        elems: Vec<ASTNode>,
+    },
+    //(x, y) = f(); stores each returned value into the matching variable
+    TupleAssgNode {
+        vars: Vec<ASTNode>,
+        call: Box<ASTNode>,
+    },
     MainNode {
         body: Box<ASTNode>,
     },
+    //a statement with the line and column it starts at
+    StmtNode {
+        pos: (usize, usize),
+        stmt: Box<ASTNode>,
+    },
     BreakNode,
     BreakpointNode,
     ContinueNode,
@@ -1016,6 +1359,23 @@ pub fn parse_usize(s: &str) -> Result<usize, ()> {
 pub fn parse_string(s: &str) -> Result<String, ()> {
     Ok(s.to_owned())
 }
+/*
+ * Target type of a cast like (int), (node*) or (str**)
+ */
+pub fn parse_cast_type(s: &str) -> Result<ASTExprType, String> {
+    let inner = s.trim_start_matches('(').trim_end_matches(')');
+    let depth = inner.matches('*').count();
+    let name = inner.replace('*', "").trim().to_owned();
+    let mut t = match name.as_str() {
+        "int" => ASTExprType::Primitive(PrimitiveType::Int),
+        "str" => ASTExprType::Primitive(PrimitiveType::String),
+        _ => TYPE_TABLE.lock().unwrap().tt_get_type(&name)?,
+    };
+    for _ in 0..depth {
+        t = ASTExprType::Pointer(Box::new(t));
+    }
+    Ok(t)
+}
 /*
  * Meta function
  * Get the type of a Global Symbol
@@ -1054,13 +1414,16 @@ pub fn __get_lsymbol_type(l: &LSymbol) -> &ASTExprType {
  */
 pub fn __lst_install_params(paramlist: &mut LinkedList<VarNode>) -> Result<(), String> {
     //Check if this variable is in Global Symbol Table
-    let mut localid = -3;
+    //params start below the return address and the return slots
+    let slots = RET_TYPE.lock().unwrap().ret_slots();
+    let mut localid = -2 - i64::try_from(slots).unwrap();
     for param in paramlist.iter_mut().rev() {
         param.validate_locality();
         let mut lst = LOCALSYMBOLTABLE.lock().unwrap();
         let mut siz = param.vartype.size()?;
-        for i in &param.varindices {
-            siz *= i;
+        //arrays are passed by their base address
+        if !param.varindices.is_empty() {
+            siz = 1;
         }
         lst.insert(
             param.varname.clone(),
@@ -1099,7 +1462,18 @@ impl std::fmt::Display for ASTExprType {
             ASTExprType::Primitive(p) => write!(f, "{}", p),
             ASTExprType::Struct(s) => write!(f, "struct_{}_t", s.name),
             ASTExprType::Class(s) => write!(f, "class_{}_t", s.name),
-            ASTExprType::Pointer(p) => write!(f, "{}{}", "*".repeat(p.depth()), p.get_base_type()),
+            ASTExprType::Enum(e) => write!(f, "enum_{}_t", e.name),
+            ASTExprType::Function(t) => {
+                let params: Vec<String> = t.params.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}(*)({})", t.ret_type, params.join(", "))
+            }
+            ASTExprType::Tuple(t) => {
+                let elems: Vec<String> = t.iter().map(|e| e.to_string()).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            ASTExprType::Pointer(p) => {
+                write!(f, "{}{}", "*".repeat(self.depth()), p.get_base_type())
+            }
         }
     }
 }
@@ -0,0 +1,181 @@
+//optimization passes and the pipelines of the -O levels
+use crate::codegen::get_function_label;
+use crate::constfold::fold_constants;
+use crate::cse::eliminate_common_subexpressions;
+use crate::dce::{eliminate_dead_code, remove_unreferenced};
+use crate::inline::inline_functions;
+use crate::ir::*;
+use crate::licm::{hoist_loop_invariants, instruction_counts};
+use crate::options::*;
+use crate::peephole::optimize_file;
+use crate::regalloc::promote_locals;
+use crate::tailcall::eliminate_tail_calls;
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy)]
+pub enum PassKind {
+    //whole program, may add or remove functions
+    Program(fn(&mut Program)),
+    //every function on its own
+    Function(fn(&mut Function)),
+    //the generated object file, after lowering
+    Object(fn(&str)),
+}
+
+#[derive(Clone, Copy)]
+pub struct Pass {
+    pub name: &'static str,
+    //lowest -O level running the pass
+    pub level: u8,
+    pub kind: PassKind,
+    //log the instruction counts of every function before and after the pass
+    pub report: bool,
+}
+
+fn __remove_unreferenced(program: &mut Program) {
+    remove_unreferenced(
+        program,
+        get_function_label(&"main".to_owned(), &String::default()),
+    );
+}
+
+//the pipeline in order, a pass may appear more than once
+pub const PASSES: &[Pass] = &[
+    Pass {
+        name: "inline",
+        level: 2,
+        kind: PassKind::Program(inline_functions),
+        report: false,
+    },
+    Pass {
+        name: "tailcall",
+        level: 2,
+        kind: PassKind::Function(eliminate_tail_calls),
+        report: false,
+    },
+    Pass {
+        name: "promote",
+        level: 1,
+        kind: PassKind::Function(promote_locals),
+        report: false,
+    },
+    Pass {
+        name: "constfold",
+        level: 1,
+        kind: PassKind::Function(fold_constants),
+        report: false,
+    },
+    Pass {
+        name: "dce",
+        level: 1,
+        kind: PassKind::Function(eliminate_dead_code),
+        report: false,
+    },
+    Pass {
+        name: "cse",
+        level: 2,
+        kind: PassKind::Function(eliminate_common_subexpressions),
+        report: true,
+    },
+    Pass {
+        name: "licm",
+        level: 2,
+        kind: PassKind::Function(hoist_loop_invariants),
+        report: true,
+    },
+    Pass {
+        name: "dce",
+        level: 1,
+        kind: PassKind::Function(eliminate_dead_code),
+        report: false,
+    },
+    Pass {
+        name: "dead-functions",
+        level: 1,
+        kind: PassKind::Program(__remove_unreferenced),
+        report: false,
+    },
+    Pass {
+        name: "peephole",
+        level: 1,
+        kind: PassKind::Object(optimize_file),
+        report: false,
+    },
+];
+
+lazy_static! {
+    //time spent in every pass run so far, in pipeline order
+    static ref PASS_TIMES: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(vec![]);
+}
+
+pub fn is_pass(name: &str) -> bool {
+    PASSES.iter().any(|p| p.name == name)
+}
+/*
+ * --pass and --no-pass override the level
+ */
+fn __enabled(pass: &Pass) -> bool {
+    pass_override(pass.name).unwrap_or(opt_level() >= pass.level)
+}
+fn __timed(pass: &Pass, run: impl FnOnce()) {
+    let start = Instant::now();
+    run();
+    let elapsed = start.elapsed();
+    let mut times = PASS_TIMES.lock().unwrap();
+    match times.iter_mut().find(|(name, _)| *name == pass.name) {
+        Some((_, total)) => *total += elapsed,
+        None => times.push((pass.name, elapsed)),
+    }
+}
+/*
+ * Runs the enabled program and function passes over the IR
+ */
+pub fn run_ir_passes(program: &mut Program) {
+    for pass in PASSES.iter().filter(|p| __enabled(p)) {
+        match pass.kind {
+            PassKind::Program(run) => __timed(pass, || run(program)),
+            PassKind::Function(run) => {
+                for function in program.functions.iter_mut() {
+                    let before = pass.report.then(|| instruction_counts(function));
+                    __timed(pass, || run(function));
+                    if let Some(before) = before {
+                        let after = instruction_counts(function);
+                        log::info!(
+                            "Function [{}] {}: {} instructions ({} in loops) -> {} ({} in loops)",
+                            function.name,
+                            pass.name,
+                            before.0,
+                            before.1,
+                            after.0,
+                            after.1
+                        );
+                    }
+                }
+            }
+            PassKind::Object(_) => {}
+        }
+    }
+}
+/*
+ * Runs the enabled passes over the object file at path
+ */
+pub fn run_object_passes(path: &str) {
+    for pass in PASSES.iter().filter(|p| __enabled(p)) {
+        if let PassKind::Object(run) = pass.kind {
+            __timed(pass, || run(path));
+        }
+    }
+}
+/*
+ * Time of every pass that ran, for --time-passes
+ */
+pub fn report_pass_times() {
+    let times = PASS_TIMES.lock().unwrap();
+    let total: Duration = times.iter().map(|(_, t)| *t).sum();
+    for (name, time) in times.iter() {
+        log::info!("Pass [{}] {:.3} ms", name, time.as_secs_f64() * 1000.0);
+    }
+    log::info!("Passes total {:.3} ms", total.as_secs_f64() * 1000.0);
+}
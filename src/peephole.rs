@@ -0,0 +1,457 @@
+//peephole optimizer over the generated xsm, run before linking
+use crate::codegen::exit_on_err;
+use std::collections::HashSet;
+use std::fs;
+
+//lines of the xsm header before the first instruction
+const HEADER_LINES: usize = 8;
+
+/*
+ * A rule looks at the instruction at index and gives back the number of
+ * lines it replaces and their replacement
+ */
+type Rule = fn(&[String], usize) -> Option<(usize, Vec<String>)>;
+
+const RULES: &[(&str, Rule)] = &[
+    ("push_pop", __push_pop),
+    ("self_move", __self_move),
+    ("arith_identity", __arith_identity),
+    ("combine_offsets", __combine_offsets),
+    ("jump_to_next", __jump_to_next),
+    ("unreachable_after_jump", __unreachable_after_jump),
+    ("jump_threading", __jump_threading),
+    ("store_load", __store_load),
+    ("dead_move", __dead_move),
+    ("address_reuse", __address_reuse),
+];
+
+/*
+ * Opcode and operands of an instruction, strings may contain commas
+ */
+fn __split(line: &str) -> (&str, Vec<&str>) {
+    let line = line.trim();
+    match line.split_once(' ') {
+        Some((opcode, rest)) => (opcode, rest.splitn(2, ',').map(|o| o.trim()).collect()),
+        None => (line, vec![]),
+    }
+}
+fn __is_label(line: &str) -> bool {
+    line.trim().ends_with(':')
+}
+fn __is_register(operand: &str) -> bool {
+    operand.len() > 1
+        && operand.starts_with('R')
+        && operand[1..].chars().all(|c| c.is_ascii_digit())
+}
+/*
+ * Whether the register appears in the operand, [R1] mentions R1 but R10 does not
+ */
+fn __mentions(operand: &str, register: &str) -> bool {
+    if operand.starts_with('"') {
+        return false;
+    }
+    operand
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|token| token == register)
+}
+fn __instruction(line: &str, opcode: &str) -> Option<Vec<String>> {
+    let (op, operands) = __split(line);
+    if op == opcode && !__is_label(line) {
+        Some(operands.into_iter().map(|o| o.to_owned()).collect())
+    } else {
+        None
+    }
+}
+/*
+ * Label of a JMP, JZ or JNZ and the rest of the instruction before it
+ */
+fn __jump_target(line: &str) -> Option<(String, String)> {
+    let (op, operands) = __split(line);
+    match (op, operands.as_slice()) {
+        ("JMP", [target]) => Some(("JMP ".to_owned(), target.to_string())),
+        ("JZ" | "JNZ", [reg, target]) => Some((format!("{} {}, ", op, reg), target.to_string())),
+        _ => None,
+    }
+}
+/*
+ * First instruction after the label, skipping other labels at the same address
+ */
+fn __after_label<'a>(code: &'a [String], label: &str) -> Option<&'a String> {
+    let definition = format!("{}:", label);
+    let start = code.iter().position(|l| l.trim() == definition)?;
+    code[start..].iter().find(|l| !__is_label(l))
+}
+
+//PUSH Rx; POP Rx does nothing, PUSH Rx; POP Ry is a move
+fn __push_pop(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let pushed = __instruction(&code[i], "PUSH")?;
+    let popped = __instruction(code.get(i + 1)?, "POP")?;
+    if pushed[0] == popped[0] {
+        Some((2, vec![]))
+    } else {
+        Some((2, vec![format!("MOV {}, {}", popped[0], pushed[0])]))
+    }
+}
+//MOV Rx, Rx
+fn __self_move(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let operands = __instruction(&code[i], "MOV")?;
+    if operands.len() == 2 && operands[0] == operands[1] && __is_register(&operands[0]) {
+        return Some((1, vec![]));
+    }
+    None
+}
+//ADD Rx, 0, SUB Rx, 0, MUL Rx, 1 and DIV Rx, 1
+fn __arith_identity(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let (op, operands) = __split(&code[i]);
+    match (op, operands.as_slice()) {
+        ("ADD" | "SUB", [_, "0"]) | ("MUL" | "DIV", [_, "1"]) => Some((1, vec![])),
+        _ => None,
+    }
+}
+//ADD Rx, a; SUB Rx, b becomes ADD Rx, a-b
+fn __combine_offsets(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let offset = |line: &str| -> Option<(String, i64)> {
+        let (op, operands) = __split(line);
+        let sign = match op {
+            "ADD" => 1,
+            "SUB" => -1,
+            _ => return None,
+        };
+        match operands.as_slice() {
+            [reg, n] if __is_register(reg) => {
+                Some((reg.to_string(), sign * n.parse::<i64>().ok()?))
+            }
+            _ => None,
+        }
+    };
+    let (reg, first) = offset(&code[i])?;
+    let (next, second) = offset(code.get(i + 1)?)?;
+    if reg != next {
+        return None;
+    }
+    let total = first.checked_add(second)?;
+    if total < 0 {
+        Some((2, vec![format!("SUB {}, {}", reg, -total)]))
+    } else {
+        Some((2, vec![format!("ADD {}, {}", reg, total)]))
+    }
+}
+//a jump to one of the labels right after it
+fn __jump_to_next(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let (_, target) = __jump_target(&code[i])?;
+    let definition = format!("{}:", target);
+    for line in code[i + 1..].iter() {
+        if !__is_label(line) {
+            break;
+        }
+        if line.trim() == definition {
+            return Some((1, vec![]));
+        }
+    }
+    None
+}
+//code after JMP or RET up to the next label is never run
+fn __unreachable_after_jump(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let (op, _) = __split(&code[i]);
+    let next = code.get(i + 1)?;
+    if (op == "JMP" || op == "RET") && !__is_label(next) {
+        return Some((2, vec![code[i].clone()]));
+    }
+    None
+}
+//a jump to a label holding JMP M goes to M directly
+fn __jump_threading(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let (prefix, target) = __jump_target(&code[i])?;
+    let mut seen = HashSet::from([target.clone()]);
+    let mut last = target.clone();
+    while let Some(line) = __after_label(code, &last) {
+        match __instruction(line, "JMP") {
+            Some(operands) => {
+                //jumps around a cycle stay as they are
+                if !seen.insert(operands[0].clone()) {
+                    return None;
+                }
+                last = operands[0].clone();
+            }
+            None => break,
+        }
+    }
+    if last == target {
+        return None;
+    }
+    Some((1, vec![prefix + last.as_str()]))
+}
+//MOV [A], Rx; MOV Rx, [A] and MOV Rx, [A]; MOV [A], Rx keep only the first
+fn __store_load(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let first = __instruction(&code[i], "MOV")?;
+    let second = __instruction(code.get(i + 1)?, "MOV")?;
+    if first.len() != 2 || second.len() != 2 {
+        return None;
+    }
+    let memory = |o: &str| o.starts_with('[');
+    let swapped = first[0] == second[1] && first[1] == second[0];
+    let (address, register) = if memory(&first[0]) {
+        (&first[0], &first[1])
+    } else {
+        (&first[1], &first[0])
+    };
+    if swapped && memory(address) && __is_register(register) && !__mentions(address, register) {
+        return Some((2, vec![code[i].clone()]));
+    }
+    None
+}
+//MOV Rx, a; MOV Rx, b where b does not read Rx
+fn __dead_move(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let first = __instruction(&code[i], "MOV")?;
+    let second = __instruction(code.get(i + 1)?, "MOV")?;
+    if first.len() == 2
+        && second.len() == 2
+        && __is_register(&first[0])
+        && first[0] == second[0]
+        && !__mentions(&second[1], &first[0])
+    {
+        return Some((2, vec![code[i + 1].clone()]));
+    }
+    None
+}
+//MOV Ra, BP; ADD Ra, k; X; MOV Ra, BP; ADD Ra, k computes the address once
+fn __address_reuse(code: &[String], i: usize) -> Option<(usize, Vec<String>)> {
+    let window = code.get(i..i + 5)?;
+    let base = __instruction(&window[0], "MOV")?;
+    if base.len() != 2 || base[1] != "BP" || !__is_register(&base[0]) {
+        return None;
+    }
+    let (op, _) = __split(&window[1]);
+    if (op != "ADD" && op != "SUB") || __split(&window[1]).1.first()? != &base[0] {
+        return None;
+    }
+    if window[3].trim() != window[0].trim() || window[4].trim() != window[1].trim() {
+        return None;
+    }
+    //X must leave both the address and BP alone
+    let (op, operands) = __split(&window[2]);
+    let writes = [
+        "MOV", "ADD", "SUB", "MUL", "DIV", "MOD", "GT", "LT", "GE", "LE", "EQ", "NE",
+    ];
+    let harmless = (writes.contains(&op)
+        && operands
+            .first()
+            .is_some_and(|d| *d != base[0] && *d != "BP"))
+        || op == "PUSH";
+    if !harmless || __is_label(&window[2]) {
+        return None;
+    }
+    Some((5, window[..3].to_vec()))
+}
+
+/*
+ * Applies the rules until none of them matches
+ */
+pub fn optimize(mut code: Vec<String>) -> Vec<String> {
+    loop {
+        let mut changed = false;
+        let mut out = Vec::with_capacity(code.len());
+        let mut i = 0;
+        while i < code.len() {
+            let rewrite = RULES.iter().find_map(|(_, rule)| rule(&code, i));
+            match rewrite {
+                Some((consumed, replacement)) => {
+                    out.extend(replacement);
+                    i += consumed;
+                    changed = true;
+                }
+                None => {
+                    out.push(code[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        code = out;
+        if !changed {
+            return code;
+        }
+    }
+}
+/*
+ * Rewrites the object file in place, the header is left as it is
+ */
+pub fn optimize_file(path: &str) {
+    let input = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            exit_on_err(e.to_string());
+            unreachable!()
+        }
+    };
+    let lines: Vec<String> = input.lines().map(|l| l.to_owned()).collect();
+    let split = HEADER_LINES.min(lines.len());
+    let mut output = lines[..split].to_vec();
+    output.extend(optimize(lines[split..].to_vec()));
+    if let Err(e) = fs::write(path, output.join("\n") + "\n") {
+        exit_on_err(e.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(code: &str) -> Vec<String> {
+        code.lines().map(|l| l.to_owned()).collect()
+    }
+    fn apply(rule: Rule, code: &str) -> Option<Vec<String>> {
+        let code = lines(code);
+        rule(&code, 0).map(|(consumed, mut replacement)| {
+            replacement.extend(code[consumed..].iter().cloned());
+            replacement
+        })
+    }
+
+    #[test]
+    fn push_pop() {
+        assert_eq!(apply(__push_pop, "PUSH R1\nPOP R1"), Some(vec![]));
+        assert_eq!(
+            apply(__push_pop, "PUSH R1\nPOP R2"),
+            Some(lines("MOV R2, R1"))
+        );
+        assert_eq!(apply(__push_pop, "PUSH R1\nADD SP, 1\nPOP R1"), None);
+    }
+
+    #[test]
+    fn self_move() {
+        assert_eq!(apply(__self_move, "MOV R3, R3"), Some(vec![]));
+        assert_eq!(apply(__self_move, "MOV R3, R13"), None);
+        assert_eq!(apply(__self_move, "MOV [R3], [R3]"), None);
+    }
+
+    #[test]
+    fn arith_identity() {
+        assert_eq!(apply(__arith_identity, "ADD R0, 0"), Some(vec![]));
+        assert_eq!(apply(__arith_identity, "SUB SP, 0"), Some(vec![]));
+        assert_eq!(apply(__arith_identity, "MUL R0, 1"), Some(vec![]));
+        assert_eq!(apply(__arith_identity, "DIV R0, 1"), Some(vec![]));
+        assert_eq!(apply(__arith_identity, "MUL R0, 0"), None);
+        assert_eq!(apply(__arith_identity, "ADD R0, 10"), None);
+    }
+
+    #[test]
+    fn combine_offsets() {
+        assert_eq!(
+            apply(__combine_offsets, "ADD R0, 3\nADD R0, 2"),
+            Some(lines("ADD R0, 5"))
+        );
+        assert_eq!(
+            apply(__combine_offsets, "SUB R0, 3\nADD R0, 1"),
+            Some(lines("SUB R0, 2"))
+        );
+        assert_eq!(apply(__combine_offsets, "ADD R0, 3\nADD R1, 2"), None);
+        assert_eq!(apply(__combine_offsets, "ADD R0, 3\nADD R0, R1"), None);
+    }
+
+    #[test]
+    fn jump_to_next() {
+        assert_eq!(apply(__jump_to_next, "JMP L2\nL2:"), Some(lines("L2:")));
+        assert_eq!(
+            apply(__jump_to_next, "JZ R0, L2\nL1:\nL2:\nRET"),
+            Some(lines("L1:\nL2:\nRET"))
+        );
+        assert_eq!(apply(__jump_to_next, "JMP L2\nRET\nL2:"), None);
+    }
+
+    #[test]
+    fn unreachable_after_jump() {
+        assert_eq!(
+            apply(__unreachable_after_jump, "JMP L1\nMOV R0, 1\nL2:"),
+            Some(lines("JMP L1\nL2:"))
+        );
+        assert_eq!(
+            apply(__unreachable_after_jump, "RET\nPOP R0"),
+            Some(lines("RET"))
+        );
+        assert_eq!(apply(__unreachable_after_jump, "JMP L1\nL2:"), None);
+        assert_eq!(
+            apply(__unreachable_after_jump, "JZ R0, L1\nMOV R0, 1"),
+            None
+        );
+    }
+
+    #[test]
+    fn jump_threading() {
+        assert_eq!(
+            apply(__jump_threading, "JMP L1\nL1:\nJMP L2\nL2:\nJMP L3"),
+            Some(lines("JMP L3\nL1:\nJMP L2\nL2:\nJMP L3"))
+        );
+        assert_eq!(
+            apply(__jump_threading, "JNZ R1, L1\nL1:\nL4:\nJMP L2"),
+            Some(lines("JNZ R1, L2\nL1:\nL4:\nJMP L2"))
+        );
+        assert_eq!(
+            apply(__jump_threading, "JMP L1\nL1:\nJMP L2\nL2:\nJMP L1"),
+            None
+        );
+        assert_eq!(apply(__jump_threading, "JMP L1\nL1:\nRET"), None);
+    }
+
+    #[test]
+    fn store_load() {
+        assert_eq!(
+            apply(__store_load, "MOV [R1], R0\nMOV R0, [R1]"),
+            Some(lines("MOV [R1], R0"))
+        );
+        assert_eq!(
+            apply(__store_load, "MOV R0, [4096]\nMOV [4096], R0"),
+            Some(lines("MOV R0, [4096]"))
+        );
+        assert_eq!(apply(__store_load, "MOV R0, [R0]\nMOV [R0], R0"), None);
+        assert_eq!(apply(__store_load, "MOV [R1], R0\nMOV R2, [R1]"), None);
+    }
+
+    #[test]
+    fn dead_move() {
+        assert_eq!(
+            apply(__dead_move, "MOV R1, 5\nMOV R1, R2"),
+            Some(lines("MOV R1, R2"))
+        );
+        assert_eq!(apply(__dead_move, "MOV R1, 5\nMOV R1, [R1]"), None);
+        assert_eq!(
+            apply(__dead_move, "MOV R1, 5\nMOV R1, [R10]"),
+            Some(lines("MOV R1, [R10]"))
+        );
+        assert_eq!(apply(__dead_move, "MOV [R1], 5\nMOV [R1], R2"), None);
+    }
+
+    #[test]
+    fn address_reuse() {
+        assert_eq!(
+            apply(
+                __address_reuse,
+                "MOV R19, BP\nADD R19, 4\nMOV R18, [R19]\nMOV R19, BP\nADD R19, 4\nMOV [R19], R17"
+            ),
+            Some(lines(
+                "MOV R19, BP\nADD R19, 4\nMOV R18, [R19]\nMOV [R19], R17"
+            ))
+        );
+        assert_eq!(
+            apply(
+                __address_reuse,
+                "MOV R19, BP\nADD R19, 4\nMOV R19, [R19]\nMOV R19, BP\nADD R19, 4"
+            ),
+            None
+        );
+        assert_eq!(
+            apply(
+                __address_reuse,
+                "MOV R19, BP\nADD R19, 4\nCALL L1\nMOV R19, BP\nADD R19, 4"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn optimize_reaches_fixed_point() {
+        assert_eq!(
+            optimize(lines("PUSH R1\nPUSH R2\nPOP R2\nPOP R1\nJMP L1\nL1:\nRET")),
+            lines("L1:\nRET")
+        );
+    }
+}
@@ -0,0 +1,359 @@
+//liveness analysis and register allocation of the IR
+use crate::ir::*;
+use std::collections::{HashMap, HashSet};
+
+//R0-R16 hold virtual registers, the rest are scratch registers of the lowering
+pub const ALLOCATABLE_REGISTERS: usize = 17;
+//R0-R7 may be clobbered by a call, R8-R16 are saved by the callee that uses them
+pub const CALLER_SAVED: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Reg(usize),
+    //k-th word after the locals of the frame
+    Slot(i64),
+}
+
+//first and last position a virtual register is live at
+#[derive(Debug, Clone, Copy)]
+pub struct Interval {
+    pub vreg: VReg,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub locations: Vec<Location>,
+    pub intervals: Vec<Interval>,
+    pub slots: i64,
+    //callee saved registers the function writes, saved in its prologue
+    pub callee_saved: Vec<usize>,
+}
+
+/*
+ * Frame offset every FrameAddr register points at
+ */
+fn __frame_addrs(function: &Function) -> HashMap<VReg, i64> {
+    let mut frame_addrs: HashMap<VReg, i64> = HashMap::new();
+    for block in function.blocks.iter() {
+        for inst in block.insts.iter() {
+            if let Inst::FrameAddr { dst, offset } = inst {
+                frame_addrs.insert(*dst, *offset);
+            }
+        }
+    }
+    frame_addrs
+}
+/*
+ * Frame words whose address escapes, a FrameAddr used other than as the
+ * address of a load or a store
+ */
+pub fn escaped_frame_words(function: &Function) -> HashSet<i64> {
+    let frame_addrs = __frame_addrs(function);
+    let mut escaped: HashSet<i64> = HashSet::new();
+    for block in function.blocks.iter() {
+        for inst in block.insts.iter() {
+            let mut uses = inst.uses();
+            match inst {
+                Inst::Load {
+                    dst: _,
+                    addr: Operand::Reg(a),
+                }
+                | Inst::Store {
+                    addr: Operand::Reg(a),
+                    src: _,
+                } => {
+                    //the address operand itself is a direct access
+                    let i = uses.iter().position(|u| u == a).unwrap();
+                    uses.remove(i);
+                }
+                _ => {}
+            }
+            for v in uses {
+                if let Some(offset) = frame_addrs.get(&v) {
+                    escaped.insert(*offset);
+                }
+            }
+        }
+        for v in block.term.uses() {
+            if let Some(offset) = frame_addrs.get(&v) {
+                escaped.insert(*offset);
+            }
+        }
+    }
+    escaped
+}
+/*
+ * Scalar locals and parameters whose address never escapes are kept in
+ * virtual registers instead of the frame
+ * A frame word qualifies when every FrameAddr of it is only used as the
+ * address of a load or a store
+ */
+pub fn promote_locals(function: &mut Function) {
+    let frame_addrs = __frame_addrs(function);
+    let escaped = escaped_frame_words(function);
+    let mut promoted: HashMap<i64, VReg> = HashMap::new();
+    let mut offsets: Vec<i64> = frame_addrs
+        .values()
+        .filter(|o| !escaped.contains(*o))
+        .copied()
+        .collect();
+    offsets.sort();
+    offsets.dedup();
+    for offset in offsets {
+        promoted.insert(offset, function.vregs);
+        function.vregs += 1;
+    }
+    let variable = |a: &Operand| match a {
+        Operand::Reg(v) => frame_addrs.get(v).and_then(|o| promoted.get(o)).copied(),
+        _ => None,
+    };
+    for block in function.blocks.iter_mut() {
+        let insts = std::mem::take(&mut block.insts);
+        for inst in insts.into_iter() {
+            match &inst {
+                Inst::FrameAddr { dst, offset: _ } if variable(&Operand::Reg(*dst)).is_some() => {}
+                Inst::Load { dst, addr } if variable(addr).is_some() => {
+                    block.insts.push(Inst::Copy {
+                        dst: *dst,
+                        src: Operand::Reg(variable(addr).unwrap()),
+                    });
+                }
+                Inst::Store { addr, src } if variable(addr).is_some() => {
+                    block.insts.push(Inst::Copy {
+                        dst: variable(addr).unwrap(),
+                        src: src.clone(),
+                    });
+                }
+                _ => block.insts.push(inst),
+            }
+        }
+    }
+    //parameters are read from the frame once on entry
+    let mut entry = vec![];
+    let mut params: Vec<(&i64, &VReg)> = promoted.iter().filter(|(o, _)| **o < 0).collect();
+    params.sort();
+    for (offset, var) in params {
+        let addr = function.vregs;
+        function.vregs += 1;
+        entry.push(Inst::FrameAddr {
+            dst: addr,
+            offset: *offset,
+        });
+        entry.push(Inst::Load {
+            dst: *var,
+            addr: Operand::Reg(addr),
+        });
+    }
+    entry.append(&mut function.blocks[0].insts);
+    function.blocks[0].insts = entry;
+}
+/*
+ * Virtual registers live on entry and on exit of every block, iterated
+ * to a fixed point
+ */
+pub fn liveness(function: &Function) -> (Vec<HashSet<VReg>>, Vec<HashSet<VReg>>) {
+    let n = function.blocks.len();
+    let mut gen: Vec<HashSet<VReg>> = vec![HashSet::new(); n];
+    let mut kill: Vec<HashSet<VReg>> = vec![HashSet::new(); n];
+    for (b, block) in function.blocks.iter().enumerate() {
+        for inst in block.insts.iter() {
+            for v in inst.uses() {
+                if !kill[b].contains(&v) {
+                    gen[b].insert(v);
+                }
+            }
+            kill[b].extend(inst.defs());
+        }
+        for v in block.term.uses() {
+            if !kill[b].contains(&v) {
+                gen[b].insert(v);
+            }
+        }
+    }
+    let mut live_in: Vec<HashSet<VReg>> = gen.clone();
+    let mut live_out: Vec<HashSet<VReg>> = vec![HashSet::new(); n];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in (0..n).rev() {
+            let mut out = HashSet::new();
+            for s in function.blocks[b].term.successors() {
+                out.extend(live_in[s].iter().copied());
+            }
+            let mut inn = gen[b].clone();
+            inn.extend(out.iter().filter(|v| !kill[b].contains(v)));
+            if inn.len() != live_in[b].len() || out.len() != live_out[b].len() {
+                changed = true;
+            }
+            live_in[b] = inn;
+            live_out[b] = out;
+        }
+    }
+    (live_in, live_out)
+}
+/*
+ * Intervals over the blocks laid out in order, every block has an entry
+ * position before its instructions and one for its terminator
+ * Holes are ignored, a register is live from its first to its last point
+ */
+fn __live_intervals(function: &Function, order: &[BlockId]) -> Vec<Interval> {
+    let (live_in, live_out) = liveness(function);
+    let mut intervals: Vec<Option<Interval>> = vec![None; function.vregs];
+    let mut touch = |v: VReg, p: usize| match &mut intervals[v] {
+        Some(i) => {
+            i.start = i.start.min(p);
+            i.end = i.end.max(p);
+        }
+        None => {
+            intervals[v] = Some(Interval {
+                vreg: v,
+                start: p,
+                end: p,
+            })
+        }
+    };
+    let mut position = 0;
+    for b in order.iter() {
+        let block = &function.blocks[*b];
+        for v in live_in[*b].iter() {
+            touch(*v, position);
+        }
+        position += 1;
+        for inst in block.insts.iter() {
+            for v in inst.uses().into_iter().chain(inst.defs()) {
+                touch(v, position);
+            }
+            position += 1;
+        }
+        for v in block.term.uses() {
+            touch(v, position);
+        }
+        for v in live_out[*b].iter() {
+            touch(*v, position);
+        }
+        position += 1;
+    }
+    let mut intervals: Vec<Interval> = intervals.into_iter().flatten().collect();
+    intervals.sort_by_key(|i| (i.start, i.vreg));
+    intervals
+}
+/*
+ * Instructions with their positions, numbered as in __live_intervals
+ */
+fn __positions<'a>(function: &'a Function, order: &[BlockId]) -> Vec<(usize, &'a Inst)> {
+    let mut positions = vec![];
+    let mut position = 0;
+    for b in order.iter() {
+        position += 1;
+        for inst in function.blocks[*b].insts.iter() {
+            positions.push((position, inst));
+            position += 1;
+        }
+        position += 1;
+    }
+    positions
+}
+/*
+ * Linear scan, gives every virtual register a machine register or a spill slot
+ * A copy whose source dies at it reuses the register of the source
+ * Registers live across a call prefer callee saved registers, the rest
+ * prefer caller saved ones so short lived values cost no saves
+ * Operands expire only after the position of their last use, so a
+ * result never shares a register with an operand of its instruction
+ */
+pub fn allocate(function: &Function, order: &[BlockId]) -> Allocation {
+    let intervals = __live_intervals(function, order);
+    let positions = __positions(function, order);
+    //only calls to compiled functions respect the caller/callee saved split
+    let calls: Vec<usize> = positions
+        .iter()
+        .filter(|(_, inst)| matches!(inst, Inst::Call { .. }))
+        .map(|(p, _)| *p)
+        .collect();
+    //a copy may take over the register of a source that dies at it
+    let mut copies: HashMap<(VReg, usize), VReg> = HashMap::new();
+    for (p, inst) in positions.iter() {
+        if let Inst::Copy {
+            dst,
+            src: Operand::Reg(src),
+        } = inst
+        {
+            copies.insert((*dst, *p), *src);
+        }
+    }
+    let mut locations = vec![Location::Slot(0); function.vregs];
+    let mut free_regs = [true; ALLOCATABLE_REGISTERS];
+    let mut used_regs = [false; ALLOCATABLE_REGISTERS];
+    let mut free_slots: Vec<i64> = vec![];
+    let mut nslots = 0;
+    //intervals currently holding a register or a slot
+    let mut active: Vec<Interval> = vec![];
+    for current in intervals.iter() {
+        active.retain(|i| {
+            if i.end >= current.start {
+                return true;
+            }
+            match locations[i.vreg] {
+                Location::Reg(r) => free_regs[r] = true,
+                Location::Slot(s) => free_slots.push(s),
+            }
+            false
+        });
+        let mut slot = || {
+            free_slots.pop().unwrap_or_else(|| {
+                nslots += 1;
+                nslots - 1
+            })
+        };
+        if let Some(src) = copies.get(&(current.vreg, current.start)) {
+            let dying = active.iter().position(|i| {
+                i.vreg == *src
+                    && i.end == current.start
+                    && matches!(locations[i.vreg], Location::Reg(_))
+            });
+            if let Some(i) = dying {
+                active.remove(i);
+                locations[current.vreg] = locations[*src];
+                active.push(*current);
+                continue;
+            }
+        }
+        let crosses_call = calls.iter().any(|p| current.start < *p && *p < current.end);
+        let (caller, callee) = (0..CALLER_SAVED, CALLER_SAVED..ALLOCATABLE_REGISTERS);
+        let preferred = if crosses_call {
+            callee.chain(caller).collect::<Vec<usize>>()
+        } else {
+            caller.chain(callee).collect::<Vec<usize>>()
+        };
+        if let Some(r) = preferred.into_iter().find(|r| free_regs[*r]) {
+            free_regs[r] = false;
+            used_regs[r] = true;
+            locations[current.vreg] = Location::Reg(r);
+        } else {
+            //the interval ending last goes to the stack
+            let victim = active
+                .iter()
+                .filter(|i| matches!(locations[i.vreg], Location::Reg(_)))
+                .max_by_key(|i| i.end)
+                .copied()
+                .unwrap();
+            if victim.end > current.end {
+                locations[current.vreg] = locations[victim.vreg];
+                locations[victim.vreg] = Location::Slot(slot());
+            } else {
+                locations[current.vreg] = Location::Slot(slot());
+            }
+        }
+        active.push(*current);
+    }
+    Allocation {
+        locations,
+        intervals,
+        slots: nslots,
+        callee_saved: (CALLER_SAVED..ALLOCATABLE_REGISTERS)
+            .filter(|r| used_regs[*r])
+            .collect(),
+    }
+}
@@ -0,0 +1,107 @@
+//tail call elimination of self recursive calls
+use crate::ir::*;
+use crate::regalloc::escaped_frame_words;
+use std::collections::HashMap;
+
+/*
+ * Call of the function itself whose results are returned unchanged, the
+ * block may only copy the results between the call and the return
+ */
+fn __tail_call(function: &Function, block: &Block) -> Option<usize> {
+    let position = block.insts.iter().rposition(|i| i.is_call())?;
+    let rets = match &block.insts[position] {
+        Inst::Call {
+            callee: Callee::Direct(label),
+            args: _,
+            rets,
+        } if *label == function.label => rets,
+        _ => return None,
+    };
+    //which result every copy of a result holds
+    let mut results: HashMap<VReg, usize> = rets.iter().enumerate().map(|(i, r)| (*r, i)).collect();
+    for inst in block.insts[position + 1..].iter() {
+        match inst {
+            Inst::Copy {
+                dst,
+                src: Operand::Reg(src),
+            } if results.contains_key(src) => {
+                let i = results[src];
+                results.insert(*dst, i);
+            }
+            _ => return None,
+        }
+    }
+    match &block.term {
+        //a void function returns nothing whatever the call returned
+        Terminator::Return(values) if values.is_empty() => Some(position),
+        Terminator::Return(values) if values.len() == rets.len() => {
+            let returned = values.iter().enumerate().all(|(i, v)| match v {
+                Operand::Reg(v) => results.get(v) == Some(&i),
+                _ => false,
+            });
+            returned.then_some(position)
+        }
+        _ => None,
+    }
+}
+/*
+ * A function returning the result of a call to itself overwrites its own
+ * parameters with the arguments and jumps back to its start, reusing the
+ * frame instead of pushing a new one
+ * Only done when every parameter is one word and no local or parameter
+ * has its address taken, the reused frame would change what it points to
+ */
+pub fn eliminate_tail_calls(function: &mut Function) {
+    if !function.one_word_params() || !escaped_frame_words(function).is_empty() {
+        return;
+    }
+    let tail_calls: Vec<(BlockId, usize)> = function
+        .blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(b, block)| __tail_call(function, block).map(|p| (b, p)))
+        .collect();
+    if tail_calls.is_empty() {
+        return;
+    }
+    //the body moves after a new entry block, so code placed at the entry
+    //later runs once and not on every iteration
+    function.insert_blocks(
+        0,
+        vec![Block {
+            insts: vec![],
+            term: Terminator::Jump(1),
+        }],
+    );
+    for (b, position) in tail_calls {
+        let block = &mut function.blocks[b + 1];
+        let args = match block.insts.drain(position..).next() {
+            Some(Inst::Call { args, .. }) => args,
+            _ => unreachable!(),
+        };
+        //every argument is read before any parameter is written
+        let mut temps = vec![];
+        for arg in args {
+            let temp = function.vregs;
+            function.vregs += 1;
+            block.insts.push(Inst::Copy {
+                dst: temp,
+                src: arg,
+            });
+            temps.push(temp);
+        }
+        for (offset, temp) in function.params.iter().zip(temps) {
+            let addr = function.vregs;
+            function.vregs += 1;
+            block.insts.push(Inst::FrameAddr {
+                dst: addr,
+                offset: *offset,
+            });
+            block.insts.push(Inst::Store {
+                addr: Operand::Reg(addr),
+                src: Operand::Reg(temp),
+            });
+        }
+        block.term = Terminator::Jump(1);
+    }
+}
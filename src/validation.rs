@@ -1,4 +1,5 @@
 use crate::codegen::*;
+use crate::options::*;
 use crate::parserlib::*;
 use std::collections::LinkedList;
 
@@ -31,13 +32,46 @@ pub fn getvartype(name: &String) -> Option<ASTExprType> {
     }
     None
 }
-#[allow(dead_code)]
+/*
+ * Signature of a declared function, None if the name is a variable or not a function
+ */
+pub fn getfunctype(name: &String) -> Option<ASTExprType> {
+    if getvartype(name).is_some() {
+        return None;
+    }
+    let gst = GLOBALSYMBOLTABLE.lock().unwrap();
+    if let Some(GSymbol::Func {
+        ret_type,
+        paramlist,
+        flabel: _,
+    }) = gst.get(name)
+    {
+        let params = paramlist
+            .iter()
+            .map(|p| {
+                let mut ptype = p.vartype.clone();
+                for _ in p.varindices.iter() {
+                    ptype = ptype.refr().unwrap();
+                }
+                ptype
+            })
+            .collect();
+        return Some(ASTExprType::Function(ASTFunctionType {
+            ret_type: Box::new(ret_type.clone()),
+            params,
+        }));
+    }
+    None
+}
 fn validate_field_array_access(
     array_name: &String,
     parent_type: &ASTExprType,
     array_access: &mut Vec<Box<ASTNode>>,
 ) -> Result<(), String> {
-    let _actual_array_type = parent_type.get_field_type(array_name)?;
+    let dind = parent_type.get_field_indices(array_name)?;
+    if array_access.len() > dind.len() {
+        return Err("Index dimension error for field [".to_owned() + array_name.as_str() + "]");
+    }
     for ei in 0..array_access.len() {
         array_access[ei].validate()?;
         if let Some(ei_type) = array_access[ei].getexprtype() {
@@ -127,8 +161,9 @@ impl ASTNode {
                 arrow_field_access,
             } => {
                 varinscope(&name)?;
+                mark_use(name);
                 let dind = getvarindices(&name).unwrap();
-                if array_access.len() > dind.len() {
+                if array_access.len() > dind.len() && !is_pointer_index(name, array_access.len()) {
                     return Err("Index dimension error for variable [".to_owned()
                         + name.as_str()
                         + "]");
@@ -173,13 +208,9 @@ impl ASTNode {
                             dot_field_access,
                             arrow_field_access,
                         } => {
-                            if array_access.len() > 0 {
-                                return Err(
-                                    "Arrays inside struct is not implemented yet!".to_owned()
-                                );
-                            }
                             currtype.get_field_id(&nname)?;
-                            //validate_field_array_access(nname, &currtype, array_access)?;
+                            validate_field_array_access(nname, &currtype, array_access)?;
+                            __mark_field_read(&currtype, nname, 1);
 
                             currtype = currtype.get_field_type(nname)?;
                             for _ in 0..array_access.len() {
@@ -215,13 +246,10 @@ impl ASTNode {
                             dot_field_access,
                             arrow_field_access,
                         } => {
-                            if array_access.len() > 0 {
-                                return Err(
-                                    "Arrays inside struct is not implemented yet!".to_owned()
-                                );
-                            }
                             if let ASTExprType::Pointer(etype) = &currtype {
                                 etype.get_field_id(&nname)?;
+                                validate_field_array_access(nname, etype, array_access)?;
+                                __mark_field_read(etype, nname, 1);
                                 currtype = etype.get_field_type(nname)?;
                                 for _ in 0..array_access.len() {
                                     currtype = currtype.derefr().unwrap();
@@ -234,7 +262,6 @@ impl ASTNode {
                                     "Arrow operator can only be used to pointer types".to_owned()
                                 );
                             }
-                            //validate_field_array_access(nname, &currtype, array_access)?;
                         }
                         ASTNode::FuncCallNode { fname, arglist } => {
                             //check if currtype is class
@@ -275,6 +302,46 @@ impl ASTNode {
                 }
                 Ok(())
             }
+            ASTNode::SwitchNode {
+                expr,
+                cases,
+                default,
+            } => {
+                let t = expr.getexprtype();
+                let enumtype = match &t {
+                    Some(ASTExprType::Primitive(PrimitiveType::Int)) => None,
+                    Some(ASTExprType::Enum(e)) => Some(e.clone()),
+                    _ => return Err("Switch expects an int or enum expression.".to_owned()),
+                };
+                let mut seen: Vec<i64> = Vec::default();
+                for (value, _) in cases.iter_mut() {
+                    let v = match value {
+                        ASTNode::INT(v) => *v,
+                        ASTNode::EnumConst { etype: _, value } => *value,
+                        _ => return Err("Case values must be int or enum constants.".to_owned()),
+                    };
+                    if value.getexprtype() != t {
+                        return Err("Case value [".to_owned()
+                            + v.to_string().as_str()
+                            + "] doesn't match the type of the switch expression.");
+                    }
+                    if seen.contains(&v) {
+                        return Err("Case value [".to_owned()
+                            + v.to_string().as_str()
+                            + "] is handled more than once.");
+                    }
+                    seen.push(v);
+                }
+                //an enum switch without default should handle every member
+                if let (Some(e), ASTNode::Void) = (enumtype, &**default) {
+                    for (i, m) in e.members.iter().enumerate() {
+                        if !seen.contains(&i64::try_from(i).unwrap()) {
+                            log::warn!("Switch over enum [{}] does not handle [{}].", e.name, m);
+                        }
+                    }
+                }
+                Ok(())
+            }
             ASTNode::WhileNode { expr, xdo: _ } => {
                 if expr.getexprtype() != Some(ASTExprType::Primitive(PrimitiveType::Bool)) {
                     return Err("Invalid expression inside while's condition.".to_owned());
@@ -299,6 +366,19 @@ impl ASTNode {
             }
             ASTNode::ReturnNode { expr } => {
                 let ct = RET_TYPE.lock().unwrap().clone();
+                let void = ASTExprType::Primitive(PrimitiveType::Void);
+                match (ct == void, **expr == ASTNode::Void) {
+                    (true, true) => return Ok(()),
+                    (true, false) => {
+                        return Err("A void function cannot return a value.".to_owned())
+                    }
+                    (false, true) => {
+                        return Err("Function must return a value of type [".to_owned()
+                            + ct.to_string().as_str()
+                            + "].")
+                    }
+                    (false, false) => {}
+                }
                 let b = expr.getexprtype();
                 if b == Some(ASTExprType::Primitive(PrimitiveType::Null)) {
                     if let ASTExprType::Pointer(_) = ct {
@@ -312,10 +392,64 @@ impl ASTNode {
             }
             ASTNode::UnaryNode {
                 op,
-                exprtype: _,
+                exprtype,
                 ptr,
                 depth,
             } => match op {
+                ASTNodeType::Cast => {
+                    let from = ptr.getexprtype();
+                    let to = exprtype.clone();
+                    match (&from, &to) {
+                        (
+                            Some(ASTExprType::Primitive(PrimitiveType::Int)),
+                            Some(ASTExprType::Enum(_)),
+                        ) => Ok(()),
+                        (
+                            Some(ASTExprType::Enum(_)),
+                            Some(ASTExprType::Primitive(PrimitiveType::Int)),
+                        ) => Ok(()),
+                        //pointers can be reinterpreted and converted to raw addresses
+                        (Some(ASTExprType::Pointer(_)), Some(ASTExprType::Pointer(_))) => Ok(()),
+                        (
+                            Some(ASTExprType::Primitive(PrimitiveType::Int)),
+                            Some(ASTExprType::Pointer(_)),
+                        ) => Ok(()),
+                        (
+                            Some(ASTExprType::Pointer(_)),
+                            Some(ASTExprType::Primitive(PrimitiveType::Int)),
+                        ) => Ok(()),
+                        (
+                            Some(ASTExprType::Primitive(PrimitiveType::Null)),
+                            Some(ASTExprType::Pointer(_)),
+                        ) => Ok(()),
+                        _ if from == to => Ok(()),
+                        _ => Err("Invalid cast from [".to_owned()
+                            + from.unwrap_or(ASTExprType::Error).to_string().as_str()
+                            + "] to ["
+                            + to.unwrap_or(ASTExprType::Error).to_string().as_str()
+                            + "]."),
+                    }
+                }
+                ASTNodeType::AllocArray => {
+                    if ptr.getexprtype() != Some(ASTExprType::Primitive(PrimitiveType::Int)) {
+                        return Err("alloc_array expects an int element count.".to_owned());
+                    }
+                    //constant counts can be checked against the allocator here
+                    if let (ASTNode::INT(n), Some(ASTExprType::Pointer(t))) = (&**ptr, exprtype) {
+                        if *n < 0 {
+                            return Err("alloc_array got a negative element count.".to_owned());
+                        }
+                        let size = t.size()? * usize::try_from(*n).unwrap();
+                        if get_allocator() == Allocator::Library && size > 8 {
+                            return Err("alloc_array needs ".to_owned()
+                                + size.to_string().as_str()
+                                + " words, the library allocator can only allocate 8. Use --allocator=builtin for larger arrays.");
+                        }
+                    } else if get_allocator() == Allocator::Library {
+                        log::warn!("alloc_array of a count known only at run time gives back -1 above the 8 words of the library allocator. Use --allocator=builtin for larger arrays, or --checked to stop there.");
+                    }
+                    Ok(())
+                }
                 ASTNodeType::Free => {
                     if let Some(ASTExprType::Pointer(_)) = ptr.getexprtype() {
                         Ok(())
@@ -337,8 +471,9 @@ impl ASTNode {
                         dot_field_access: _,
                         arrow_field_access: _,
                     } => {
-                        if let Some(ASTExprType::Pointer(_)) = ptr.getexprtype() {
-                            Ok(())
+                        __unmark_field_target(ptr);
+                        if let Some(ASTExprType::Pointer(p)) = ptr.getexprtype() {
+                            __validate_alloc_size(&p)
                         } else {
                             Err("Alloc can only be used on pointer types.".to_owned())
                         }
@@ -346,6 +481,9 @@ impl ASTNode {
                     _ => Err("Alloc expects a declared variable.".to_owned()),
                 },
                 ASTNodeType::Deref => {
+                    if let ASTNode::VAR { name, .. } = &**ptr {
+                        mark_use(name);
+                    }
                     if let Some(ptrtype) = ptr.getexprtype() {
                         if ptrtype.depth() < depth.unwrap() {
                             return Err("Dereferencing non pointer type.".to_owned());
@@ -361,8 +499,18 @@ impl ASTNode {
                         dot_field_access: _,
                         arrow_field_access: _,
                     } => {
-                        let varindices = getvarindices(name).unwrap();
-                        if array_access.len() != varindices.len() {
+                        let varindices = match getvarindices(name) {
+                            Some(v) => v,
+                            None => {
+                                return Err("Symbol [".to_owned()
+                                    + name.as_str()
+                                    + "] is not declared.")
+                            }
+                        };
+                        mark_use(name);
+                        if array_access.len() != varindices.len()
+                            && !is_pointer_index(name, array_access.len())
+                        {
                             return Err("Reference operator can only reference to the basetype of an array.".to_owned());
                         }
                         Ok(())
@@ -371,6 +519,11 @@ impl ASTNode {
                 },
                 ASTNodeType::Write => {
                     ptr.validate()?;
+                    if let Some(ASTExprType::Tuple(_)) = ptr.getexprtype() {
+                        return Err(
+                            "Tuples can only be destructured with (x, y) = f();.".to_owned()
+                        );
+                    }
                     match &**ptr {
                         ASTNode::VAR {
                             name: _,
@@ -413,6 +566,7 @@ impl ASTNode {
                 rhs,
             } => match op {
                 ASTNodeType::Equals => {
+                    __unmark_field_target(lhs);
                     let lhs_t = lhs.getexprtype();
                     let rhs_t = rhs.getexprtype();
 
@@ -434,11 +588,21 @@ impl ASTNode {
                 | ASTNodeType::Ee
                 | ASTNodeType::Lt
                 | ASTNodeType::Lte => {
-                    if self.getexprtype() != Some(ASTExprType::Primitive(PrimitiveType::Bool)) {
-                        Err("Boolean operator got invalid types.".to_owned())
-                    } else {
-                        Ok(())
+                    if self.getexprtype() == Some(ASTExprType::Primitive(PrimitiveType::Bool)) {
+                        return Ok(());
+                    }
+                    if let ASTNode::BinaryNode { lhs, rhs, .. } = self {
+                        for t in [lhs.getexprtype(), rhs.getexprtype()] {
+                            if let Some(ASTExprType::Enum(e)) = t {
+                                return Err("Enum [".to_owned()
+                                    + e.name.as_str()
+                                    + "] can only be compared with the same enum, convert with int() or "
+                                    + e.name.as_str()
+                                    + "().");
+                            }
+                        }
                     }
+                    Err("Boolean operator got invalid types.".to_owned())
                 }
                 ASTNodeType::Plus
                 | ASTNodeType::Minus
@@ -446,15 +610,31 @@ impl ASTNode {
                 | ASTNodeType::Slash
                 | ASTNodeType::Mod => {
                     let expr = self.getexprtype();
-                    if expr != None && expr != Some(ASTExprType::Primitive(PrimitiveType::Void)) {
-                        Ok(())
-                    } else {
-                        Err("Operator +-/*% got invalid types.".to_owned())
+                    if expr.is_some()
+                        && expr != Some(ASTExprType::Primitive(PrimitiveType::Void))
+                        && expr != Some(ASTExprType::Error)
+                    {
+                        return Ok(());
                     }
+                    if let ASTNode::BinaryNode {
+                        op: ASTNodeType::Plus | ASTNodeType::Minus,
+                        exprtype: _,
+                        lhs,
+                        rhs,
+                    } = self
+                    {
+                        let is_ptr =
+                            |t: Option<ASTExprType>| matches!(t, Some(ASTExprType::Pointer(_)));
+                        if is_ptr(lhs.getexprtype()) || is_ptr(rhs.getexprtype()) {
+                            return Err("Pointer arithmetic expects a pointer and an int, or two pointers of the same type for -.".to_owned());
+                        }
+                    }
+                    Err("Operator +-/*% got invalid types.".to_owned())
                 }
                 _ => Ok(()),
             },
             ASTNode::FuncCallNode { fname, arglist } => {
+                mark_use(fname);
                 let gst = GLOBALSYMBOLTABLE.lock().unwrap();
                 let mut p;
                 if let Some(entry) = gst.get(fname) {
@@ -478,6 +658,73 @@ impl ASTNode {
                 std::mem::drop(gst);
                 compare_arglist_paramlist(fname, arglist, &mut p)
             }
+            ASTNode::IndirectCallNode { fptr, arglist } => {
+                //calling through the pointer uses it
+                fptr.validate()?;
+                match fptr.getexprtype() {
+                    Some(ASTExprType::Function(f)) => {
+                        let mut fname = match &**fptr {
+                            ASTNode::VAR { name, .. } => name.clone(),
+                            _ => "function pointer".to_owned(),
+                        };
+                        let mut p: LinkedList<VarNode> = f
+                            .params
+                            .iter()
+                            .map(|t| VarNode {
+                                varname: String::default(),
+                                vartype: t.clone(),
+                                varindices: vec![],
+                            })
+                            .collect();
+                        compare_arglist_paramlist(&mut fname, arglist, &mut p)
+                    }
+                    _ => Err("Only functions and function pointers can be called.".to_owned()),
+                }
+            }
+            ASTNode::TupleAssgNode { vars, call } => {
+                for var in vars.iter() {
+                    __unmark_field_target(var);
+                }
+                let fname = match &**call {
+                    ASTNode::FuncCallNode { fname, arglist: _ } => fname.clone(),
+                    _ => return Err(
+                        "Destructuring assignment expects a call to a function returning a tuple."
+                            .to_owned(),
+                    ),
+                };
+                let types = match call.getexprtype() {
+                    Some(ASTExprType::Tuple(t)) => t,
+                    _ => {
+                        return Err("Function [".to_owned()
+                            + fname.as_str()
+                            + "] does not return a tuple.")
+                    }
+                };
+                if types.len() != vars.len() {
+                    return Err("Function [".to_owned()
+                        + fname.as_str()
+                        + "] returns "
+                        + types.len().to_string().as_str()
+                        + " values, destructuring expects "
+                        + vars.len().to_string().as_str()
+                        + ".");
+                }
+                for (i, (var, t)) in vars.iter_mut().zip(types.iter()).enumerate() {
+                    if var.getexprtype().as_ref() != Some(t) {
+                        return Err("Destructuring assignment of invalid type at [".to_owned()
+                            + (i + 1).to_string().as_str()
+                            + "] position.");
+                    }
+                }
+                Ok(())
+            }
+            ASTNode::FuncRefNode { fname } => match getfunctype(fname) {
+                Some(_) => {
+                    mark_use(fname);
+                    Ok(())
+                }
+                None => Err("Function name [".to_owned() + fname.as_str() + "] is not declared"),
+            },
             ASTNode::FuncDefNode {
                 fname,
                 ret_type: r1,
@@ -588,6 +835,7 @@ impl ASTNode {
             ASTNode::Null => Some(ASTExprType::Primitive(PrimitiveType::Null)),
             ASTNode::STR(_) => Some(ASTExprType::Primitive(PrimitiveType::String)),
             ASTNode::INT(_) => Some(ASTExprType::Primitive(PrimitiveType::Int)),
+            ASTNode::EnumConst { etype, value: _ } => Some(etype.clone()),
             ASTNode::VAR {
                 name,
                 array_access,
@@ -611,15 +859,13 @@ impl ASTNode {
                                 dot_field_access,
                                 arrow_field_access,
                             } => {
-                                if array_access.len() > 0 {
-                                    exit_on_err(
-                                        "Arrays inside structs are not implemented yet.".to_owned(),
-                                    );
-                                }
                                 if let Err(e) = vtype.get_field_id(nname) {
                                     exit_on_err(e.to_owned());
                                 }
                                 vtype = vtype.get_field_type(nname).unwrap();
+                                for _ in 0..array_access.len() {
+                                    vtype = vtype.derefr()?;
+                                }
                                 dotptr = &**dot_field_access;
                                 arrowptr = &**arrow_field_access;
                             }
@@ -639,16 +885,14 @@ impl ASTNode {
                                 dot_field_access,
                                 arrow_field_access,
                             } => {
-                                if array_access.len() > 0 {
-                                    exit_on_err(
-                                        "Arrays inside structs are not implemented yet.".to_owned(),
-                                    );
-                                }
                                 if let ASTExprType::Pointer(etype) = &vtype {
                                     if let Err(e) = etype.get_field_id(&nname) {
                                         exit_on_err(e.to_owned());
                                     }
                                     vtype = etype.get_field_type(nname).unwrap();
+                                    for _ in 0..array_access.len() {
+                                        vtype = vtype.derefr()?;
+                                    }
                                     dotptr = &**dot_field_access;
                                     arrowptr = &**arrow_field_access;
                                 } else {
@@ -667,6 +911,7 @@ impl ASTNode {
                                     );
                                 }
                             }
+                            ASTNode::Void => {}
                             _ => unreachable!(),
                         }
                     }
@@ -693,6 +938,7 @@ impl ASTNode {
                         exprtype.clone()
                     }
                 }
+                ASTNodeType::AllocArray | ASTNodeType::Free | ASTNodeType::Cast => exprtype.clone(),
                 ASTNodeType::Ref => {
                     if exprtype == &None {
                         if let Some(base) = ptr.getexprtype() {
@@ -722,6 +968,9 @@ impl ASTNode {
                                 ASTExprType::Primitive(PrimitiveType::Int),
                                 ASTExprType::Primitive(PrimitiveType::Int),
                             ) => Some(ASTExprType::Primitive(PrimitiveType::Bool)),
+                            (ASTExprType::Enum(a), ASTExprType::Enum(b)) if a == b => {
+                                Some(ASTExprType::Primitive(PrimitiveType::Bool))
+                            }
                             _ => Some(ASTExprType::Error),
                         };
                         exprtype.clone()
@@ -739,6 +988,9 @@ impl ASTNode {
                                 ASTExprType::Primitive(PrimitiveType::Null),
                                 ASTExprType::Pointer(..),
                             ) => Some(ASTExprType::Primitive(PrimitiveType::Bool)),
+                            (ASTExprType::Enum(a), ASTExprType::Enum(b)) if a == b => {
+                                Some(ASTExprType::Primitive(PrimitiveType::Bool))
+                            }
                             (
                                 ASTExprType::Pointer(..),
                                 ASTExprType::Primitive(PrimitiveType::Null),
@@ -755,7 +1007,7 @@ impl ASTNode {
                                 if ptr1.depth() == ptr2.depth()
                                     && ptr1.get_base_type() == ptr2.get_base_type()
                                 {
-                                    Some(*ptr1.clone())
+                                    Some(ASTExprType::Primitive(PrimitiveType::Bool))
                                 } else {
                                     Some(ASTExprType::Error)
                                 }
@@ -794,14 +1046,21 @@ impl ASTNode {
                                 ASTExprType::Primitive(PrimitiveType::Int),
                                 ASTExprType::Primitive(PrimitiveType::Int),
                             ) => Some(ASTExprType::Primitive(PrimitiveType::Int)),
+                            //pointer arithmetic keeps the pointer type
                             (
-                                ASTExprType::Primitive(PrimitiveType::Int),
                                 ASTExprType::Pointer(p),
-                            ) => Some(*p.clone()),
+                                ASTExprType::Primitive(PrimitiveType::Int),
+                            ) => Some(ASTExprType::Pointer(p)),
                             (
-                                ASTExprType::Pointer(p),
                                 ASTExprType::Primitive(PrimitiveType::Int),
-                            ) => Some(*p.clone()),
+                                ASTExprType::Pointer(p),
+                            ) if *op == ASTNodeType::Plus => Some(ASTExprType::Pointer(p)),
+                            //difference of two pointers is counted in elements
+                            (ASTExprType::Pointer(p), ASTExprType::Pointer(q))
+                                if *op == ASTNodeType::Minus && p == q =>
+                            {
+                                Some(ASTExprType::Primitive(PrimitiveType::Int))
+                            }
                             _ => Some(ASTExprType::Error),
                         };
                         exprtype.clone()
@@ -811,6 +1070,18 @@ impl ASTNode {
                 }
                 _ => Some(ASTExprType::Primitive(PrimitiveType::Void)),
             },
+            ASTNode::FuncRefNode { fname } => getfunctype(fname),
+            ASTNode::TupleNode { elems } => {
+                let mut types = vec![];
+                for elem in elems.iter_mut() {
+                    types.push(elem.getexprtype()?);
+                }
+                Some(ASTExprType::Tuple(types))
+            }
+            ASTNode::IndirectCallNode { fptr, arglist: _ } => match fptr.getexprtype() {
+                Some(ASTExprType::Function(f)) => Some(*f.ret_type),
+                _ => None,
+            },
             ASTNode::FuncCallNode { fname, arglist: _ } => {
                 let gst = GLOBALSYMBOLTABLE.lock().unwrap();
                 if let Some(entry) = gst.get(&fname.clone()) {
@@ -830,6 +1101,316 @@ impl ASTNode {
         }
     }
 }
+/*
+ * Control must not fall off the end of a non void function, line and column
+ * locate the function in the source, unreachable statements are warned about
+ */
+pub fn check_function_returns(
+    fname: &String,
+    ret_type: &ASTExprType,
+    body: &ASTNode,
+    (line, column): (usize, usize),
+) -> Result<(), String> {
+    let flow = __flow(fname, body);
+    if *ret_type != ASTExprType::Primitive(PrimitiveType::Void) && flow != Flow::Returns {
+        return Err("Function [".to_owned()
+            + fname.as_str()
+            + "] at line "
+            + line.to_string().as_str()
+            + " column "
+            + column.to_string().as_str()
+            + ": not all code paths return a value.");
+    }
+    Ok(())
+}
+/*
+ * main exits through the code of the header when it falls off the end,
+ * so only its unreachable statements are warned about
+ */
+pub fn check_main_returns(body: &ASTNode) {
+    __flow(&"main".to_owned(), body);
+}
+/*
+ * Statements with the line they start at, if known
+ */
+fn __flatten_stmts<'a>(node: &'a ASTNode, stmts: &mut Vec<(Option<usize>, &'a ASTNode)>) {
+    match node {
+        ASTNode::BinaryNode {
+            op: ASTNodeType::Connector,
+            exprtype: _,
+            lhs,
+            rhs,
+        } => {
+            __flatten_stmts(lhs, stmts);
+            __flatten_stmts(rhs, stmts);
+        }
+        ASTNode::StmtNode { pos, stmt } => stmts.push((Some(pos.0), stmt)),
+        ASTNode::Void => {}
+        _ => stmts.push((None, node)),
+    }
+}
+//how control leaves a statement list
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flow {
+    //on to the next statement
+    Falls,
+    //out of the list by break or continue, or return on some paths
+    Jumps,
+    //every path returns
+    Returns,
+}
+/*
+ * Flow after either of two paths
+ */
+fn __merge_flow(a: Flow, b: Flow) -> Flow {
+    match (a, b) {
+        (Flow::Falls, _) | (_, Flow::Falls) => Flow::Falls,
+        (Flow::Returns, Flow::Returns) => Flow::Returns,
+        _ => Flow::Jumps,
+    }
+}
+/*
+ * How control leaves the statement list, loops are assumed to possibly run
+ * zero times
+ * Statements after a return, break or continue on every path are warned about
+ */
+fn __flow(fname: &String, node: &ASTNode) -> Flow {
+    let mut stmts = vec![];
+    __flatten_stmts(node, &mut stmts);
+    let mut flow = Flow::Falls;
+    for (line, stmt) in stmts {
+        if flow != Flow::Falls {
+            match line {
+                Some(line) => log::warn!(
+                    "Unreachable statement at line {} in function [{}].",
+                    line,
+                    fname
+                ),
+                None => log::warn!("Unreachable statement in function [{}].", fname),
+            }
+            break;
+        }
+        flow = match stmt {
+            ASTNode::ReturnNode { expr: _ } => Flow::Returns,
+            ASTNode::BreakNode | ASTNode::ContinueNode => Flow::Jumps,
+            //both branches are walked for their warnings
+            ASTNode::IfElseNode {
+                expr: _,
+                xif,
+                xelse,
+            } => __merge_flow(__flow(fname, xif), __flow(fname, xelse)),
+            ASTNode::IfNode { expr: _, xif } => {
+                __flow(fname, xif);
+                Flow::Falls
+            }
+            //break and continue in the body leave only the loop
+            ASTNode::WhileNode { expr: _, xdo } => {
+                __flow(fname, xdo);
+                Flow::Falls
+            }
+            //cases don't fall through, without a default the switch may do nothing
+            ASTNode::SwitchNode {
+                expr: _,
+                cases,
+                default,
+            } => {
+                let mut flow = __flow(fname, default);
+                for (_, body) in cases {
+                    flow = __merge_flow(flow, __flow(fname, body));
+                }
+                flow
+            }
+            _ => Flow::Falls,
+        };
+    }
+    flow
+}
+/*
+ * Record a reference to a local, or to a global variable or function
+ */
+pub fn mark_use(name: &String) {
+    let local = LOCALSYMBOLTABLE.lock().unwrap().contains_key(name);
+    let mut uses = USES.lock().unwrap();
+    if local {
+        uses.locals.insert(name.clone());
+    } else {
+        uses.globals.insert(name.clone());
+    }
+}
+fn __mark_field_read(parent: &ASTExprType, field: &str, count: i64) {
+    let key = parent.get_type_name().unwrap() + "." + field;
+    *USES.lock().unwrap().fields.entry(key).or_insert(0) += count;
+}
+/*
+ * The last field of an assignment target was counted as a read when the variable
+ * was validated, take it back
+ */
+fn __unmark_field_target(target: &ASTNode) {
+    let (name, array_access, mut dotptr, mut arrowptr) = match target {
+        ASTNode::VAR {
+            name,
+            array_access,
+            dot_field_access,
+            arrow_field_access,
+        } => (
+            name,
+            array_access,
+            &**dot_field_access,
+            &**arrow_field_access,
+        ),
+        _ => return,
+    };
+    let mut currtype = match getvartype(name) {
+        Some(t) => t,
+        None => return,
+    };
+    for _ in 0..array_access.len() {
+        currtype = currtype.derefr().unwrap_or(ASTExprType::Error);
+    }
+    let mut last: Option<(ASTExprType, String)> = None;
+    loop {
+        let (parent, field) = match (dotptr, arrowptr) {
+            (ASTNode::VAR { .. }, _) => (currtype.clone(), dotptr),
+            (_, ASTNode::VAR { .. }) => match currtype.derefr() {
+                Some(t) => (t, arrowptr),
+                None => return,
+            },
+            _ => break,
+        };
+        if let ASTNode::VAR {
+            name: fname,
+            array_access,
+            dot_field_access,
+            arrow_field_access,
+        } = field
+        {
+            currtype = match parent.get_field_type(fname) {
+                Ok(t) => t,
+                Err(_) => return,
+            };
+            for _ in 0..array_access.len() {
+                currtype = currtype.derefr().unwrap_or(ASTExprType::Error);
+            }
+            last = Some((parent, fname.clone()));
+            dotptr = &**dot_field_access;
+            arrowptr = &**arrow_field_access;
+        }
+    }
+    if let Some((parent, field)) = last {
+        __mark_field_read(&parent, &field, -1);
+    }
+}
+/*
+ * Warn about locals and params of a function that are never referenced,
+ * names starting with an underscore are silenced
+ */
+pub fn warn_unused_locals(fname: &String) {
+    let lst = LOCALSYMBOLTABLE.lock().unwrap().clone();
+    let mut uses = USES.lock().unwrap();
+    let mut names: Vec<&String> = lst.keys().collect();
+    names.sort();
+    for name in names {
+        if uses.locals.contains(name) || name.starts_with('_') || name == "self" {
+            continue;
+        }
+        let LSymbol::Var {
+            vartype: _,
+            varid,
+            varindices: _,
+        } = &lst[name];
+        let kind = if *varid < 0 {
+            "Parameter"
+        } else {
+            "Local variable"
+        };
+        log::warn!("{} [{}] of function [{}] is never used.", kind, name, fname);
+    }
+    uses.locals.clear();
+}
+/*
+ * Warn about globals and functions never referenced, and class fields never read
+ */
+pub fn warn_unused_globals() {
+    let builtin = GlobalSymbolTable::default().table;
+    let gst = GLOBALSYMBOLTABLE.lock().unwrap();
+    let tt = TYPE_TABLE.lock().unwrap();
+    let uses = USES.lock().unwrap();
+    let mut names: Vec<&String> = gst.keys().collect();
+    names.sort();
+    for name in names {
+        if uses.globals.contains(name)
+            || builtin.contains_key(name)
+            || name.starts_with('_')
+            || name == "main"
+        {
+            continue;
+        }
+        match gst[name] {
+            GSymbol::Var { .. } => log::warn!("Global variable [{}] is never used.", name),
+            GSymbol::Func { .. } => log::warn!("Function [{}] is never called.", name),
+        }
+    }
+    let mut classes: Vec<&ASTClassType> = tt
+        .table
+        .values()
+        .filter_map(|t| match t {
+            ASTExprType::Class(c) => Some(c),
+            _ => None,
+        })
+        .collect();
+    classes.sort_by(|a, b| a.name.cmp(&b.name));
+    for class in classes {
+        let mut fields: Vec<&String> = class
+            .symbol_table
+            .table
+            .iter()
+            .filter_map(|(name, entry)| match entry {
+                CSymbol::Var { .. } => Some(name),
+                _ => None,
+            })
+            .collect();
+        fields.sort();
+        for field in fields {
+            let key = class.name.clone() + "." + field.as_str();
+            if uses.fields.get(&key).copied().unwrap_or(0) <= 0 && !field.starts_with('_') {
+                log::warn!("Field [{}] of class [{}] is never read.", field, class.name);
+            }
+        }
+    }
+}
+/*
+ * Words alloc() needs for the pointee
+ */
+pub fn get_alloc_size(t: &ASTExprType) -> usize {
+    match t {
+        //methods do not take space inside an object
+        ASTExprType::Class(c) => usize::try_from(c.fieldsize).unwrap(),
+        ASTExprType::Error => 0,
+        _ => t.size().unwrap(),
+    }
+}
+/*
+ * The library allocator hands out fixed blocks of 8 words, check if the pointee fits
+ */
+fn __validate_alloc_size(t: &ASTExprType) -> Result<(), String> {
+    let size = get_alloc_size(t);
+    if get_allocator() == Allocator::Library && size > 8 {
+        return Err("Type [".to_owned()
+            + t.to_string().as_str()
+            + "] needs "
+            + size.to_string().as_str()
+            + " words, alloc() can only allocate 8. Use --allocator=builtin for larger blocks.");
+    }
+    Ok(())
+}
+/*
+ * Pointer variables can be indexed like one dimensional arrays, p[i] is *(p+i)
+ */
+pub fn is_pointer_index(name: &String, accessed: usize) -> bool {
+    accessed == 1
+        && getvarindices(name) == Some(vec![])
+        && matches!(getvartype(name), Some(ASTExprType::Pointer(_)))
+}
 pub fn getvarindices(name: &String) -> Option<Vec<usize>> {
     let lst = LOCALSYMBOLTABLE.lock().unwrap();
     if let Some(LSymbol::Var {
@@ -851,27 +1432,81 @@ pub fn getvarindices(name: &String) -> Option<Vec<usize>> {
     }
     None
 }
-pub fn getvarid(name: &String) -> Option<i64> {
-    let lst = LOCALSYMBOLTABLE.lock().unwrap();
-    if let Some(LSymbol::Var {
-        vartype: _,
-        varid,
-        varindices: _,
-    }) = lst.get(name)
-    {
-        return Some(*varid);
-    }
-    let gst = GLOBALSYMBOLTABLE.lock().unwrap();
-    if let Some(GSymbol::Var {
-        vartype: _,
-        varid,
-        varindices: _,
-    }) = gst.get(name)
+/*
+ * Dimensions of an array expression that are left after indexing,
+ * following field accesses to the last variable in the chain
+ */
+pub fn getarraydims(node: &ASTNode) -> Option<Vec<usize>> {
+    if let ASTNode::VAR {
+        name,
+        array_access,
+        dot_field_access,
+        arrow_field_access,
+    } = node
     {
-        return Some(i64::try_from(*varid).unwrap());
+        let mut dims = getvarindices(name)?;
+        let mut accessed = array_access.len();
+        let mut currtype = getvartype(name)?;
+        for _ in 0..accessed {
+            currtype = currtype.derefr()?;
+        }
+        let mut dotptr = &**dot_field_access;
+        let mut arrowptr = &**arrow_field_access;
+        loop {
+            let field = match (dotptr, arrowptr) {
+                (ASTNode::VAR { .. }, _) => dotptr,
+                (_, ASTNode::VAR { .. }) => {
+                    currtype = currtype.derefr()?;
+                    arrowptr
+                }
+                _ => break,
+            };
+            if let ASTNode::VAR {
+                name: fname,
+                array_access,
+                dot_field_access,
+                arrow_field_access,
+            } = field
+            {
+                dims = currtype.get_field_indices(fname).ok()?;
+                accessed = array_access.len();
+                currtype = currtype.get_field_type(fname).ok()?;
+                for _ in 0..accessed {
+                    currtype = currtype.derefr()?;
+                }
+                dotptr = &**dot_field_access;
+                arrowptr = &**arrow_field_access;
+            }
+        }
+        if accessed > dims.len() {
+            return None;
+        }
+        return Some(dims[accessed..].to_vec());
     }
     None
 }
+/*
+ * len() of a fixed-size array, known at compile time
+ */
+pub fn getarraylen(node: &ASTNode) -> Result<usize, String> {
+    let name = match node {
+        ASTNode::VAR { name, .. } => name.clone(),
+        _ => return Err("len() expects an array variable.".to_owned()),
+    };
+    varinscope(&name)?;
+    match getarraydims(node) {
+        Some(dims) if !dims.is_empty() => {
+            if dims[0] == 0 {
+                Err("len() needs a fixed-size array, [".to_owned()
+                    + name.as_str()
+                    + "] is an unsized array parameter.")
+            } else {
+                Ok(dims[0])
+            }
+        }
+        _ => Err("len() expects an array, [".to_owned() + name.as_str() + "] is not one."),
+    }
+}
 pub fn varinscope(name: &String) -> Result<(), String> {
     let lst = LOCALSYMBOLTABLE.lock().unwrap();
     if lst.contains_key(name) {
@@ -914,7 +1549,9 @@ pub fn compare_arglist_paramlist(
 
     let mut ctr = 1;
     while let (Some(arg), Some(param)) = (aiter.next(), piter.next()) {
-        if arg.getexprtype().unwrap() != param.vartype {
+        if !param.varindices.is_empty() {
+            __compare_array_arg(fname, arg, param, ctr)?;
+        } else if arg.getexprtype().unwrap() != param.vartype {
             return Err("Function [".to_owned()
                 + fname.as_str()
                 + "] call arguments and declaration arguments dont match in type at ["
@@ -925,3 +1562,43 @@ pub fn compare_arglist_paramlist(
     }
     Ok(())
 }
+/*
+ * Array parameters take the base address of an array with the same element type,
+ * every dimension but an unsized first one has to match
+ */
+fn __compare_array_arg(
+    fname: &str,
+    arg: &mut ASTNode,
+    param: &VarNode,
+    ctr: usize,
+) -> Result<(), String> {
+    let mut ptype = param.vartype.clone();
+    for _ in param.varindices.iter() {
+        ptype = ptype.refr().unwrap();
+    }
+    if arg.getexprtype() != Some(ptype) {
+        return Err("Function [".to_owned()
+            + fname
+            + "] call arguments and declaration arguments dont match in type at ["
+            + ctr.to_string().as_str()
+            + "] position.");
+    }
+    let matches = match getarraydims(arg) {
+        Some(dims) if dims.len() == param.varindices.len() => {
+            (param.varindices[0] == 0 || param.varindices[0] == dims[0])
+                && dims[1..] == param.varindices[1..]
+        }
+        //plain pointers carry no dimensions, allowed for unsized one dimensional arrays
+        _ => param.varindices == vec![0],
+    };
+    if !matches {
+        return Err("Function [".to_owned()
+            + fname
+            + "] expects an array with matching dimensions for ["
+            + param.varname.as_str()
+            + "] at ["
+            + ctr.to_string().as_str()
+            + "] position.");
+    }
+    Ok(())
+}
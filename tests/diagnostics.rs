@@ -0,0 +1,702 @@
+use std::fs;
+use std::process::Command;
+
+/*
+ * Compiles source with rexplc, returns whether it succeeded and the log
+ */
+fn diagnose(name: &str, source: &str, flags: &[&str]) -> (bool, String) {
+    let path = format!("{}/diag_{}.expl", env!("CARGO_TARGET_TMPDIR"), name);
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rexplc"))
+        .args(flags)
+        .arg(&path)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+fn assert_rejects(name: &str, source: &str, flags: &[&str], error: &str) {
+    let (ok, log) = diagnose(name, source, flags);
+    assert!(!ok, "{} compiled: {}", name, log);
+    assert!(log.contains(error), "{} missing [{}]: {}", name, error, log);
+}
+
+#[test]
+fn missing_return_is_rejected() {
+    let source = "decl
+	int sign(int a);
+enddecl
+int sign(int a){
+	begin
+		if(a>0)then
+			return 1;
+		endif;
+	end
+}
+int main(){
+	begin
+		write(sign(3));
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "missing_return",
+        source,
+        &[],
+        "Function [sign] at line 4 column 1: not all code paths return a value.",
+    );
+}
+
+#[test]
+fn main_may_fall_off_the_end() {
+    let source = "int main(){
+	begin
+		write(1);
+	end
+}
+";
+    let (ok, log) = diagnose("main_falls", source, &[]);
+    assert!(ok, "{}", log);
+    assert!(!log.contains("return a value"), "{}", log);
+}
+
+#[test]
+fn statements_after_break_are_unreachable() {
+    let source = "int main(){
+	decl
+		int i;
+	enddecl
+	begin
+		i=0;
+		while(i<3)do
+			if(i==1)then
+				break;
+				write(i);
+			endif;
+			i=i+1;
+			continue;
+			write(i);
+		endwhile;
+		return 0;
+		write(i);
+	end
+}
+";
+    let (ok, log) = diagnose("unreachable", source, &[]);
+    assert!(ok, "{}", log);
+    for line in [10, 14, 17] {
+        let warning = format!("Unreachable statement at line {} in function [main].", line);
+        assert!(log.contains(&warning), "missing [{}]: {}", warning, log);
+    }
+}
+
+#[test]
+fn function_passed_by_address_is_used() {
+    let source = "decl
+	int lt(int a, int b);
+	int sort(int a[], int n, int (*cmp)(int, int));
+enddecl
+int lt(int a, int b){
+	begin
+		if(a<b)then
+			return 1;
+		endif;
+		return 0;
+	end
+}
+int sort(int a[], int n, int (*cmp)(int, int)){
+	decl
+		int i, j, t;
+	enddecl
+	begin
+		i=0;
+		while(i<n)do
+			j=0;
+			while(j<n-1)do
+				if(cmp(a[j+1], a[j])==1)then
+					t=a[j];
+					a[j]=a[j+1];
+					a[j+1]=t;
+				endif;
+				j=j+1;
+			endwhile;
+			i=i+1;
+		endwhile;
+		return 0;
+	end
+}
+int main(){
+	decl
+		int a[5], r;
+	enddecl
+	begin
+		a[0]=4;
+		a[1]=2;
+		a[2]=5;
+		a[3]=1;
+		a[4]=3;
+		r=sort(a, 5, &lt);
+		return 0;
+	end
+}
+";
+    let (ok, log) = diagnose("funcref_used", source, &[]);
+    assert!(ok, "{}", log);
+    assert!(!log.contains("never called"), "{}", log);
+    assert!(!log.contains("never used"), "{}", log);
+}
+
+#[test]
+fn global_initializer_must_be_constant() {
+    let source = "decl
+	int n;
+	int g = n + 1;
+enddecl
+int main(){
+	begin
+		write(g);
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "global_initializer",
+        source,
+        &[],
+        "Initializer of global [g]: [n] is not initialized with a constant.",
+    );
+}
+
+#[test]
+fn array_field_index_dimensions_are_checked() {
+    let source = "type
+	Buf { int n; int data[4]; };
+endtype
+decl
+	Buf b;
+enddecl
+int main(){
+	begin
+		b.data[1][2]=7;
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "array_field_dimensions",
+        source,
+        &[],
+        "Index dimension error for field [data]",
+    );
+}
+
+#[test]
+fn adding_two_pointers_is_rejected() {
+    let source = "decl
+	int a[4];
+enddecl
+int main(){
+	decl
+		int *p, *q, d;
+	enddecl
+	begin
+		p=&a[0];
+		q=&a[2];
+		d=p+q;
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "pointer_sum",
+        source,
+        &[],
+        "Pointer arithmetic expects a pointer and an int, or two pointers of the same type for -.",
+    );
+}
+
+#[test]
+fn array_parameters_are_checked() {
+    let unsized_len = "decl
+	int count(int v[]);
+enddecl
+int count(int v[]){
+	begin
+		return len(v);
+	end
+}
+int main(){
+	begin
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "len_unsized",
+        unsized_len,
+        &[],
+        "len() needs a fixed-size array, [v] is an unsized array parameter.",
+    );
+    let wrong_dims = "decl
+	int m[2][4];
+	int first(int w[][3]);
+enddecl
+int first(int w[][3]){
+	begin
+		return w[0][0];
+	end
+}
+int main(){
+	begin
+		write(first(m));
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "array_dims",
+        wrong_dims,
+        &[],
+        "Function [first] expects an array with matching dimensions for [w] at [1] position.",
+    );
+}
+
+const COLOR: &str = "type
+	enum Color { RED, BLACK, GREEN };
+endtype
+";
+
+#[test]
+fn enums_mix_with_int_only_through_casts() {
+    let compare = COLOR.to_owned()
+        + "int main(){
+	decl
+		Color c;
+	enddecl
+	begin
+		c=Color.RED;
+		if(c==0)then
+			write(1);
+		endif;
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "enum_compare",
+        &compare,
+        &[],
+        "Enum [Color] can only be compared with the same enum, convert with int() or Color().",
+    );
+    let assign = COLOR.to_owned()
+        + "int main(){
+	decl
+		Color c;
+	enddecl
+	begin
+		c=1;
+		return 0;
+	end
+}
+";
+    assert_rejects("enum_assign", &assign, &[], "Assignment of invalid type.");
+}
+
+#[test]
+fn switch_over_enum_warns_about_missing_cases() {
+    let source = COLOR.to_owned()
+        + "int main(){
+	decl
+		Color c;
+	enddecl
+	begin
+		c=Color.RED;
+		switch(c)
+			case Color.RED: write(10);
+			case Color.BLACK: write(20);
+		endswitch;
+		return 0;
+	end
+}
+";
+    let (ok, log) = diagnose("enum_switch", &source, &[]);
+    assert!(ok, "{}", log);
+    assert!(
+        log.contains("Switch over enum [Color] does not handle [GREEN]."),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn cast_from_str_to_pointer_is_rejected() {
+    let source = "int main(){
+	decl
+		int *p;
+		str s;
+	enddecl
+	begin
+		s=\"x\";
+		p=(int*) s;
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "cast_str",
+        source,
+        &[],
+        "Invalid cast from [str_t] to [*int_t].",
+    );
+}
+
+#[test]
+fn function_pointer_types_are_checked() {
+    let source = "decl
+	int lt(int x, int y);
+enddecl
+int lt(int x, int y){
+	begin
+		if(x<y)then
+			return 1;
+		endif;
+		return 0;
+	end
+}
+int main(){
+	decl
+		int (*f)(int, int);
+		int (*g)(int);
+	enddecl
+	begin
+		f=&lt;
+		write(f(2, \"x\"));
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "fptr_call",
+        source,
+        &[],
+        "Function [f] call arguments and declaration arguments dont match in type at [2] position.",
+    );
+    let assign = source
+        .replace("f=&lt;", "g=&lt;")
+        .replace("write(f(2, \"x\"));", "");
+    assert_rejects("fptr_assign", &assign, &[], "Assignment of invalid type.");
+}
+
+const TWO: &str = "decl
+	(int, int) two(int a);
+enddecl
+(int, int) two(int a){
+	begin
+		return (a, a+1);
+	end
+}
+";
+
+#[test]
+fn tuple_shapes_are_checked() {
+    let destructure = TWO.to_owned()
+        + "int main(){
+	decl
+		int x, y, z;
+	enddecl
+	begin
+		(x, y, z)=two(1);
+		return 0;
+	end
+}
+";
+    assert_rejects(
+        "tuple_count",
+        &destructure,
+        &[],
+        "Function [two] returns 2 values, destructuring expects 3.",
+    );
+    let bad_return = destructure
+        .replace("return (a, a+1);", "return (a, \"s\");")
+        .replace("(x, y, z)", "(x, y)");
+    assert_rejects("tuple_return", &bad_return, &[], "Invalid return type.");
+    let plain = destructure.replace("(x, y, z)=two(1);", "x=two(1);");
+    assert_rejects("tuple_assign", &plain, &[], "Assignment of invalid type.");
+}
+
+#[test]
+fn void_is_checked() {
+    let source = "decl
+	void show(int x);
+enddecl
+void show(int x){
+	begin
+		write(x);
+	end
+}
+int main(){
+	begin
+		show(1);
+		return 0;
+	end
+}
+";
+    let returns_value = source.replace("write(x);", "write(x);\n\t\treturn x;");
+    assert_rejects(
+        "void_return",
+        &returns_value,
+        &[],
+        "A void function cannot return a value.",
+    );
+    let used = source.replace("show(1);", "write(show(1));");
+    assert_rejects(
+        "void_used",
+        &used,
+        &[],
+        "The result of a void function call cannot be used in an expression.",
+    );
+    let variable = source.replace(
+        "int main(){\n\tbegin",
+        "int main(){\n\tdecl\n\t\tvoid v;\n\tenddecl\n\tbegin",
+    );
+    assert_rejects(
+        "void_variable",
+        &variable,
+        &[],
+        "Variable [v] cannot be declared void.",
+    );
+}
+
+#[test]
+fn reads_before_assignment_are_warned_about() {
+    let source = "type
+	pt{
+		int x;
+		int y;
+	};
+endtype
+decl
+	int fill(int* p);
+enddecl
+int fill(int* p){
+	begin
+		*p=3;
+		return 0;
+	end
+}
+int main(){
+	decl
+		int a, b, c, d, e, f, g, r;
+		pt s;
+		pt* q;
+	enddecl
+	begin
+		if(1==1)then
+			a=1;
+			b=2;
+		else
+			a=2;
+		endif;
+		write(a);
+		write(b);
+		while(a<3)do
+			c=1;
+			a=a+1;
+		endwhile;
+		write(c);
+		read(d);
+		write(d);
+		r=fill(&e);
+		write(e);
+		write(f+1);
+		s.x=1;
+		write(s.x);
+		write(s.y);
+		q->x=4;
+		g=1;
+		g=g+1;
+		return 0;
+	end
+}
+";
+    let (ok, log) = diagnose("definite_assignment", source, &[]);
+    assert!(ok, "{}", log);
+    let warning = |v: &str| {
+        format!(
+            "Variable [{}] may be used before it is assigned in function [main].",
+            v
+        )
+    };
+    //b is set on one branch only, c in a loop that may not run
+    for v in ["b", "c", "f", "s.y", "q"] {
+        assert!(log.contains(&warning(v)), "missing [{}]: {}", v, log);
+    }
+    //read(d) and fill(&e) initialize
+    for v in ["a", "d", "e", "g", "s.x"] {
+        assert!(!log.contains(&warning(v)), "unexpected [{}]: {}", v, log);
+    }
+}
+
+/*
+ * main of a program with a list node type, a function giving its
+ * allocation to the caller, and body as the statements of main
+ */
+fn ownership_program(body: &str) -> String {
+    "type
+	Node { int v; Node *next; };
+endtype
+decl
+	Node *mk();
+enddecl
+Node *mk(){
+	decl
+		Node *n;
+	enddecl
+	begin
+		n=alloc();
+		return n;
+	end
+}
+int main(){
+	decl
+		Node *p, *q;
+		int x;
+	enddecl
+	begin
+		initialize();
+"
+    .to_owned()
+        + body
+        + "		return 0;
+	end
+}
+"
+}
+
+#[test]
+fn owned_pointers_freed_once_pass() {
+    let source = ownership_program(
+        "		p=alloc();
+		p->v=1;
+		q=mk();
+		q->next=p;
+		x=free(p);
+		x=free(q);
+",
+    );
+    let (ok, log) = diagnose("ownership_clean", &source, &["--ownership"]);
+    assert!(ok, "{}", log);
+}
+
+#[test]
+fn double_free_is_rejected() {
+    let source = ownership_program(
+        "		p=alloc();
+		x=free(p);
+		x=free(p);
+",
+    );
+    assert_rejects(
+        "double_free",
+        &source,
+        &["--ownership"],
+        "Double free of [p] at line 25 column 3, it was already freed at line 24 column 3 (allocated at line 23 column 3).",
+    );
+    //the checker is opt-in
+    let (ok, log) = diagnose("double_free_unchecked", &source, &[]);
+    assert!(ok, "{}", log);
+}
+
+#[test]
+fn double_free_on_a_later_iteration_is_rejected() {
+    let source = ownership_program(
+        "		p=alloc();
+		x=0;
+		while(x<2)do
+			x=free(p);
+			x=x+1;
+		endwhile;
+",
+    );
+    assert_rejects(
+        "double_free_loop",
+        &source,
+        &["--ownership"],
+        "Double free of [p] at line 26 column 4, it was already freed at line 26 column 4 (allocated at line 23 column 3).",
+    );
+    let source = ownership_program(
+        "		p=alloc();
+		x=0;
+		while(x<2)do
+			write(p->v);
+			x=free(p);
+		endwhile;
+",
+    );
+    assert_rejects(
+        "use_after_free_loop",
+        &source,
+        &["--ownership"],
+        "Use of [p] at line 26 column 4 after it was freed at line 27 column 4 (allocated at line 23 column 3).",
+    );
+    //a block allocated and freed in every iteration is fine
+    let source = ownership_program(
+        "		x=0;
+		while(x<2)do
+			p=alloc();
+			p->v=x;
+			write(p->v);
+			x=free(p);
+		endwhile;
+",
+    );
+    let (ok, log) = diagnose("alloc_free_loop", &source, &["--ownership"]);
+    assert!(ok, "{}", log);
+}
+
+#[test]
+fn use_after_free_is_rejected() {
+    let source = ownership_program(
+        "		p=alloc();
+		x=free(p);
+		write(p->v);
+",
+    );
+    assert_rejects(
+        "use_after_free",
+        &source,
+        &["--ownership"],
+        "Use of [p] at line 25 column 3 after it was freed at line 24 column 3 (allocated at line 23 column 3).",
+    );
+}
+
+#[test]
+fn leaks_are_rejected() {
+    let overwritten = ownership_program(
+        "		q=mk();
+		q=alloc();
+		x=free(q);
+",
+    );
+    assert_rejects(
+        "leak_overwrite",
+        &overwritten,
+        &["--ownership"],
+        "Pointer [q] allocated at line 23 column 3 is overwritten at line 24 column 3 without being freed.",
+    );
+    let returned = ownership_program(
+        "		p=alloc();
+		p->v=1;
+",
+    );
+    assert_rejects(
+        "leak_return",
+        &returned,
+        &["--ownership"],
+        "Memory allocated at line 23 column 3 leaks when function [main] returns at line 25 column 3.",
+    );
+}
@@ -0,0 +1,700 @@
+//shared with the other tests, not every helper is used here
+#[allow(dead_code)]
+mod xsm;
+
+use std::fs;
+use std::process::Command;
+
+/*
+ * Compiles source with rexplc and returns the linked xsm
+ */
+fn compile(name: &str, source: &str, flags: &[&str]) -> String {
+    let base = format!("{}/lang_{}", env!("CARGO_TARGET_TMPDIR"), name);
+    let path = base.clone() + ".expl";
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rexplc"))
+        .args(flags)
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "rexplc {:?} failed on {}: {}",
+        flags,
+        path,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::read_to_string(base + ".xsm").unwrap()
+}
+
+/*
+ * Runs the program unoptimized, optimized and with runtime checks, all
+ * three have to write the same words, which are returned
+ */
+fn run(name: &str, source: &str, flags: &[&str], input: &[&str]) -> Vec<String> {
+    let mut expected = None;
+    for mode in ["-O0", "-O2", "--checked"] {
+        let mut all = flags.to_vec();
+        all.push(mode);
+        let xsm = compile(name, source, &all);
+        let output = xsm::run(&xsm, input)
+            .unwrap_or_else(|e| panic!("{} {:?} did not run: {}", name, all, e));
+        match &expected {
+            None => expected = Some(output),
+            Some(expected) => assert_eq!(*expected, output, "{} {:?}", name, all),
+        }
+    }
+    expected.unwrap()
+}
+
+const HEAP_ARRAY: &str = "decl
+	int sum(int *p, int n);
+enddecl
+int sum(int *p, int n){
+	decl
+		int i, s;
+	enddecl
+	begin
+		i=0;
+		s=0;
+		while(i<n)do
+			s=s+p[i];
+			i=i+1;
+		endwhile;
+		return s;
+	end
+}
+int main(){
+	decl
+		int n, i, *p;
+	enddecl
+	begin
+		initialize();
+		read(n);
+		p=alloc_array(int, n);
+		i=0;
+		while(i<n)do
+			read(p[i]);
+			i=i+1;
+		endwhile;
+		write(sum(p, n));
+		i=free(p);
+		return 0;
+	end
+}
+";
+
+#[test]
+fn heap_arrays_with_both_allocators() {
+    let input = ["5", "1", "2", "3", "4", "5"];
+    for allocator in ["--allocator=library", "--allocator=builtin"] {
+        let output = run("heap_array", HEAP_ARRAY, &[allocator], &input);
+        assert_eq!(output, ["15"], "{}", allocator);
+    }
+    let input = [
+        "12", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12",
+    ];
+    let output = run(
+        "heap_array_big",
+        HEAP_ARRAY,
+        &["--allocator=builtin"],
+        &input,
+    );
+    assert_eq!(output, ["78"]);
+}
+
+#[test]
+fn heap_array_above_library_block_traps_when_checked() {
+    let input = [
+        "12", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12",
+    ];
+    let xsm = compile(
+        "heap_array_trap",
+        HEAP_ARRAY,
+        &["--allocator=library", "--checked"],
+    );
+    let output = xsm::run(&xsm, &input).unwrap();
+    assert_eq!(
+        output,
+        [
+            "Array larger than the 8 words of the library allocator",
+            "line 25"
+        ]
+    );
+}
+
+#[test]
+fn initializers_of_globals_and_locals() {
+    let source = "decl
+	int g = 5, h = g + 1;
+	int arr[3] = {1, 2};
+	str s = \"hi\";
+enddecl
+int main(){
+	decl
+		int x = 7, y = x + g;
+		int a[2][2] = {1, 2, 3, 4};
+	enddecl
+	begin
+		write(g);
+		write(h);
+		write(arr[0]);
+		write(arr[1]);
+		write(arr[2]);
+		write(s);
+		write(x);
+		write(y);
+		write(a[1][0]);
+		return 0;
+	end
+}
+";
+    let output = run("initializers", source, &[], &[]);
+    assert_eq!(output, ["5", "6", "1", "2", "0", "hi", "7", "12", "3"]);
+}
+
+const ARRAY_FIELDS: &str = "type
+	Buf { int n; int grid[2][3]; int data[4]; };
+endtype
+class
+Stack{
+	decl
+		int top;
+		int items[5];
+	div
+		int push(int x);
+		int pop();
+		int init();
+	enddecl
+	int push(int x){
+		begin
+			self->items[self->top]=x;
+			self->top=self->top+1;
+			return 0;
+		end
+	}
+	int init(){
+		begin
+			self->top=0;
+			return 0;
+		end
+	}
+	int pop(){
+		begin
+			self->top=self->top-1;
+			return self->items[self->top];
+		end
+	}
+};
+endclass
+decl
+	Buf b;
+	Stack *s;
+enddecl
+int main(){
+	decl
+		int i, j, r;
+		Buf *q;
+	enddecl
+	begin
+		initialize();
+		i=0;
+		while(i<2)do
+			j=0;
+			while(j<3)do
+				b.grid[i][j]=i*3+j;
+				j=j+1;
+			endwhile;
+			i=i+1;
+		endwhile;
+		i=0;
+		while(i<4)do
+			b.data[i]=i*10;
+			i=i+1;
+		endwhile;
+		b.n=7;
+		write(b.grid[1][2]);
+		write(b.grid[0][1]);
+		write(b.data[3]);
+		write(b.n);
+		q=alloc();
+		q->data[2]=77;
+		write(q->data[2]);
+		s=alloc();
+		r=s->init();
+		r=s->push(4);
+		r=s->push(9);
+		write(s->pop());
+		write(s->pop());
+		return 0;
+	end
+}
+";
+
+#[test]
+fn array_fields_in_types_and_classes() {
+    let output = run("array_fields", ARRAY_FIELDS, &["--allocator=builtin"], &[]);
+    assert_eq!(output, ["5", "1", "30", "7", "77", "9", "4"]);
+}
+
+#[test]
+fn arrays_of_structs_and_pointer_arithmetic() {
+    let source = "type
+	Pt { int x; int y; };
+endtype
+decl
+	Pt pts[3];
+	Pt *pp[2];
+	int a[4];
+enddecl
+int main(){
+	decl
+		Pt *r, *s;
+		int *p, *q, d;
+	enddecl
+	begin
+		pts[0].x=1;
+		pts[1].x=11;
+		pts[2].y=22;
+		pp[0]=&pts[2];
+		pp[1]=&pts[1];
+		write(pp[0]->y);
+		write(pp[1]->x);
+		r=&pts[0];
+		s=r+2;
+		write(s->y);
+		d=s-r;
+		write(d);
+		s=s-1;
+		write(s->x);
+		a[1]=5;
+		a[3]=8;
+		p=&a[1];
+		q=p+2;
+		write(*q);
+		write(q-p);
+		return 0;
+	end
+}
+";
+    let output = run("pointer_arithmetic", source, &[], &[]);
+    assert_eq!(output, ["22", "11", "22", "2", "11", "8", "2"]);
+}
+
+#[test]
+fn arrays_passed_by_reference_with_len() {
+    let source = "decl
+	int a[5];
+	int m[2][3];
+	int sum(int v[], int n);
+	int msum(int w[][3], int r);
+	int setz(int v[]);
+enddecl
+int sum(int v[], int n){
+	decl
+		int i, s;
+	enddecl
+	begin
+		i=0;
+		s=0;
+		while(i<n)do
+			s=s+v[i];
+			i=i+1;
+		endwhile;
+		return s;
+	end
+}
+int msum(int w[][3], int r){
+	decl
+		int i, j, s;
+	enddecl
+	begin
+		s=0;
+		i=0;
+		while(i<r)do
+			j=0;
+			while(j<3)do
+				s=s+w[i][j];
+				j=j+1;
+			endwhile;
+			i=i+1;
+		endwhile;
+		return s;
+	end
+}
+int setz(int v[]){
+	begin
+		v[0]=100;
+		return 0;
+	end
+}
+int main(){
+	decl
+		int i, x, loc[3];
+	enddecl
+	begin
+		i=0;
+		while(i<5)do
+			a[i]=i;
+			i=i+1;
+		endwhile;
+		i=0;
+		while(i<6)do
+			m[i/3][i%3]=i+1;
+			i=i+1;
+		endwhile;
+		write(sum(a, len(a)));
+		write(msum(m, len(m)));
+		loc[0]=1;
+		loc[1]=2;
+		loc[2]=3;
+		x=setz(loc);
+		write(loc[0]);
+		write(sum(loc, len(loc)));
+		return 0;
+	end
+}
+";
+    let output = run("array_params", source, &[], &[]);
+    assert_eq!(output, ["10", "21", "100", "105"]);
+}
+
+#[test]
+fn enums_and_switch() {
+    let source = "type
+	enum Color { RED, BLACK, GREEN };
+endtype
+decl
+	Color g;
+enddecl
+int main(){
+	decl
+		Color c;
+		int x;
+	enddecl
+	begin
+		c=Color.BLACK;
+		g=Color(2);
+		if(c==Color.BLACK)then
+			write(1);
+		endif;
+		x=int(g);
+		write(x);
+		switch(c)
+			case Color.RED: write(10);
+			case Color.BLACK: write(20);
+			default: write(30);
+		endswitch;
+		switch(x)
+			case 1: write(100);
+			default: write(200);
+		endswitch;
+		return 0;
+	end
+}
+";
+    let output = run("enums", source, &[], &[]);
+    assert_eq!(output, ["1", "2", "20", "200"]);
+}
+
+#[test]
+fn casts_and_sizeof() {
+    let source = "type
+	Pt { int x; int y; };
+	Big { int a[3]; Pt p; };
+endtype
+int main(){
+	decl
+		int *p, a, n;
+		Pt *q;
+	enddecl
+	begin
+		initialize();
+		q=alloc();
+		q->x=5;
+		p=(int*) q;
+		write(*p);
+		a=(int) q;
+		q=(Pt*) a;
+		write(q->x);
+		n=sizeof(Pt);
+		write(n);
+		write(sizeof(Big));
+		write(sizeof(int*));
+		return 0;
+	end
+}
+";
+    let output = run("casts", source, &[], &[]);
+    assert_eq!(output, ["5", "5", "2", "5", "1"]);
+}
+
+const SORT_WITH_COMPARATOR: &str = "decl
+	int a[5];
+	int lt(int x, int y);
+	int gt(int x, int y);
+	int sort(int v[], int n, int (*cmp)(int, int));
+enddecl
+int lt(int x, int y){
+	begin
+		if(x<y)then
+			return 1;
+		endif;
+		return 0;
+	end
+}
+int gt(int x, int y){
+	begin
+		if(x>y)then
+			return 1;
+		endif;
+		return 0;
+	end
+}
+int sort(int v[], int n, int (*cmp)(int, int)){
+	decl
+		int i, j, t;
+	enddecl
+	begin
+		i=0;
+		while(i<n)do
+			j=0;
+			while(j<n-1)do
+				if(cmp(v[j+1], v[j])==1)then
+					t=v[j];
+					v[j]=v[j+1];
+					v[j+1]=t;
+				endif;
+				j=j+1;
+			endwhile;
+			i=i+1;
+		endwhile;
+		return 0;
+	end
+}
+int main(){
+	decl
+		int i, x;
+		int (*f)(int, int);
+	enddecl
+	begin
+		a[0]=3;
+		a[1]=1;
+		a[2]=4;
+		a[3]=1;
+		a[4]=5;
+		x=sort(a, 5, &lt);
+		i=0;
+		while(i<5)do
+			write(a[i]);
+			i=i+1;
+		endwhile;
+		f=&gt;
+		x=sort(a, 5, f);
+		i=0;
+		while(i<5)do
+			write(a[i]);
+			i=i+1;
+		endwhile;
+		write(f(2, 1));
+		return 0;
+	end
+}
+";
+
+#[test]
+fn function_pointers_and_indirect_calls() {
+    let output = run("function_pointers", SORT_WITH_COMPARATOR, &[], &[]);
+    assert_eq!(
+        output,
+        ["1", "1", "3", "4", "5", "5", "4", "3", "1", "1", "1"]
+    );
+}
+
+#[test]
+fn tuple_returns_and_destructuring() {
+    let source = "decl
+	(int, int, int) exteul(int a, int b);
+	(int, str) pair(int a);
+enddecl
+(int, int, int) exteul(int a, int b){
+	decl
+		int g, x, y;
+	enddecl
+	begin
+		if(b==0)then
+			return (a, 1, 0);
+		endif;
+		(g, x, y)=exteul(b, a%b);
+		return (g, y, x-(a/b)*y);
+	end
+}
+(int, str) pair(int a){
+	begin
+		return (a+1, \"hi\");
+	end
+}
+int main(){
+	decl
+		int g, x, y, k;
+		str s;
+	enddecl
+	begin
+		k=5;
+		(g, x, y)=exteul(240, 46);
+		write(g);
+		write(x);
+		write(y);
+		write(240*x+46*y);
+		(k, s)=pair(k+3);
+		write(k);
+		write(s);
+		write(k+g);
+		return 0;
+	end
+}
+";
+    let output = run("tuples", source, &[], &[]);
+    assert_eq!(output, ["2", "-9", "47", "2", "9", "hi", "11"]);
+}
+
+#[test]
+fn void_functions_and_bare_return() {
+    let source = "decl
+	int cnt;
+	void bump(int by);
+	void show(int x);
+	void (*cb)(int);
+enddecl
+void bump(int by){
+	begin
+		if(by==0)then
+			return;
+		endif;
+		cnt=cnt+by;
+	end
+}
+void show(int x){
+	begin
+		write(x);
+	end
+}
+int main(){
+	decl
+		int i;
+	enddecl
+	begin
+		cnt=0;
+		i=0;
+		while(i<4)do
+			bump(i);
+			i=i+1;
+		endwhile;
+		bump(0);
+		show(cnt);
+		cb=&show;
+		cb(cnt+1);
+		return 0;
+	end
+}
+";
+    let output = run("void", source, &[], &[]);
+    assert_eq!(output, ["6", "7"]);
+}
+
+#[test]
+fn ownership_checked_list_runs() {
+    let source = "type
+	Node { int v; Node *next; };
+endtype
+decl
+	Node *push(Node *head, int v);
+enddecl
+Node *push(Node *head, int v){
+	decl
+		Node *n;
+	enddecl
+	begin
+		n=alloc();
+		n->v=v;
+		n->next=head;
+		return n;
+	end
+}
+int main(){
+	decl
+		Node *head, *p;
+		int i, x;
+	enddecl
+	begin
+		initialize();
+		head=null;
+		read(i);
+		while(i>0)do
+			head=push(head, i);
+			i=i-1;
+		endwhile;
+		while(head!=null)do
+			write(head->v);
+			p=head;
+			head=head->next;
+			x=free(p);
+		endwhile;
+		return 0;
+	end
+}
+";
+    let output = run("ownership_list", source, &["--ownership"], &["3"]);
+    assert_eq!(output, ["1", "2", "3"]);
+}
+
+#[test]
+fn checked_mode_traps_with_source_line() {
+    let source = "type
+	Node { int v; Node *next; };
+endtype
+decl
+	int a[3];
+enddecl
+int main(){
+	decl
+		int i, z;
+		Node *p;
+	enddecl
+	begin
+		read(i);
+		z=0;
+		p=null;
+		if(i==0)then
+			a[i+5]=1;
+		endif;
+		if(i==1)then
+			write(10/z);
+		endif;
+		if(i==2)then
+			write(p->v);
+		endif;
+		if(i==3)then
+			write(10%z);
+		endif;
+		write(99);
+		return 0;
+	end
+}
+";
+    let xsm = compile("checked", source, &["--checked"]);
+    let traps = [
+        ("0", "Out of bounds", "line 17"),
+        ("1", "Division by zero", "line 20"),
+        ("2", "Null pointer", "line 23"),
+        ("3", "Division by zero", "line 26"),
+    ];
+    for (input, message, line) in traps {
+        let output = xsm::run(&xsm, &[input]).unwrap();
+        assert_eq!(output, [message, line], "input {}", input);
+    }
+    assert_eq!(xsm::run(&xsm, &["4"]).unwrap(), ["99"]);
+    //without checks the out of bounds store goes unnoticed
+    let xsm = compile("unchecked", source, &[]);
+    assert_eq!(xsm::run(&xsm, &["0"]).unwrap(), ["99"]);
+}
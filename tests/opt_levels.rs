@@ -0,0 +1,256 @@
+mod xsm;
+
+use std::fs;
+use std::process::{Command, Output};
+
+const LEVELS: &[&str] = &["-O0", "-O1", "-O2"];
+
+fn rexplc(path: &str, flags: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rexplc"))
+        .args(flags)
+        .arg(path)
+        .output()
+        .unwrap()
+}
+
+/*
+ * Compiles source with rexplc and returns the linked xsm
+ */
+fn compile(name: &str, source: &str, flags: &[&str]) -> String {
+    let base = format!("{}/opt_{}", env!("CARGO_TARGET_TMPDIR"), name);
+    let path = base.clone() + ".expl";
+    fs::write(&path, source).unwrap();
+    let output = rexplc(&path, flags);
+    assert!(
+        output.status.success(),
+        "rexplc {:?} failed on {}: {}",
+        flags,
+        path,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::read_to_string(base + ".xsm").unwrap()
+}
+
+/*
+ * Every level, with and without runtime checks, writes the same output
+ * as -O0, which is returned
+ */
+fn assert_same_output(name: &str, source: &str, input: &[&str]) -> Vec<String> {
+    let mut expected = None;
+    for checked in [&[][..], &["--checked"][..]] {
+        for level in LEVELS {
+            let mut flags = vec![*level];
+            flags.extend(checked);
+            let xsm = compile(name, source, &flags);
+            let output = xsm::run(&xsm, input)
+                .unwrap_or_else(|e| panic!("{} {:?} did not run: {}", name, flags, e));
+            match &expected {
+                None => expected = Some(output),
+                Some(expected) => assert_eq!(*expected, output, "{} {:?}", name, flags),
+            }
+        }
+    }
+    expected.unwrap()
+}
+
+fn sample(path: &str) -> String {
+    fs::read_to_string(format!("{}/src/expl/{}", env!("CARGO_MANIFEST_DIR"), path)).unwrap()
+}
+
+fn static_size(xsm: &str) -> usize {
+    xsm.lines().skip(8).filter(|l| !l.trim().is_empty()).count()
+}
+
+const CALLS: &str = "decl
+	int max(int a, int b);
+	int bump(int a);
+	int sum(int n, int acc);
+	int g;
+enddecl
+int max(int a, int b){
+	begin
+		if(a>b)then
+			return a;
+		endif;
+		return b;
+	end
+}
+int bump(int a){
+	begin
+		a=a+1;
+		g=g+a;
+		return a;
+	end
+}
+int sum(int n, int acc){
+	begin
+		if(n==0)then
+			return acc;
+		endif;
+		return sum(n-1, acc+n);
+	end
+}
+int main(){
+	decl
+		int a, b;
+	enddecl
+	begin
+		g=0;
+		a=3;
+		b=bump(a);
+		write(a);
+		write(b);
+		write(max(max(1, 7), max(b, 2)));
+		write(g);
+		write(sum(100, 0));
+		return 0;
+	end
+}
+";
+
+const LOOPS: &str = "decl
+	int m[3][3];
+enddecl
+int main(){
+	decl
+		int i, j, n, s;
+	enddecl
+	begin
+		read(n);
+		i=0;
+		while(i<3)do
+			j=0;
+			while(j<3)do
+				m[i][j]=i*n+j;
+				j=j+1;
+			endwhile;
+			i=i+1;
+		endwhile;
+		s=0;
+		i=0;
+		while(i<3)do
+			j=0;
+			while(j<3)do
+				s=s+m[i][j]*m[i][j]+n*n;
+				j=j+1;
+			endwhile;
+			i=i+1;
+		endwhile;
+		write(s);
+		return 0;
+	end
+}
+";
+
+//the address of a local reaches the recursive call in tail position
+const ESCAPING_TAIL_CALL: &str = "decl
+	int g;
+	int f(int n, int *p);
+enddecl
+int f(int n, int *p){
+	decl
+		int x;
+	enddecl
+	begin
+		x=n*10;
+		if(n==0)then
+			return *p;
+		endif;
+		return f(n-1, &x);
+	end
+}
+int main(){
+	begin
+		g=99;
+		write(f(1, &g));
+		write(f(0, &g));
+		return 0;
+	end
+}
+";
+
+#[test]
+fn calls_agree_across_levels() {
+    let output = assert_same_output("calls", CALLS, &[]);
+    assert_eq!(output, ["3", "4", "7", "4", "5050"]);
+}
+
+#[test]
+fn loops_agree_across_levels() {
+    let output = assert_same_output("loops", LOOPS, &["5"]);
+    assert_eq!(output, ["705"]);
+}
+
+#[test]
+fn escaping_locals_keep_their_frame() {
+    let output = assert_same_output("escaping_tail_call", ESCAPING_TAIL_CALL, &[]);
+    assert_eq!(output, ["10", "99"]);
+}
+
+#[test]
+fn samples_agree_across_levels() {
+    let samples: &[(&str, &[&str])] = &[
+        ("stage5/bubble.expl", &["5", "3", "1", "4", "5", "2"]),
+        ("stage5/quick.expl", &["5", "3", "1", "4", "5", "2"]),
+        (
+            "stage5/nxn.expl",
+            &["2", "1", "2", "3", "4", "5", "6", "7", "8"],
+        ),
+        ("stage5/fibo.expl", &["10"]),
+        ("stage5/exteulR.expl", &["240", "46"]),
+        ("stage6/linked.expl", &["3", "5", "6", "7"]),
+        ("stage7/bstc.expl", &["5", "3", "8", "1", "4", "0"]),
+    ];
+    for (path, input) in samples {
+        let name = path.replace(['/', '.'], "_");
+        assert_same_output(&name, &sample(path), input);
+    }
+}
+
+#[test]
+fn higher_levels_generate_less_code() {
+    let sizes: Vec<usize> = LEVELS
+        .iter()
+        .map(|level| static_size(&compile("size", LOOPS, &[level])))
+        .collect();
+    assert!(
+        sizes[0] > sizes[1] && sizes[1] > sizes[2],
+        "sizes {:?}",
+        sizes
+    );
+}
+
+#[test]
+fn pass_overrides_level() {
+    let plain = compile("override", LOOPS, &["-O0"]);
+    let peephole = compile("override", LOOPS, &["-O0", "--pass=peephole"]);
+    assert!(static_size(&peephole) < static_size(&plain));
+    let full = compile("override", LOOPS, &["-O2"]);
+    let without = compile(
+        "override",
+        LOOPS,
+        &["-O2", "--no-pass=licm", "--no-pass=cse"],
+    );
+    assert_ne!(full, without);
+    assert_eq!(xsm::run(&full, &["5"]), xsm::run(&without, &["5"]));
+}
+
+#[test]
+fn unknown_level_and_pass_are_rejected() {
+    let path = format!("{}/opt_rejected.expl", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(&path, LOOPS).unwrap();
+    assert!(!rexplc(&path, &["-O3"]).status.success());
+    assert!(!rexplc(&path, &["--pass=unroll"]).status.success());
+}
+
+#[test]
+fn time_passes_reports_every_pass_run() {
+    let path = format!("{}/opt_time.expl", env!("CARGO_TARGET_TMPDIR"));
+    fs::write(&path, LOOPS).unwrap();
+    let output = rexplc(&path, &["-O1", "--time-passes"]);
+    let log = String::from_utf8_lossy(&output.stderr);
+    for pass in ["promote", "constfold", "dce", "peephole"] {
+        assert!(log.contains(&format!("Pass [{}]", pass)), "{}", log);
+    }
+    assert!(!log.contains("Pass [licm]"), "{}", log);
+}
@@ -0,0 +1,90 @@
+//shared with the other tests, not every helper is used here
+#[allow(dead_code)]
+mod xsm;
+
+use std::fs;
+use std::process::Command;
+
+/*
+ * Compiles a sample with rexplc and returns the linked xsm
+ */
+fn compile(path: &str, flags: &[&str]) -> String {
+    let base = format!(
+        "{}/regalloc_{}",
+        env!("CARGO_TARGET_TMPDIR"),
+        path.replace(['/', '.'], "_")
+    );
+    let source = format!("{}/src/expl/{}", env!("CARGO_MANIFEST_DIR"), path);
+    fs::copy(source, base.clone() + ".expl").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rexplc"))
+        .args(flags)
+        .arg(base.clone() + ".expl")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "rexplc {:?} failed on {}: {}",
+        flags,
+        path,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::read_to_string(base + ".xsm").unwrap()
+}
+
+fn static_size(xsm: &str) -> usize {
+    xsm.lines().skip(8).filter(|l| !l.trim().is_empty()).count()
+}
+
+const SAMPLES: &[(&str, &[&str])] = &[
+    ("stage5/bubble.expl", &["5", "3", "1", "4", "5", "2"]),
+    ("stage5/quick.expl", &["5", "3", "1", "4", "5", "2"]),
+    (
+        "stage5/nxn.expl",
+        &["2", "1", "2", "3", "4", "5", "6", "7", "8"],
+    ),
+    ("stage5/fibo.expl", &["10"]),
+    ("stage5/factR.expl", &["6"]),
+    ("stage5/exteulR.expl", &["240", "46"]),
+    ("stage6/linked.expl", &["3", "5", "6", "7"]),
+    ("stage7/fact.expl", &["4"]),
+    ("stage7/bstc.expl", &["5", "3", "8", "1", "4", "0"]),
+];
+
+/*
+ * Keeping locals in registers never makes a stage sample larger or
+ * slower than keeping them in their stack slots, and the samples
+ * generate less code in total
+ */
+#[test]
+fn register_allocation_does_not_increase_instructions() {
+    let (mut before, mut after) = (0, 0);
+    for (path, input) in SAMPLES {
+        let slots = compile(path, &["--no-pass=promote"]);
+        let registers = compile(path, &[]);
+        let (slots_output, slots_steps) = xsm::run_counted(&slots, input).unwrap();
+        let (output, steps) = xsm::run_counted(&registers, input).unwrap();
+        assert_eq!(slots_output, output, "{}", path);
+        assert!(
+            static_size(&registers) <= static_size(&slots),
+            "{}: {} instructions, {} without promotion",
+            path,
+            static_size(&registers),
+            static_size(&slots)
+        );
+        assert!(
+            steps <= slots_steps,
+            "{}: {} executed, {} without promotion",
+            path,
+            steps,
+            slots_steps
+        );
+        before += static_size(&slots);
+        after += static_size(&registers);
+    }
+    assert!(
+        after < before,
+        "{} instructions, {} without promotion",
+        after,
+        before
+    );
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::process::Command;
+
+//R0-R19 are the general purpose registers of XSM
+const XSM_REGISTERS: usize = 20;
+
+/*
+ * Compiles source with rexplc and returns the linked xsm
+ */
+fn compile(name: &str, source: &str, flags: &[&str]) -> String {
+    let base = format!("{}/spill_{}", env!("CARGO_TARGET_TMPDIR"), name);
+    let path = base.clone() + ".expl";
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rexplc"))
+        .args(flags)
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "rexplc failed on {}: {}",
+        path,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::read_to_string(base + ".xsm").unwrap()
+}
+
+/*
+ * Every register named in the program must exist in XSM
+ */
+fn assert_registers_valid(xsm: &str) {
+    let bytes = xsm.as_bytes();
+    for i in 0..bytes.len() {
+        let starts_word = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+        if bytes[i] != b'R' || !starts_word {
+            continue;
+        }
+        let digits: String = xsm[i + 1..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        if let Ok(n) = digits.parse::<usize>() {
+            assert!(n < XSM_REGISTERS, "register R{} used", n);
+        }
+    }
+}
+
+fn program(globals: &str, body: &str) -> String {
+    format!(
+        "decl\n{}\nenddecl\nint main() {{\n\tdecl\n\t\tint x, y;\n\tenddecl\n\tbegin\n\t\tx = 3;\n{}\n\t\treturn 0;\n\tend\n}}\n",
+        globals, body
+    )
+}
+
+#[test]
+fn deep_right_nested_expression() {
+    let mut expr = "x".to_owned();
+    for i in 0..200 {
+        expr = format!("(x + {} - {})", i, expr);
+    }
+    let xsm = compile(
+        "arith",
+        &program("", &format!("\t\ty = {};\n\t\twrite(y);", expr)),
+        &[],
+    );
+    assert_registers_valid(&xsm);
+    assert!(xsm.contains("PUSH"), "deep expression was not spilled");
+}
+
+#[test]
+fn deep_nested_array_indices() {
+    let mut expr = "a[0]".to_owned();
+    for _ in 0..100 {
+        expr = format!("a[x * 0 + {}]", expr);
+    }
+    let body = format!("\t\ta[0] = 0;\n\t\twrite({});", expr);
+    let xsm = compile("index", &program("\tint a[5];", &body), &[]);
+    assert_registers_valid(&xsm);
+    let xsm = compile(
+        "index_checked",
+        &program("\tint a[5];", &body),
+        &["--checked"],
+    );
+    assert_registers_valid(&xsm);
+}
+
+#[test]
+fn nested_calls_in_arguments() {
+    //every call is made with the registers of a deep chain live
+    let mut expr = "f(1)".to_owned();
+    for depth in 0..4 {
+        expr = format!("f({})", expr);
+        for i in 0..30 {
+            expr = format!("(x + {} - {})", depth * i, expr);
+        }
+    }
+    let source = format!(
+        "decl\n\tint f(int v);\nenddecl\nint f(int v) {{\n\tbegin\n\t\treturn v + 1;\n\tend\n}}\n{}",
+        program("", &format!("\t\twrite({});", expr)).replacen("decl\n\nenddecl\n", "", 1)
+    );
+    let xsm = compile("calls", &source, &[]);
+    assert_registers_valid(&xsm);
+}
@@ -0,0 +1,310 @@
+//minimal XSM machine running the programs compiled by the tests
+use std::collections::HashMap;
+
+//words of code start after the 8 line header
+const CODE_START: i64 = 2056;
+const MAX_STEPS: usize = 5_000_000;
+//the library heap, handed out in blocks of 8 words
+const HEAP_START: i64 = 1024;
+const HEAP_END: i64 = 2048;
+const BLOCK_SIZE: i64 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Word {
+    Int(i64),
+    Str(String),
+}
+
+impl Word {
+    fn int(&self) -> Result<i64, String> {
+        match self {
+            Word::Int(n) => Ok(*n),
+            Word::Str(s) => s.parse().map_err(|_| format!("[{}] is not an integer", s)),
+        }
+    }
+    fn text(&self) -> String {
+        match self {
+            Word::Int(n) => n.to_string(),
+            Word::Str(s) => s.clone(),
+        }
+    }
+}
+
+struct Machine {
+    code: HashMap<i64, String>,
+    mem: HashMap<i64, Word>,
+    regs: HashMap<String, Word>,
+    ip: i64,
+    sp: i64,
+    bp: i64,
+    heap: i64,
+    freed: Vec<i64>,
+    input: Vec<String>,
+    output: Vec<String>,
+}
+
+/*
+ * Operands split on the commas outside string literals
+ */
+fn split_args(rest: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in rest.chars() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c == ',' && !quoted {
+            args.push(current.trim().to_owned());
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.trim().is_empty() {
+        args.push(current.trim().to_owned());
+    }
+    args
+}
+
+impl Machine {
+    fn load(&self, addr: i64) -> Word {
+        self.mem.get(&addr).cloned().unwrap_or(Word::Int(0))
+    }
+    fn value(&self, operand: &str) -> Result<Word, String> {
+        match operand {
+            "SP" => Ok(Word::Int(self.sp)),
+            "BP" => Ok(Word::Int(self.bp)),
+            "IP" => Ok(Word::Int(self.ip)),
+            _ if operand.starts_with('R') => {
+                Ok(self.regs.get(operand).cloned().unwrap_or(Word::Int(0)))
+            }
+            _ if operand.starts_with('"') => Ok(Word::Str(operand.trim_matches('"').to_owned())),
+            _ if operand.starts_with('[') => {
+                let addr = self.value(operand[1..operand.len() - 1].trim())?.int()?;
+                Ok(self.load(addr))
+            }
+            _ => operand
+                .parse()
+                .map(Word::Int)
+                .map_err(|_| format!("bad operand [{}]", operand)),
+        }
+    }
+    fn set(&mut self, operand: &str, word: Word) -> Result<(), String> {
+        match operand {
+            "SP" => self.sp = word.int()?,
+            "BP" => self.bp = word.int()?,
+            _ if operand.starts_with('R') => {
+                let n: usize = operand[1..]
+                    .parse()
+                    .map_err(|_| format!("bad register [{}]", operand))?;
+                if n > 19 {
+                    return Err(format!("bad register [{}]", operand));
+                }
+                self.regs.insert(operand.to_owned(), word);
+            }
+            _ if operand.starts_with('[') => {
+                let addr = self.value(operand[1..operand.len() - 1].trim())?.int()?;
+                if addr < 0 {
+                    return Err(format!("bad address [{}]", addr));
+                }
+                self.mem.insert(addr, word);
+            }
+            _ => return Err(format!("bad destination [{}]", operand)),
+        }
+        Ok(())
+    }
+    fn read_input(&mut self) -> Result<Word, String> {
+        if self.input.is_empty() {
+            return Err("input exhausted".to_owned());
+        }
+        let word = self.input.remove(0);
+        Ok(word.parse().map(Word::Int).unwrap_or(Word::Str(word)))
+    }
+    /*
+     * CALL 0 with the function name and 3 arguments below the return slot
+     */
+    fn library(&mut self) -> Result<(), String> {
+        let name = self.load(self.sp - 4).text();
+        let arg1 = self.load(self.sp - 3);
+        let arg2 = self.load(self.sp - 2);
+        let result = match name.as_str() {
+            "Write" => {
+                self.output.push(arg2.text());
+                Word::Int(0)
+            }
+            "Read" => {
+                let word = self.read_input()?;
+                self.mem.insert(arg2.int()?, word);
+                Word::Int(0)
+            }
+            "Alloc" => {
+                let size = arg1.int()?;
+                if size > BLOCK_SIZE {
+                    return Err(format!("Alloc of [{}] words, blocks have 8", size));
+                }
+                match self.freed.pop() {
+                    Some(addr) => Word::Int(addr),
+                    None if self.heap + BLOCK_SIZE <= HEAP_END => {
+                        self.heap += BLOCK_SIZE;
+                        Word::Int(self.heap - BLOCK_SIZE)
+                    }
+                    None => Word::Int(-1),
+                }
+            }
+            "Free" => {
+                self.freed.push(arg1.int()?);
+                Word::Int(0)
+            }
+            "Heapset" => {
+                self.heap = HEAP_START;
+                self.freed.clear();
+                Word::Int(0)
+            }
+            _ => return Err(format!("unknown library function [{}]", name)),
+        };
+        self.mem.insert(self.sp, result);
+        Ok(())
+    }
+    fn step(&mut self) -> Result<bool, String> {
+        let inst = self
+            .code
+            .get(&self.ip)
+            .cloned()
+            .ok_or(format!("bad ip [{}]", self.ip))?;
+        let (op, rest) = inst.split_once(char::is_whitespace).unwrap_or((&inst, ""));
+        let args = split_args(rest);
+        let mut next = self.ip + 2;
+        match op {
+            "MOV" => {
+                let word = self.value(&args[1])?;
+                self.set(&args[0], word)?;
+            }
+            "ADD" | "SUB" | "MUL" | "DIV" | "MOD" => {
+                let a = self.value(&args[0])?.int()?;
+                let b = self.value(&args[1])?.int()?;
+                if (op == "DIV" || op == "MOD") && b == 0 {
+                    return Err("division by zero".to_owned());
+                }
+                let r = match op {
+                    "ADD" => a + b,
+                    "SUB" => a - b,
+                    "MUL" => a * b,
+                    "DIV" => a / b,
+                    _ => a % b,
+                };
+                self.set(&args[0], Word::Int(r))?;
+            }
+            "INR" | "DCR" => {
+                let a = self.value(&args[0])?.int()?;
+                self.set(&args[0], Word::Int(if op == "INR" { a + 1 } else { a - 1 }))?;
+            }
+            "GT" | "LT" | "GE" | "LE" | "EQ" | "NE" => {
+                let a = self.value(&args[0])?;
+                let b = self.value(&args[1])?;
+                let ordering = match (&a, &b) {
+                    (Word::Int(x), Word::Int(y)) => x.cmp(y),
+                    _ => a.text().cmp(&b.text()),
+                };
+                let r = match op {
+                    "GT" => ordering.is_gt(),
+                    "LT" => ordering.is_lt(),
+                    "GE" => ordering.is_ge(),
+                    "LE" => ordering.is_le(),
+                    "EQ" => a == b,
+                    _ => a != b,
+                };
+                self.set(&args[0], Word::Int(r as i64))?;
+            }
+            "JZ" | "JNZ" => {
+                let zero = self.value(&args[0])? == Word::Int(0);
+                if zero == (op == "JZ") {
+                    next = self.value(&args[1])?.int()?;
+                }
+            }
+            "JMP" => next = self.value(&args[0])?.int()?,
+            "PUSH" => {
+                let word = self.value(&args[0])?;
+                self.sp += 1;
+                self.mem.insert(self.sp, word);
+            }
+            "POP" => {
+                let word = self.load(self.sp);
+                self.set(&args[0], word)?;
+                self.sp -= 1;
+            }
+            "CALL" => match self.value(&args[0])?.int()? {
+                0 => self.library()?,
+                target => {
+                    self.sp += 1;
+                    self.mem.insert(self.sp, Word::Int(next));
+                    next = target;
+                }
+            },
+            "RET" => {
+                next = self.load(self.sp).int()?;
+                self.sp -= 1;
+            }
+            "INT" => match args[0].as_str() {
+                "10" => return Ok(false),
+                "7" => {
+                    let word = self.load(self.sp - 2);
+                    self.output.push(word.text());
+                    self.mem.insert(self.sp, Word::Int(0));
+                }
+                "6" => {
+                    let addr = self.load(self.sp - 2).int()?;
+                    let word = self.read_input()?;
+                    self.mem.insert(addr, word);
+                    self.mem.insert(self.sp, Word::Int(0));
+                }
+                n => return Err(format!("unknown interrupt [{}]", n)),
+            },
+            "BRKP" | "NOP" => {}
+            _ => return Err(format!("unknown instruction [{}]", inst)),
+        }
+        self.ip = next;
+        Ok(true)
+    }
+}
+
+/*
+ * Runs a linked program until it exits, returns the words it wrote
+ */
+pub fn run(xsm: &str, input: &[&str]) -> Result<Vec<String>, String> {
+    run_counted(xsm, input).map(|(output, _)| output)
+}
+/*
+ * Like run, also returns the number of instructions executed
+ */
+pub fn run_counted(xsm: &str, input: &[&str]) -> Result<(Vec<String>, usize), String> {
+    let lines: Vec<&str> = xsm.split('\n').collect();
+    let entry = lines
+        .get(1)
+        .and_then(|l| l.trim().parse().ok())
+        .ok_or("missing entry point in header")?;
+    let mut code = HashMap::new();
+    for (i, line) in lines.iter().skip(8).enumerate() {
+        if !line.trim().is_empty() {
+            code.insert(CODE_START + 2 * i as i64, line.trim().to_owned());
+        }
+    }
+    let mut machine = Machine {
+        code,
+        mem: HashMap::new(),
+        regs: HashMap::new(),
+        ip: entry,
+        sp: 0,
+        bp: 0,
+        heap: HEAP_START,
+        freed: vec![],
+        input: input.iter().map(|s| s.to_string()).collect(),
+        output: vec![],
+    };
+    for steps in 1..=MAX_STEPS {
+        if !machine.step()? {
+            return Ok((machine.output, steps));
+        }
+    }
+    Err("step limit reached".to_owned())
+}